@@ -7,8 +7,10 @@ import khiin.proto.Request
 import khiin.proto.Response
 import khiin.proto.command
 import khiin.proto.request
+import java.io.File
 
 const val KHIIN_ANDROID_NATIVE_LIBRARY = "khiin_droid"
+const val USER_DB_FILE = "khiin_user.db"
 
 val log = loggerFor("EngineManager")
 
@@ -22,7 +24,8 @@ object EngineManager {
             reset()
         } else {
             dbFileName = dbPath
-            enginePtr = load(dbFileName)
+            val userDbFileName = File(File(dbPath).parent, USER_DB_FILE).path
+            enginePtr = loadReadOnly(dbFileName, userDbFileName)
         }
     }
 
@@ -43,6 +46,11 @@ object EngineManager {
 
     private external fun load(dbFileName: String): Long
 
+    private external fun loadReadOnly(
+        dbFileName: String,
+        userDbFileName: String
+    ): Long
+
     private external fun sendCommand(enginePtr: Long, cmdBytes: ByteArray): ByteArray
 
     private external fun shutdown(enginePtr: Long)
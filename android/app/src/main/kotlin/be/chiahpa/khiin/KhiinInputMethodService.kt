@@ -5,7 +5,9 @@ import android.view.View
 import android.view.inputmethod.EditorInfo
 import be.chiahpa.khiin.keyboard.ComposeKeyboardView
 import be.chiahpa.khiin.service.KhiinServiceLifecycleOwner
+import be.chiahpa.khiin.service.assetsOutdated
 import be.chiahpa.khiin.service.copyAssetToFiles
+import be.chiahpa.khiin.service.markAssetsCopied
 import be.chiahpa.khiin.utils.loggerFor
 import java.io.File
 
@@ -19,7 +21,12 @@ class KhiinInputMethodService : InputMethodService() {
         super.onCreate()
         lifecycleOwner.onCreate()
 
-        copyAssetToFiles(this, "khiin.db")
+        // The bundled database changes with the app, and is only read in
+        // place, so any copy left from an earlier version is replaced
+        val overwrite = assetsOutdated(this)
+        copyAssetToFiles(this, "khiin.db", overwrite)
+        copyAssetToFiles(this, "khiin.idx", overwrite)
+        markAssetsCopied(this)
         dbPath = File(filesDir, "khiin.db").absolutePath
     }
 
@@ -8,6 +8,9 @@ import java.io.IOException
 import java.io.InputStream
 import java.io.OutputStream
 
+private const val ASSET_PREFS = "khiin_assets"
+private const val COPIED_AT_KEY = "copied_at"
+
 @Throws(IOException::class)
 private fun copyFile(inputStream: InputStream, outputStream: OutputStream) {
     val buffer = ByteArray(8192)
@@ -45,3 +48,22 @@ fun copyAssetToFiles(
     outstream.flush()
     outstream.close()
 }
+
+/**
+ * Whether the app has been installed or updated since its assets were last
+ * copied to the files directory, so that the copies may be out of date
+ */
+fun assetsOutdated(context: Context): Boolean {
+    val prefs = context.getSharedPreferences(ASSET_PREFS, Context.MODE_PRIVATE)
+    return prefs.getLong(COPIED_AT_KEY, 0L) != lastUpdateTime(context)
+}
+
+fun markAssetsCopied(context: Context) {
+    context.getSharedPreferences(ASSET_PREFS, Context.MODE_PRIVATE)
+        .edit()
+        .putLong(COPIED_AT_KEY, lastUpdateTime(context))
+        .apply()
+}
+
+private fun lastUpdateTime(context: Context): Long =
+    context.packageManager.getPackageInfo(context.packageName, 0).lastUpdateTime
@@ -3,6 +3,7 @@ use jni::objects::JClass;
 use jni::objects::JString;
 use jni::sys::jlong;
 use jni::JNIEnv;
+use khiin::db::OpenMode;
 use khiin::Engine;
 use khiin_protos::command::Command;
 use khiin_protos::command::Request;
@@ -38,6 +39,51 @@ pub extern "system" fn Java_be_chiahpa_khiin_EngineManager_load<'local>(
     Box::into_raw(Box::new(engine)) as jlong
 }
 
+/// Reads the database in place instead of copying it into memory, and keeps
+/// user data in `user_db_filename`
+#[no_mangle]
+pub extern "system" fn Java_be_chiahpa_khiin_EngineManager_loadReadOnly<
+    'local,
+>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    db_filename: JString<'local>,
+    user_db_filename: JString<'local>,
+) -> jlong {
+    #[cfg(target_os = "android")]
+    android_log::init("KhiinEngine").unwrap();
+    #[cfg(not(target_os = "android"))]
+    env_logger::init();
+    log::debug!("Trying to initialize Khiin Engine (read-only)");
+
+    let db_filename: String = env
+        .get_string(&db_filename)
+        .expect("Could not get string!")
+        .into();
+    let user_db_filename: String = env
+        .get_string(&user_db_filename)
+        .expect("Could not get string!")
+        .into();
+
+    log::debug!("Using database from file: {}", db_filename);
+
+    let engine = Engine::with_open_mode(
+        &db_filename,
+        &user_db_filename,
+        OpenMode::ReadOnly,
+    );
+    match engine {
+        Some(engine) => {
+            log::debug!("Engine intialized");
+            Box::into_raw(Box::new(engine)) as jlong
+        },
+        None => {
+            log::debug!("Unable to initialize engine.");
+            0
+        },
+    }
+}
+
 #[no_mangle]
 pub extern "system" fn Java_be_chiahpa_khiin_EngineManager_sendCommand<
     'local,
@@ -0,0 +1,150 @@
+#ifndef KHIIN_H
+#define KHIIN_H
+
+/* Generated by cbindgen from capi/src/lib.rs, do not edit */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+typedef enum KhiinStatus {
+  KHIIN_STATUS_OK = 0,
+  /**
+   * A required pointer argument was null
+   */
+  KHIIN_STATUS_NULL_ARGUMENT = 1,
+  /**
+   * A string argument is not valid UTF-8
+   */
+  KHIIN_STATUS_INVALID_UTF8 = 2,
+  /**
+   * The engine could not be created, e.g. the database failed to load
+   */
+  KHIIN_STATUS_ENGINE_INIT = 3,
+  /**
+   * The response could not be serialized
+   */
+  KHIIN_STATUS_COMMAND_FAILED = 4,
+  /**
+   * The engine panicked. The handle should be destroyed.
+   */
+  KHIIN_STATUS_PANIC = 5,
+  /**
+   * An argument has a value the function does not accept
+   */
+  KHIIN_STATUS_INVALID_ARGUMENT = 6,
+} KhiinStatus;
+
+/**
+ * How the dictionary database is opened, one of the `KHIIN_OPEN_MODE_*`
+ * values. It is a plain integer rather than an enum, so that an unknown
+ * value from the host is reported instead of being undefined behaviour.
+ */
+typedef uint32_t KhiinOpenMode;
+
+/**
+ * Opaque handle to an engine and its sessions
+ */
+typedef struct KhiinEngine KhiinEngine;
+
+/**
+ * Bytes allocated by the engine, to be released with `khiin_buffer_free`
+ */
+typedef struct KhiinBuffer {
+  uint8_t *data;
+  size_t len;
+} KhiinBuffer;
+
+/**
+ * Copies the database into memory, creating the file if needed
+ */
+#define KHIIN_OPEN_MODE_MEMORY 0
+
+/**
+ * Reads the database file in place without writing to it
+ */
+#define KHIIN_OPEN_MODE_READ_ONLY 1
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Creates an engine with the dictionary database at `db_filename`, which
+ * is created if it does not exist. On success `*engine` is set to a handle
+ * to release with `khiin_engine_destroy`, otherwise it is set to null.
+ *
+ * # Safety
+ *
+ * `db_filename` must be a NUL-terminated string and `engine` must point to
+ * writable memory for one pointer.
+ */
+enum KhiinStatus khiin_engine_create(const char *db_filename, struct KhiinEngine **engine);
+
+/**
+ * Same as `khiin_engine_create`, opening the database at `db_filename`
+ * with `mode` and keeping user data in `user_db_filename`. With
+ * `KHIIN_OPEN_MODE_READ_ONLY` the database must already exist, e.g. in a
+ * read-only app bundle, and `user_db_filename` must be writable.
+ *
+ * # Safety
+ *
+ * `db_filename` and `user_db_filename` must be NUL-terminated strings and
+ * `engine` must point to writable memory for one pointer.
+ */
+enum KhiinStatus khiin_engine_create_with_mode(const char *db_filename,
+                                               const char *user_db_filename,
+                                               KhiinOpenMode mode,
+                                               struct KhiinEngine **engine);
+
+/**
+ * Releases an engine created by `khiin_engine_create`. Null is ignored.
+ *
+ * # Safety
+ *
+ * `engine` must be null or a handle from `khiin_engine_create` that has not
+ * been destroyed yet.
+ */
+enum KhiinStatus khiin_engine_destroy(struct KhiinEngine *engine);
+
+/**
+ * Sends a serialized `Command` and sets `*response` to the serialized
+ * `Command` with its `Response`, to release with `khiin_buffer_free`.
+ * Errors handling the request are reported in the `Response` itself, with
+ * `KHIIN_STATUS_OK` returned.
+ *
+ * # Safety
+ *
+ * `engine` must be a live handle from `khiin_engine_create`, not used by
+ * another thread at the same time. `command` must point to `len` readable
+ * bytes, or may be null if `len` is 0. `response` must point to writable
+ * memory for one `KhiinBuffer`.
+ */
+enum KhiinStatus khiin_engine_send_command(struct KhiinEngine *engine,
+                                           const uint8_t *command,
+                                           size_t len,
+                                           struct KhiinBuffer *response);
+
+/**
+ * Releases a buffer returned by the engine. Empty buffers are ignored.
+ *
+ * # Safety
+ *
+ * `buffer` must have been returned by this library and not freed before.
+ */
+void khiin_buffer_free(struct KhiinBuffer buffer);
+
+/**
+ * Message describing the last failed call on this thread, or null if the
+ * last call succeeded. The string is owned by the library and is valid
+ * until the next call on the same thread.
+ */
+const char *khiin_last_error(void);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* KHIIN_H */
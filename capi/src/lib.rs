@@ -0,0 +1,438 @@
+//! C interface to the Khíín engine, for hosts that cannot link Rust crates
+//! directly. The header is generated into `include/khiin.h`.
+//!
+//! Commands are exchanged as serialized `khiin.proto.Command` messages, the
+//! same as with `Engine::send_command_bytes`. No function unwinds into the
+//! host: panics are caught and reported as `KHIIN_STATUS_PANIC`.
+
+use std::any::Any;
+use std::cell::RefCell;
+use std::ffi::c_char;
+use std::ffi::CStr;
+use std::ffi::CString;
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::ptr;
+use std::slice;
+
+use khiin::db::OpenMode;
+use khiin::Engine;
+
+/// Opaque handle to an engine and its sessions
+pub struct KhiinEngine {
+    engine: Engine,
+}
+
+#[repr(C)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum KhiinStatus {
+    Ok = 0,
+    /// A required pointer argument was null
+    NullArgument = 1,
+    /// A string argument is not valid UTF-8
+    InvalidUtf8 = 2,
+    /// The engine could not be created, e.g. the database failed to load
+    EngineInit = 3,
+    /// The response could not be serialized
+    CommandFailed = 4,
+    /// The engine panicked. The handle should be destroyed.
+    Panic = 5,
+    /// An argument has a value the function does not accept
+    InvalidArgument = 6,
+}
+
+/// How the dictionary database is opened, one of the `KHIIN_OPEN_MODE_*`
+/// values. It is a plain integer rather than an enum, so that an unknown
+/// value from the host is reported instead of being undefined behaviour.
+pub type KhiinOpenMode = u32;
+
+/// Copies the database into memory, creating the file if needed
+pub const KHIIN_OPEN_MODE_MEMORY: KhiinOpenMode = 0;
+
+/// Reads the database file in place without writing to it
+pub const KHIIN_OPEN_MODE_READ_ONLY: KhiinOpenMode = 1;
+
+fn open_mode(mode: KhiinOpenMode) -> Result<OpenMode, Failure> {
+    match mode {
+        KHIIN_OPEN_MODE_MEMORY => Ok(OpenMode::Memory),
+        KHIIN_OPEN_MODE_READ_ONLY => Ok(OpenMode::ReadOnly),
+        _ => Err((
+            KhiinStatus::InvalidArgument,
+            format!("Unknown open mode {}", mode),
+        )),
+    }
+}
+
+/// Bytes allocated by the engine, to be released with `khiin_buffer_free`
+#[repr(C)]
+pub struct KhiinBuffer {
+    pub data: *mut u8,
+    pub len: usize,
+}
+
+impl KhiinBuffer {
+    fn empty() -> Self {
+        Self {
+            data: ptr::null_mut(),
+            len: 0,
+        }
+    }
+
+    fn from_vec(bytes: Vec<u8>) -> Self {
+        let bytes = bytes.into_boxed_slice();
+        let len = bytes.len();
+        let data = Box::into_raw(bytes) as *mut u8;
+        Self { data, len }
+    }
+}
+
+type Failure = (KhiinStatus, String);
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+    let message = CString::new(message.replace('\0', " ")).ok();
+    LAST_ERROR.with(|e| *e.borrow_mut() = message);
+}
+
+fn panic_message(panic: Box<dyn Any + Send>) -> String {
+    if let Some(msg) = panic.downcast_ref::<&str>() {
+        msg.to_string()
+    } else if let Some(msg) = panic.downcast_ref::<String>() {
+        msg.clone()
+    } else {
+        "Unknown panic".to_string()
+    }
+}
+
+/// Runs `f`, recording its error message for `khiin_last_error`
+fn guard<F>(f: F) -> KhiinStatus
+where
+    F: FnOnce() -> Result<(), Failure>,
+{
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => {
+            LAST_ERROR.with(|e| *e.borrow_mut() = None);
+            KhiinStatus::Ok
+        },
+        Ok(Err((status, message))) => {
+            log::error!("{:?}: {}", status, message);
+            set_last_error(message);
+            status
+        },
+        Err(panic) => {
+            let message = panic_message(panic);
+            log::error!("Panic in khiin_capi: {}", message);
+            set_last_error(message);
+            KhiinStatus::Panic
+        },
+    }
+}
+
+fn null_argument(name: &str) -> Failure {
+    (KhiinStatus::NullArgument, format!("{} is null", name))
+}
+
+/// # Safety
+///
+/// `s` must be null or a NUL-terminated string.
+unsafe fn str_argument<'a>(
+    s: *const c_char,
+    name: &str,
+) -> Result<&'a str, Failure> {
+    if s.is_null() {
+        return Err(null_argument(name));
+    }
+
+    CStr::from_ptr(s)
+        .to_str()
+        .map_err(|e| (KhiinStatus::InvalidUtf8, format!("{}: {}", name, e)))
+}
+
+fn engine_init_failure(filename: &str) -> Failure {
+    (
+        KhiinStatus::EngineInit,
+        format!("Unable to load the database at {}", filename),
+    )
+}
+
+/// Creates an engine with the dictionary database at `db_filename`, which
+/// is created if it does not exist. On success `*engine` is set to a handle
+/// to release with `khiin_engine_destroy`, otherwise it is set to null.
+///
+/// # Safety
+///
+/// `db_filename` must be a NUL-terminated string and `engine` must point to
+/// writable memory for one pointer.
+#[no_mangle]
+pub unsafe extern "C" fn khiin_engine_create(
+    db_filename: *const c_char,
+    engine: *mut *mut KhiinEngine,
+) -> KhiinStatus {
+    guard(|| {
+        if engine.is_null() {
+            return Err(null_argument("engine"));
+        }
+        *engine = ptr::null_mut();
+
+        let filename = str_argument(db_filename, "db_filename")?;
+        let created =
+            Engine::new(filename).ok_or_else(|| engine_init_failure(filename))?;
+
+        *engine = Box::into_raw(Box::new(KhiinEngine { engine: created }));
+        Ok(())
+    })
+}
+
+/// Same as `khiin_engine_create`, opening the database at `db_filename`
+/// with `mode` and keeping user data in `user_db_filename`. With
+/// `KHIIN_OPEN_MODE_READ_ONLY` the database must already exist, e.g. in a
+/// read-only app bundle, and `user_db_filename` must be writable.
+///
+/// # Safety
+///
+/// `db_filename` and `user_db_filename` must be NUL-terminated strings and
+/// `engine` must point to writable memory for one pointer.
+#[no_mangle]
+pub unsafe extern "C" fn khiin_engine_create_with_mode(
+    db_filename: *const c_char,
+    user_db_filename: *const c_char,
+    mode: KhiinOpenMode,
+    engine: *mut *mut KhiinEngine,
+) -> KhiinStatus {
+    guard(|| {
+        if engine.is_null() {
+            return Err(null_argument("engine"));
+        }
+        *engine = ptr::null_mut();
+
+        let mode = open_mode(mode)?;
+        let filename = str_argument(db_filename, "db_filename")?;
+        let user_filename = str_argument(user_db_filename, "user_db_filename")?;
+        let created = Engine::with_open_mode(filename, user_filename, mode)
+            .ok_or_else(|| engine_init_failure(filename))?;
+
+        *engine = Box::into_raw(Box::new(KhiinEngine { engine: created }));
+        Ok(())
+    })
+}
+
+/// Releases an engine created by `khiin_engine_create`. Null is ignored.
+///
+/// # Safety
+///
+/// `engine` must be null or a handle from `khiin_engine_create` that has not
+/// been destroyed yet.
+#[no_mangle]
+pub unsafe extern "C" fn khiin_engine_destroy(
+    engine: *mut KhiinEngine,
+) -> KhiinStatus {
+    guard(|| {
+        if !engine.is_null() {
+            drop(Box::from_raw(engine));
+        }
+        Ok(())
+    })
+}
+
+/// Sends a serialized `Command` and sets `*response` to the serialized
+/// `Command` with its `Response`, to release with `khiin_buffer_free`.
+/// Errors handling the request are reported in the `Response` itself, with
+/// `KHIIN_STATUS_OK` returned.
+///
+/// # Safety
+///
+/// `engine` must be a live handle from `khiin_engine_create`, not used by
+/// another thread at the same time. `command` must point to `len` readable
+/// bytes, or may be null if `len` is 0. `response` must point to writable
+/// memory for one `KhiinBuffer`.
+#[no_mangle]
+pub unsafe extern "C" fn khiin_engine_send_command(
+    engine: *mut KhiinEngine,
+    command: *const u8,
+    len: usize,
+    response: *mut KhiinBuffer,
+) -> KhiinStatus {
+    guard(|| {
+        if response.is_null() {
+            return Err(null_argument("response"));
+        }
+        *response = KhiinBuffer::empty();
+        if engine.is_null() {
+            return Err(null_argument("engine"));
+        }
+        if command.is_null() && len > 0 {
+            return Err(null_argument("command"));
+        }
+
+        let bytes = if len == 0 {
+            &[]
+        } else {
+            slice::from_raw_parts(command, len)
+        };
+        let engine = &mut (*engine).engine;
+        let output = engine
+            .send_command_bytes(bytes)
+            .map_err(|e| (KhiinStatus::CommandFailed, format!("{:#}", e)))?;
+
+        *response = KhiinBuffer::from_vec(output);
+        Ok(())
+    })
+}
+
+/// Releases a buffer returned by the engine. Empty buffers are ignored.
+///
+/// # Safety
+///
+/// `buffer` must have been returned by this library and not freed before.
+#[no_mangle]
+pub unsafe extern "C" fn khiin_buffer_free(buffer: KhiinBuffer) {
+    guard(|| {
+        if !buffer.data.is_null() {
+            let bytes = ptr::slice_from_raw_parts_mut(buffer.data, buffer.len);
+            drop(Box::from_raw(bytes));
+        }
+        Ok(())
+    });
+}
+
+/// Message describing the last failed call on this thread, or null if the
+/// last call succeeded. The string is owned by the library and is valid
+/// until the next call on the same thread.
+#[no_mangle]
+pub extern "C" fn khiin_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| match e.borrow().as_ref() {
+        Some(message) => message.as_ptr(),
+        None => ptr::null(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use khiin::engine::commands;
+    use khiin_protos::command::Command;
+    use protobuf::Message;
+
+    use super::*;
+
+    fn db_filename() -> CString {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("..")
+            .join("target")
+            .join("debug")
+            .join("khiin.db");
+        CString::new(path.to_str().unwrap()).unwrap()
+    }
+
+    fn last_error() -> String {
+        let message = khiin_last_error();
+        assert!(!message.is_null());
+        unsafe { CStr::from_ptr(message) }.to_str().unwrap().into()
+    }
+
+    #[test]
+    fn it_sends_commands() {
+        let mut engine = ptr::null_mut();
+        let filename = db_filename();
+        unsafe {
+            let status = khiin_engine_create(filename.as_ptr(), &mut engine);
+            assert_eq!(status, KhiinStatus::Ok);
+            assert!(khiin_last_error().is_null());
+
+            let bytes = commands::send_char('a').write_to_bytes().unwrap();
+            let mut response = KhiinBuffer::empty();
+            let status = khiin_engine_send_command(
+                engine,
+                bytes.as_ptr(),
+                bytes.len(),
+                &mut response,
+            );
+            assert_eq!(status, KhiinStatus::Ok);
+
+            let output = slice::from_raw_parts(response.data, response.len);
+            let cmd = Command::parse_from_bytes(output).unwrap();
+            assert_eq!(cmd.response.preedit.segments.len(), 1);
+            khiin_buffer_free(response);
+
+            let status = khiin_engine_destroy(engine);
+            assert_eq!(status, KhiinStatus::Ok);
+        }
+    }
+
+    #[test]
+    fn it_opens_the_database_read_only() {
+        let mut engine = ptr::null_mut();
+        let filename = db_filename();
+        let user_path = std::env::temp_dir()
+            .join(format!("khiin_capi_user_{}.db", std::process::id()));
+        let user_filename = CString::new(user_path.to_str().unwrap()).unwrap();
+        unsafe {
+            let status = khiin_engine_create_with_mode(
+                filename.as_ptr(),
+                user_filename.as_ptr(),
+                KHIIN_OPEN_MODE_READ_ONLY,
+                &mut engine,
+            );
+            assert_eq!(status, KhiinStatus::Ok);
+            assert_eq!(khiin_engine_destroy(engine), KhiinStatus::Ok);
+
+            let status = khiin_engine_create_with_mode(
+                filename.as_ptr(),
+                ptr::null(),
+                KHIIN_OPEN_MODE_READ_ONLY,
+                &mut engine,
+            );
+            assert_eq!(status, KhiinStatus::NullArgument);
+            assert_eq!(last_error(), "user_db_filename is null");
+
+            let status = khiin_engine_create_with_mode(
+                filename.as_ptr(),
+                user_filename.as_ptr(),
+                2,
+                &mut engine,
+            );
+            assert_eq!(status, KhiinStatus::InvalidArgument);
+            assert_eq!(last_error(), "Unknown open mode 2");
+            assert!(engine.is_null());
+        }
+        std::fs::remove_file(user_path).unwrap();
+    }
+
+    #[test]
+    fn it_reports_bad_arguments() {
+        let mut engine = ptr::null_mut();
+        unsafe {
+            let status = khiin_engine_create(ptr::null(), &mut engine);
+            assert_eq!(status, KhiinStatus::NullArgument);
+            assert_eq!(last_error(), "db_filename is null");
+            assert!(engine.is_null());
+
+            let filename = CString::new(vec![0xff, 0xfe]).unwrap();
+            let status = khiin_engine_create(filename.as_ptr(), &mut engine);
+            assert_eq!(status, KhiinStatus::InvalidUtf8);
+
+            let mut response = KhiinBuffer::empty();
+            let status = khiin_engine_send_command(
+                engine,
+                ptr::null(),
+                0,
+                &mut response,
+            );
+            assert_eq!(status, KhiinStatus::NullArgument);
+            assert!(response.data.is_null());
+            khiin_buffer_free(response);
+            assert_eq!(khiin_engine_destroy(ptr::null_mut()), KhiinStatus::Ok);
+        }
+    }
+
+    #[test]
+    fn it_catches_panics() {
+        let status = guard(|| panic!("boom"));
+        assert_eq!(status, KhiinStatus::Panic);
+        assert_eq!(last_error(), "boom");
+    }
+}
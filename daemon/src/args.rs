@@ -0,0 +1,56 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+use clap::Parser;
+use tokio::time::Duration;
+
+use crate::server;
+
+#[derive(Parser, Debug)]
+pub struct Args {
+    /// Socket to listen on, by default `khiin.sock` in `$XDG_RUNTIME_DIR`,
+    /// or `khiin-<uid>.sock` in the temp directory
+    #[arg(short, long)]
+    pub socket: Option<PathBuf>,
+
+    /// Database file, by default `khiin.db` next to the executable
+    #[arg(short, long)]
+    pub db_file: Option<PathBuf>,
+
+    /// Seconds to keep running with no clients connected, or 0 to never stop
+    #[arg(short, long, default_value_t = 300)]
+    pub idle_timeout: u64,
+}
+
+impl Args {
+    pub fn socket_path(&self) -> PathBuf {
+        if let Some(socket) = &self.socket {
+            return socket.clone();
+        }
+
+        match std::env::var_os("XDG_RUNTIME_DIR") {
+            Some(dir) => PathBuf::from(dir).join("khiin.sock"),
+            None => {
+                let file = format!("khiin-{}.sock", server::current_uid());
+                std::env::temp_dir().join(file)
+            },
+        }
+    }
+
+    pub fn db_path(&self) -> Result<PathBuf> {
+        if let Some(db_file) = &self.db_file {
+            return Ok(db_file.clone());
+        }
+
+        let mut db_path = std::env::current_exe()?;
+        db_path.set_file_name("khiin.db");
+        Ok(db_path)
+    }
+
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        match self.idle_timeout {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        }
+    }
+}
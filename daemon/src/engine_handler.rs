@@ -0,0 +1,39 @@
+use std::thread;
+use std::thread::JoinHandle;
+
+use khiin::Engine;
+use tokio::sync::mpsc;
+use tokio::sync::oneshot;
+
+pub type EngineMessage = (Vec<u8>, oneshot::Sender<Vec<u8>>);
+
+const QUEUE_SIZE: usize = 32;
+
+/// Runs `engine` on its own thread, since commands block on the database.
+/// Commands from all clients are handled one at a time in the order they
+/// arrive. The thread ends once every sender has been dropped.
+pub fn spawn(
+    mut engine: Engine,
+) -> (mpsc::Sender<EngineMessage>, JoinHandle<()>) {
+    let (tx, mut rx) = mpsc::channel::<EngineMessage>(QUEUE_SIZE);
+
+    let thread = thread::spawn(move || {
+        while let Some((command_bytes, sender)) = rx.blocking_recv() {
+            let bytes = match engine.send_command_bytes(&command_bytes) {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    log::error!("Unable to serialize the response: {}", e);
+                    continue;
+                },
+            };
+
+            if sender.send(bytes).is_err() {
+                log::debug!("Client left before its response was sent");
+            }
+        }
+
+        log::debug!("Engine thread stopped");
+    });
+
+    (tx, thread)
+}
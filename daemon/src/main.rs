@@ -0,0 +1,69 @@
+#[cfg(unix)]
+mod args;
+#[cfg(unix)]
+mod engine_handler;
+#[cfg(unix)]
+mod server;
+
+#[cfg(unix)]
+#[tokio::main]
+pub async fn main() -> anyhow::Result<()> {
+    use anyhow::anyhow;
+    use clap::Parser;
+    use khiin::Engine;
+
+    use self::args::Args;
+
+    env_logger::init();
+    let args = Args::parse();
+
+    let db_path = args.db_path()?;
+    let engine = Engine::new(&db_path).ok_or(anyhow!(
+        "Unable to load the database at {:?}",
+        db_path
+    ))?;
+
+    let socket = args.socket_path();
+    let listener = server::bind(&socket)?;
+    log::info!("Begin listening on: {:?}", socket);
+
+    let (engine_tx, engine_thread) = engine_handler::spawn(engine);
+    let result =
+        server::run(listener, engine_tx, args.idle_timeout(), shutdown_signal())
+            .await;
+
+    if let Err(e) = std::fs::remove_file(&socket) {
+        log::warn!("Unable to remove {:?}: {}", socket, e);
+    }
+    if engine_thread.join().is_err() {
+        log::error!("Engine thread panicked");
+    }
+
+    log::info!("Shutdown complete.");
+    result
+}
+
+/// Resolves on Ctrl-C or SIGTERM
+#[cfg(unix)]
+async fn shutdown_signal() {
+    use tokio::signal::unix::signal;
+    use tokio::signal::unix::SignalKind;
+
+    match signal(SignalKind::terminate()) {
+        Ok(mut terminate) => {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {},
+                _ = terminate.recv() => {},
+            }
+        },
+        Err(_) => {
+            let _ = tokio::signal::ctrl_c().await;
+        },
+    }
+}
+
+#[cfg(not(unix))]
+pub fn main() {
+    eprintln!("khiin_daemon needs Unix domain sockets, use khiin_service");
+    std::process::exit(1);
+}
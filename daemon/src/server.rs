@@ -0,0 +1,329 @@
+//! Serves one engine to any number of clients over a Unix domain socket.
+//! Messages use the same framing as the Windows service: a serialized
+//! `Command` prefixed with its length as a little-endian `u32`.
+
+use std::future::pending;
+use std::future::Future;
+use std::fs::Permissions;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use anyhow::Result;
+use futures::io::BufReader;
+use khiin_protos::command::Command;
+use khiin_protos::command::CommandType;
+use khiin_protos::command::Response;
+use khiin_protos::helpers::read_u32_delimited_bytes_async;
+use protobuf::Message;
+use tokio::io::AsyncWriteExt;
+use tokio::net::UnixListener;
+use tokio::net::UnixStream;
+use tokio::sync::mpsc;
+use tokio::sync::oneshot;
+use tokio::sync::watch;
+use tokio::time::sleep;
+use tokio::time::Duration;
+use tokio_util::compat::TokioAsyncReadCompatExt;
+use tokio_util::sync::CancellationToken;
+
+use crate::engine_handler::EngineMessage;
+
+/// The effective user id of the daemon, which must own its socket
+pub fn current_uid() -> u32 {
+    // Safety: `geteuid` has no preconditions and always succeeds
+    unsafe { libc::geteuid() }
+}
+
+/// Binds to `path`, replacing any socket file left behind by a daemon that
+/// did not shut down cleanly. The socket is only accessible to the current
+/// user, and a file owned by anyone else is never used or replaced.
+pub fn bind(path: &Path) -> Result<UnixListener> {
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if metadata.uid() != current_uid() {
+            return Err(anyhow!("{:?} is owned by another user", path));
+        }
+
+        if std::os::unix::net::UnixStream::connect(path).is_ok() {
+            return Err(anyhow!("Another daemon is listening on {:?}", path));
+        }
+
+        std::fs::remove_file(path)?;
+    }
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    let listener = UnixListener::bind(path)?;
+    std::fs::set_permissions(path, Permissions::from_mode(0o600))?;
+    Ok(listener)
+}
+
+/// Counts a client as connected for as long as it is alive
+struct Connection {
+    count: Arc<watch::Sender<usize>>,
+}
+
+impl Connection {
+    fn new(count: Arc<watch::Sender<usize>>) -> Self {
+        count.send_modify(|n| *n += 1);
+        Self { count }
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.count.send_modify(|n| *n -= 1);
+    }
+}
+
+/// Resolves once no client has been connected for `timeout`
+async fn idle(mut count: watch::Receiver<usize>, timeout: Duration) {
+    loop {
+        if *count.borrow_and_update() == 0 {
+            tokio::select! {
+                _ = sleep(timeout) => return,
+                res = count.changed() => {
+                    if res.is_err() {
+                        return;
+                    }
+                },
+            }
+        } else if count.changed().await.is_err() {
+            return;
+        }
+    }
+}
+
+fn is_shutdown(bytes: &[u8]) -> bool {
+    Command::parse_from_bytes(bytes).is_ok_and(|cmd| {
+        cmd.request.type_.enum_value_or_default() == CommandType::CMD_SHUTDOWN
+    })
+}
+
+/// The daemon answers `CMD_SHUTDOWN` itself, since it applies to the
+/// process rather than to the engine
+fn shutdown_response(bytes: &[u8]) -> Result<Vec<u8>> {
+    let mut cmd = Command::parse_from_bytes(bytes)?;
+    cmd.response = Some(Response::default()).into();
+    Ok(cmd.write_to_bytes()?)
+}
+
+struct ClientHandler {
+    engine_tx: mpsc::Sender<EngineMessage>,
+    cancel_token: CancellationToken,
+}
+
+impl ClientHandler {
+    async fn run(&self, stream: UnixStream) -> Result<()> {
+        let (reader, mut writer) = stream.into_split();
+        let mut reader = BufReader::new(reader.compat());
+
+        loop {
+            let bytes = tokio::select! {
+                res = read_u32_delimited_bytes_async(&mut reader) => {
+                    match res {
+                        Ok(bytes) => bytes,
+                        Err(_) => {
+                            log::debug!("Client disconnected");
+                            return Ok(());
+                        },
+                    }
+                },
+                _ = self.cancel_token.cancelled() => return Ok(()),
+            };
+
+            if is_shutdown(&bytes) {
+                let response = shutdown_response(&bytes)?;
+                writer.write_all(&delimit(response)).await?;
+                self.cancel_token.cancel();
+                return Ok(());
+            }
+
+            let (tx, rx) = oneshot::channel();
+            self.engine_tx.send((bytes, tx)).await?;
+            let response = rx.await?;
+            writer.write_all(&delimit(response)).await?;
+        }
+    }
+}
+
+fn delimit(mut bytes: Vec<u8>) -> Vec<u8> {
+    let len = (bytes.len() as u32).to_le_bytes();
+    bytes.splice(..0, len.iter().cloned());
+    bytes
+}
+
+/// Accepts clients until one of them sends `CMD_SHUTDOWN`, `shutdown`
+/// resolves, or no client has been connected for `idle_timeout`. Clients
+/// still connected then finish their current command before being closed.
+pub async fn run(
+    listener: UnixListener,
+    engine_tx: mpsc::Sender<EngineMessage>,
+    idle_timeout: Option<Duration>,
+    shutdown: impl Future,
+) -> Result<()> {
+    let cancel_token = CancellationToken::new();
+    let (count_tx, mut count_rx) = watch::channel(0);
+    let count_tx = Arc::new(count_tx);
+
+    let idle_rx = count_rx.clone();
+    let idle_timer = async move {
+        match idle_timeout {
+            Some(timeout) => idle(idle_rx, timeout).await,
+            None => pending().await,
+        }
+    };
+    tokio::pin!(idle_timer);
+    tokio::pin!(shutdown);
+
+    let result = loop {
+        let stream = tokio::select! {
+            res = listener.accept() => match res {
+                Ok((stream, _)) => stream,
+                Err(e) => break Err(e.into()),
+            },
+            _ = &mut idle_timer => {
+                log::debug!("No clients connected, stopping.");
+                break Ok(());
+            },
+            _ = cancel_token.cancelled() => {
+                log::debug!("Shutdown request received.");
+                break Ok(());
+            },
+            _ = &mut shutdown => {
+                log::debug!("Shutting down!");
+                break Ok(());
+            },
+        };
+
+        let connection = Connection::new(count_tx.clone());
+        let handler = ClientHandler {
+            engine_tx: engine_tx.clone(),
+            cancel_token: cancel_token.clone(),
+        };
+
+        tokio::spawn(async move {
+            if let Err(e) = handler.run(stream).await {
+                log::error!("Connection error: {}", e);
+            }
+            drop(connection);
+        });
+    };
+
+    cancel_token.cancel();
+    while *count_rx.borrow_and_update() > 0 {
+        if count_rx.changed().await.is_err() {
+            break;
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::thread::JoinHandle;
+
+    use futures::io::BufReader;
+    use khiin::engine::commands;
+    use khiin::Engine;
+    use khiin_protos::command::ErrorCode;
+    use khiin_protos::helpers::parse_u32_delimited_bytes_async;
+    use khiin_protos::helpers::WriteDelim;
+    use tokio::net::unix::OwnedReadHalf;
+    use tokio::net::unix::OwnedWriteHalf;
+    use tokio::time::timeout;
+    use tokio_util::compat::Compat;
+
+    use super::*;
+    use crate::engine_handler;
+
+    fn socket_path(name: &str) -> PathBuf {
+        let file = format!("khiin-{}-{}.sock", name, std::process::id());
+        std::env::temp_dir().join(file)
+    }
+
+    fn spawn_engine() -> (mpsc::Sender<EngineMessage>, JoinHandle<()>) {
+        let db_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("..")
+            .join("target")
+            .join("debug")
+            .join("khiin.db");
+        engine_handler::spawn(Engine::new(db_path).unwrap())
+    }
+
+    struct Client {
+        reader: BufReader<Compat<OwnedReadHalf>>,
+        writer: OwnedWriteHalf,
+    }
+
+    impl Client {
+        async fn connect(path: &Path) -> Self {
+            let stream = UnixStream::connect(path).await.unwrap();
+            let (reader, writer) = stream.into_split();
+            Self {
+                reader: BufReader::new(reader.compat()),
+                writer,
+            }
+        }
+
+        async fn send(&mut self, cmd: Command) -> Command {
+            let bytes = cmd.write_u32_delimited_bytes().unwrap();
+            self.writer.write_all(&bytes).await.unwrap();
+            parse_u32_delimited_bytes_async(&mut self.reader)
+                .await
+                .unwrap()
+        }
+    }
+
+    #[tokio::test]
+    async fn it_serves_concurrent_clients() {
+        let path = socket_path("clients");
+        let listener = bind(&path).unwrap();
+        assert!(bind(&path).is_err());
+        let metadata = std::fs::metadata(&path).unwrap();
+        assert_eq!(metadata.uid(), current_uid());
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+        let (engine_tx, engine_thread) = spawn_engine();
+        let server = run(listener, engine_tx, None, pending::<()>());
+        let server = tokio::spawn(server);
+
+        let mut a = Client::connect(&path).await;
+        let mut b = Client::connect(&path).await;
+        let (res_a, res_b) = tokio::join!(
+            a.send(commands::simple(CommandType::CMD_RESET)),
+            b.send(commands::send_char('a')),
+        );
+        assert_eq!(res_a.response.error.enum_value(), Ok(ErrorCode::OK));
+        assert_eq!(res_b.response.error.enum_value(), Ok(ErrorCode::OK));
+
+        let res = b.send(commands::simple(CommandType::CMD_SHUTDOWN)).await;
+        let cmd_type = res.request.type_.enum_value();
+        assert_eq!(cmd_type, Ok(CommandType::CMD_SHUTDOWN));
+        assert_eq!(res.response.error.enum_value(), Ok(ErrorCode::OK));
+
+        let stopped = timeout(Duration::from_secs(5), server).await;
+        assert!(stopped.unwrap().unwrap().is_ok());
+        engine_thread.join().unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_stops_when_idle() {
+        let path = socket_path("idle");
+        let listener = bind(&path).unwrap();
+        let (engine_tx, engine_thread) = spawn_engine();
+        let idle_timeout = Some(Duration::from_millis(100));
+        let server = run(listener, engine_tx, idle_timeout, pending::<()>());
+
+        let stopped = timeout(Duration::from_secs(5), server).await;
+        assert!(stopped.unwrap().is_ok());
+        engine_thread.join().unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+}
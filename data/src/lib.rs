@@ -3,4 +3,11 @@ pub static CONVERSIONS_CSV: &'static str =
 
 pub static INPUTS_CSV: &'static str = include_str!("../data/frequency.csv");
 
+pub static EMOJI_CSV: &str = include_str!("../data/emoji.csv");
+
+pub static EMOJI_KEYWORDS_CSV: &str =
+    include_str!("../data/emoji_keywords.csv");
+
+pub static SYMBOLS_TSV: &str = include_str!("../data/symbols.tsv");
+
 pub static SYLLABLES_TXT: &'static str = include_str!("../data/syllables.txt");
\ No newline at end of file
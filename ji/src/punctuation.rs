@@ -76,59 +76,182 @@
 // menu, 漢字 submode == [〕, 】, 〗]
 // direct output, 羅字 submode == ]
 
-const APOSTROPHE_HANJI_CHARS: [char; 3] = ['「', '」', '\''];
-// const APOSTROPHE_LOMAJI_CHARS: [char; 3] = ['‘', '’', '\''];
+// # -------
+
+// The following keys are direct output in both submodes:
+
+// input = .   漢字 == 。   羅字 == .
+// input = ,   漢字 == 、   羅字 == ,
+// input = ?   漢字 == ？   羅字 == ?
+// input = !   漢字 == ！   羅字 == !
+// input = (   漢字 == （   羅字 == (
+// input = )   漢字 == ）   羅字 == )
+
+/// How a punctuation key behaves in one output submode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Punctuation {
+    /// Committed as soon as the key is typed
+    Direct(&'static str),
+    /// Opens a candidate menu, with the first item focused
+    Menu(&'static [&'static str]),
+}
+
+use Punctuation::*;
+
+const APOSTROPHE_HANJI: Punctuation = Menu(&["「", "」", "'"]);
+const APOSTROPHE_LOMAJI: Punctuation = Menu(&["‘", "’", "'"]);
+
+const QUOTE_HANJI: Punctuation = Menu(&["『", "』", "\"", "々", "〱"]);
+const QUOTE_LOMAJI: Punctuation = Menu(&["“", "”", "\""]);
+
+const LESS_THAN_HANJI: Punctuation = Menu(&["〈", "《"]);
+const LESS_THAN_LOMAJI: Punctuation = Menu(&["<", "«"]);
 
-const QUOTE_HANJI_CHARS: [char; 5] = ['『', '』', '"', '々', '〱'];
-// const QUOTE_LOMAJI_CHARS: [char; 3] = ['"', '"', '"'];
+const GREATER_THAN_HANJI: Punctuation = Menu(&["〉", "》"]);
+const GREATER_THAN_LOMAJI: Punctuation = Menu(&[">", "»"]);
 
-const LESS_THAN_HANJI_CHARS: [char; 2] = ['〈', '《'];
-const LESS_THAN_LOMAJI_CHARS: [char; 2] = ['<', '«'];
+const SEMICOLON_HANJI: Punctuation = Direct("・");
+const SEMICOLON_LOMAJI: Punctuation = Direct(";");
 
-const GREATER_THAN_HANJI_CHARS: [char; 2] = ['〉', '》'];
-const GREATER_THAN_LOMAJI_CHARS: [char; 2] = ['>', '»'];
+const COLON_HANJI: Punctuation = Menu(&["：", "⋯⋯"]);
+const COLON_LOMAJI: Punctuation = Direct(":");
 
-// const COLON_HANJI_CHARS: [char; 2] = ['：', '⋯'];
-// const COLON_LOMAJI_CHAR: char = ':';
+const PLUS_HANJI: Punctuation = Menu(&["+", "＋", "⁺"]);
+const PLUS_LOMAJI: Punctuation = Menu(&["+", "⁺"]);
 
-const PLUS_HANJI_CHARS: [char; 3] = ['+', '＋', '⁺'];
-const PLUS_LOMAJI_CHARS: [char; 2] = ['+', '⁺'];
+const EQUALS_HANJI: Punctuation = Menu(&["=", "＝", "〓"]);
+const EQUALS_LOMAJI: Punctuation = Direct("=");
 
-const EQUALS_HANJI_CHARS: [char; 3] = ['=', '＝', '〓'];
-// const EQUALS_LOMAJI_CHAR: char = '=';
+const UNDERSCORE_HANJI: Punctuation = Menu(&["_", "—", "＿", "⁻"]);
+const UNDERSCORE_LOMAJI: Punctuation = Menu(&["_", "—", "⁻"]);
 
-const UNDERSCORE_HANJI_CHARS: [char; 4] = ['_', '—', '＿', '⁻'];
-const UNDERSCORE_LOMAJI_CHARS: [char; 3] = ['_', '—', '⁻'];
+const LEFT_BRACKET_HANJI: Punctuation = Menu(&["〔", "【", "〖"]);
+const LEFT_BRACKET_LOMAJI: Punctuation = Direct("[");
 
-const LEFT_BRACKET_HANJI_CHARS: [char; 3] = ['〔', '【', '〖'];
-// const LEFT_BRACKET_LOMAJI_CHAR: char = '[';
+const RIGHT_BRACKET_HANJI: Punctuation = Menu(&["〕", "】", "〗"]);
+const RIGHT_BRACKET_LOMAJI: Punctuation = Direct("]");
 
-const RIGHT_BRACKET_HANJI_CHARS: [char; 3] = ['〕', '】', '〗'];
-// const RIGHT_BRACKET_LOMAJI_CHAR: char = ']';
+/// Opening and closing punctuation, used to close a pair when its opening
+/// half is committed, or to offer the closing half first when it was not
+const PAIRS: [(&str, &str); 16] = [
+    ("「", "」"),
+    ("『", "』"),
+    ("〈", "〉"),
+    ("《", "》"),
+    ("〔", "〕"),
+    ("【", "】"),
+    ("〖", "〗"),
+    ("（", "）"),
+    ("‘", "’"),
+    ("“", "”"),
+    ("«", "»"),
+    ("(", ")"),
+    ("[", "]"),
+    ("<", ">"),
+    ("＜", "＞"),
+    ("［", "］"),
+];
 
-pub fn get_lomaji_chars(key: char) -> Option<Vec<char>> {
+pub fn hanji_punctuation(key: char) -> Option<Punctuation> {
     match key {
-        // '\'' => Some(APOSTROPHE_LOMAJI_CHARS.to_vec()),
-        // '"' => Some(QUOTE_LOMAJI_CHARS.to_vec()),
-        '<' => Some(LESS_THAN_LOMAJI_CHARS.to_vec()),
-        '>' => Some(GREATER_THAN_LOMAJI_CHARS.to_vec()),
-        '+' => Some(PLUS_LOMAJI_CHARS.to_vec()),
-        '_' => Some(UNDERSCORE_LOMAJI_CHARS.to_vec()),
+        '\'' => Some(APOSTROPHE_HANJI),
+        '"' => Some(QUOTE_HANJI),
+        '<' => Some(LESS_THAN_HANJI),
+        '>' => Some(GREATER_THAN_HANJI),
+        ';' => Some(SEMICOLON_HANJI),
+        ':' => Some(COLON_HANJI),
+        '+' => Some(PLUS_HANJI),
+        '=' => Some(EQUALS_HANJI),
+        '_' => Some(UNDERSCORE_HANJI),
+        '[' => Some(LEFT_BRACKET_HANJI),
+        ']' => Some(RIGHT_BRACKET_HANJI),
+        '.' => Some(Direct("。")),
+        ',' => Some(Direct("、")),
+        '?' => Some(Direct("？")),
+        '!' => Some(Direct("！")),
+        '(' => Some(Direct("（")),
+        ')' => Some(Direct("）")),
         _ => None,
     }
 }
 
-pub fn get_hanji_chars(key: char) -> Option<Vec<char>> {
+pub fn lomaji_punctuation(key: char) -> Option<Punctuation> {
     match key {
-        '\'' => Some(APOSTROPHE_HANJI_CHARS.to_vec()),
-        '"' => Some(QUOTE_HANJI_CHARS.to_vec()),
-        '<' => Some(LESS_THAN_HANJI_CHARS.to_vec()),
-        '>' => Some(GREATER_THAN_HANJI_CHARS.to_vec()),
-        '+' => Some(PLUS_HANJI_CHARS.to_vec()),
-        '=' => Some(EQUALS_HANJI_CHARS.to_vec()),
-        '_' => Some(UNDERSCORE_HANJI_CHARS.to_vec()),
-        '[' => Some(LEFT_BRACKET_HANJI_CHARS.to_vec()),
-        ']' => Some(RIGHT_BRACKET_HANJI_CHARS.to_vec()),
+        '\'' => Some(APOSTROPHE_LOMAJI),
+        '"' => Some(QUOTE_LOMAJI),
+        '<' => Some(LESS_THAN_LOMAJI),
+        '>' => Some(GREATER_THAN_LOMAJI),
+        ';' => Some(SEMICOLON_LOMAJI),
+        ':' => Some(COLON_LOMAJI),
+        '+' => Some(PLUS_LOMAJI),
+        '=' => Some(EQUALS_LOMAJI),
+        '_' => Some(UNDERSCORE_LOMAJI),
+        '[' => Some(LEFT_BRACKET_LOMAJI),
+        ']' => Some(RIGHT_BRACKET_LOMAJI),
+        '.' => Some(Direct(".")),
+        ',' => Some(Direct(",")),
+        '?' => Some(Direct("?")),
+        '!' => Some(Direct("!")),
+        '(' => Some(Direct("(")),
+        ')' => Some(Direct(")")),
+        _ => None,
+    }
+}
+
+pub fn is_punctuation_key(key: char) -> bool {
+    hanji_punctuation(key).is_some()
+}
+
+/// The closing half of an opening punctuation mark
+pub fn closing_pair(open: &str) -> Option<&'static str> {
+    PAIRS.iter().find(|(o, _)| *o == open).map(|(_, c)| *c)
+}
+
+/// The closing half to insert together with an opening punctuation mark.
+/// Angle brackets are left unpaired, since they are also comparison signs.
+pub fn auto_closing_pair(open: &str) -> Option<&'static str> {
+    match open {
+        "<" | "＜" => None,
+        _ => closing_pair(open),
+    }
+}
+
+/// The opening half of a closing punctuation mark
+pub fn opening_pair(close: &str) -> Option<&'static str> {
+    PAIRS.iter().find(|(_, c)| *c == close).map(|(o, _)| *o)
+}
+
+/// Full width form of a printable ASCII character, e.g. `!` to `！`
+pub fn to_fullwidth(ch: char) -> Option<char> {
+    match ch {
+        '!'..='~' => char::from_u32(ch as u32 - 0x21 + 0xFF01),
         _ => None,
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_follows_the_spec() {
+        assert_eq!(hanji_punctuation(';'), Some(Direct("・")));
+        assert_eq!(lomaji_punctuation(':'), Some(Direct(":")));
+        assert_eq!(hanji_punctuation(':'), Some(Menu(&["：", "⋯⋯"])));
+        assert_eq!(lomaji_punctuation('a'), None);
+        assert!(is_punctuation_key('['));
+    }
+
+    #[test]
+    fn it_finds_pairs() {
+        assert_eq!(closing_pair("「"), Some("」"));
+        assert_eq!(opening_pair("」"), Some("「"));
+        assert_eq!(closing_pair("」"), None);
+        assert_eq!(auto_closing_pair("（"), Some("）"));
+        assert_eq!(auto_closing_pair("<"), None);
+        assert_eq!(to_fullwidth('!'), Some('！'));
+        assert_eq!(to_fullwidth('~'), Some('～'));
+        assert_eq!(to_fullwidth('a'), Some('ａ'));
+        assert_eq!(to_fullwidth(' '), None);
+    }
+}
@@ -14,6 +14,19 @@ pub struct Args {
 
     #[arg(short, long)]
     pub output_file: String,
+
+    /// CSV with `lgram,rgram,count` columns used to fill the n-gram tables
+    #[arg(short, long)]
+    pub bigram_file: Option<String>,
+
+    /// Precompiled word index, by default next to the output file with the
+    /// `.idx` extension
+    #[arg(short, long)]
+    pub index_file: Option<String>,
+
+    /// Do not write the precompiled word index
+    #[arg(long)]
+    pub no_index: bool,
 }
 
 impl Args {
@@ -30,6 +43,13 @@ impl Args {
             return Err(anyhow!("Frequency file {:?} not found.", path));
         }
 
+        if let Some(bigram_file) = &args.bigram_file {
+            let path = PathBuf::from(bigram_file);
+            if !path.exists() {
+                return Err(anyhow!("Bigram file {:?} not found.", path));
+            }
+        }
+
         Ok(args)
     }
 }
@@ -2,6 +2,7 @@ mod clap;
 
 use std::borrow::Cow;
 use std::fs::read_to_string;
+use std::path::PathBuf;
 
 use anyhow::Result;
 use simplelog::Config;
@@ -9,6 +10,8 @@ use simplelog::SimpleLogger;
 
 use khiin::db::CsvFiles;
 use khiin::db::Database;
+use khiin::index_path;
+use khiin::CompiledIndex;
 
 use self::clap::Args;
 
@@ -17,13 +20,19 @@ pub fn main() -> Result<()> {
     log::debug!("Begin database generation");
 
     let result = match Args::validate() {
-        Ok(args) => Database::from_csv(
-            &args.output_file,
-            CsvFiles::new(
+        Ok(args) => {
+            let mut csv_files = CsvFiles::new(
                 read_to_cow(&args.frequency_file)?,
                 read_to_cow(&args.conversions_file)?,
-            ),
-        ),
+            );
+
+            if let Some(bigram_file) = &args.bigram_file {
+                csv_files = csv_files.with_bigrams(read_to_cow(bigram_file)?);
+            }
+
+            Database::from_csv(&args.output_file, csv_files)
+                .and_then(|db| write_index(&args, &db))
+        },
         Err(e) => {
             log::error!("{}", e);
             return Err(e);
@@ -38,6 +47,21 @@ pub fn main() -> Result<()> {
     Ok(())
 }
 
+fn write_index(args: &Args, db: &Database) -> Result<()> {
+    if args.no_index {
+        return Ok(());
+    }
+
+    let index_file = match &args.index_file {
+        Some(file) => PathBuf::from(file),
+        None => index_path(&args.output_file),
+    };
+
+    CompiledIndex::write(db, &index_file)?;
+    log::debug!("Index written to: {:?}", index_file);
+    Ok(())
+}
+
 fn read_to_cow(file: &str) -> Result<Cow<str>> {
     Ok(Cow::Owned(read_to_string(file)?))
 }
@@ -47,6 +47,10 @@ impl BufferElement for ActionElem {
         raw_caret
     }
 
+    fn caret_stops(&self) -> Vec<(usize, usize)> {
+        vec![(self.display_char_count(), self.raw_char_count())]
+    }
+
     fn set_converted(&mut self, converted: bool) {
         // Not impl
     }
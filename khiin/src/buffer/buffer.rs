@@ -3,6 +3,8 @@ use std::ops::DerefMut;
 
 use khiin_ji::IsHanji;
 
+use crate::buffer::buffer_element::caret_from_stops;
+use crate::buffer::buffer_element::raw_caret_from_stops;
 use crate::buffer::BufferElement;
 use crate::buffer::BufferElementEnum;
 use crate::buffer::Spacer;
@@ -113,41 +115,34 @@ impl Buffer {
     // composed:  "pengan" 6
     // converted: "平安"    2
     pub fn raw_caret_from(&self, char_caret: usize) -> usize {
-        if char_caret == self.display_char_count() {
-            return self.raw_char_count();
-        }
-
-        let caret_at_index = self.elem_index_at_char_caret(char_caret);
-
-        assert!(caret_at_index < self.elems.len());
-
-        let mut pre = self.clone();
-
-        // Index 0 covered by the assert above
-        let at = pre.split_off(caret_at_index);
-        let at = &at[0];
-
-        let pre_caret_char_count = pre.display_text().chars().count();
-
-        let remainder = char_caret - pre_caret_char_count;
+        raw_caret_from_stops(&self.caret_stops(), char_caret)
+    }
 
-        pre.raw_char_count() + at.raw_caret_from(remainder)
+    pub fn caret_from(&self, raw_caret: usize) -> usize {
+        caret_from_stops(&self.caret_stops(), raw_caret)
     }
 
-    fn elem_index_at_char_caret(&self, char_caret: usize) -> usize {
-        let mut remainder = char_caret;
-        let mut index = 0;
-        for (i, elem) in self.elems.iter().enumerate() {
-            index = i;
-            let elem_char_count = elem.composed_char_count();
-            if remainder > elem_char_count {
-                remainder -= elem_char_count;
-            } else {
-                break;
+    /// Positions where the caret may rest in the displayed text, as
+    /// `(display, raw)` char offsets in ascending order, starting at `(0, 0)`.
+    /// Positions that share a raw offset, such as either side of a spacer, are
+    /// only kept once.
+    pub fn caret_stops(&self) -> Vec<(usize, usize)> {
+        let mut stops = vec![(0, 0)];
+        let mut display = 0;
+        let mut raw = 0;
+
+        for elem in self.elems.iter() {
+            for (d, r) in elem.caret_stops() {
+                let last_raw = stops.last().map_or(0, |(_, r)| *r);
+                if raw + r > last_raw {
+                    stops.push((display + d, raw + r));
+                }
             }
+            display += elem.display_char_count();
+            raw += elem.raw_char_count();
         }
 
-        index
+        stops
     }
 
     pub fn elem_index_at_raw_char_count(&self, char_count: usize) -> usize {
@@ -171,7 +166,7 @@ impl Buffer {
         self.clear_autospace();
 
         let mut i = 0;
-        while i < self.elems.len() - 1 {
+        while i + 1 < self.elems.len() {
             match (
                 &self.elems[i].display_text().chars().last(),
                 &self.elems[i + 1].display_text().chars().next(),
@@ -263,4 +258,29 @@ mod tests {
         buf.elems.push(el.into());
         assert_eq!(buf.raw_text().as_str(), "ho");
     }
+
+    #[test]
+    fn it_autospaces_any_buffer() {
+        let mut buf = Buffer::default();
+        buf.autospace();
+        assert_eq!(buf.elems.len(), 0);
+        buf.push(StringElem::from("ho").into());
+        buf.push(StringElem::from("a").into());
+        buf.autospace();
+        assert_eq!(buf.elems.len(), 3);
+    }
+
+    #[test]
+    fn it_maps_carets_across_elements() {
+        let mut buf = Buffer::default();
+        buf.push(StringElem::from("ab").into());
+        buf.push(Spacer::new().into());
+        buf.push(StringElem::from_raw_input(".".into(), "。".into()).into());
+        let stops = buf.caret_stops();
+        assert_eq!(stops, vec![(0, 0), (1, 1), (2, 2), (4, 3)]);
+        assert_eq!(buf.raw_caret_from(3), 2);
+        assert_eq!(buf.raw_caret_from(4), 3);
+        assert_eq!(buf.caret_from(2), 2);
+        assert_eq!(buf.caret_from(3), 4);
+    }
 }
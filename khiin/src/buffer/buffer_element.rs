@@ -36,6 +36,11 @@ pub trait BufferElement {
     fn raw_caret_from(&self, caret: usize) -> usize;
     fn caret_from(&self, raw_caret: usize) -> usize;
 
+    /// Positions inside the element where the caret may rest, as
+    /// `(display, raw)` char offsets in ascending order. The start of the
+    /// element is not included, and the end always is.
+    fn caret_stops(&self) -> Vec<(usize, usize)>;
+
     fn set_converted(&mut self, converted: bool);
     fn is_converted(&self) -> bool;
     fn is_selected(&self) -> bool;
@@ -47,3 +52,31 @@ pub trait BufferElement {
     // fn insert(&mut self, idx: usize, ch: char);
     // fn erase(&mut self, idx: usize);
 }
+
+/// Raw caret of the last stop at or before the display `caret`
+pub(crate) fn raw_caret_from_stops(
+    stops: &[(usize, usize)],
+    caret: usize,
+) -> usize {
+    stops
+        .iter()
+        .take_while(|(display, _)| *display <= caret)
+        .last()
+        .map_or(0, |(_, raw)| *raw)
+}
+
+/// Display caret of the first stop at or after the `raw_caret`
+pub(crate) fn caret_from_stops(
+    stops: &[(usize, usize)],
+    raw_caret: usize,
+) -> usize {
+    if raw_caret == 0 {
+        return 0;
+    }
+
+    stops
+        .iter()
+        .find(|(_, raw)| *raw >= raw_caret)
+        .or(stops.last())
+        .map_or(0, |(display, _)| *display)
+}
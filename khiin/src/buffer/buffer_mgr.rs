@@ -6,9 +6,11 @@ use anyhow::anyhow;
 use anyhow::Result;
 
 use khiin_ji::lomaji::is_legal_lomaji;
-use khiin_ji::punctuation::get_hanji_chars;
-use khiin_ji::punctuation::get_lomaji_chars;
+use khiin_ji::punctuation::closing_pair;
+use khiin_ji::punctuation::is_punctuation_key;
+use khiin_ji::punctuation::opening_pair;
 use khiin_ji::IsHanji;
+use khiin_ji::ToByteLen;
 use khiin_protos::command::preedit::Segment;
 use khiin_protos::command::Candidate;
 use khiin_protos::command::CandidateList;
@@ -22,11 +24,13 @@ use regex::Regex;
 
 use crate::buffer::Buffer;
 use crate::buffer::BufferElement;
+use crate::buffer::BufferElementEnum;
 use crate::config::Config;
 use crate::config::InputMode;
 use crate::config::KhinMode;
 use crate::data::Dictionary;
-use crate::db::Database;
+use crate::db::DictionaryBackend;
+use crate::engine::error::CommandError;
 use crate::engine::EngInner;
 use crate::input::converter::convert_all;
 use crate::input::converter::convert_guess;
@@ -35,6 +39,11 @@ use crate::input::converter::get_candidates;
 use crate::input::converter::get_candidates_for_word;
 use crate::input::converter::get_candidates_for_word_with_tone;
 use crate::input::converter::get_numberic_tone_char;
+use crate::input::converter::to_dictionary_keys;
+use crate::input::parser::EMOJI_TRIGGER;
+use crate::input::punctuation::move_to_front;
+use crate::input::punctuation::punct_output;
+use crate::input::punctuation::PunctOutput;
 use crate::utils::CharSubstr;
 
 use super::ActionElem;
@@ -63,6 +72,14 @@ pub(crate) struct BufferMgr {
 
     /// Whether the candidate is expanded
     cand_expanded: bool,
+
+    /// Opening brackets and quotes committed without their closing half,
+    /// innermost last. Kept across commits, cleared by `clear_history`.
+    open_puncts: Vec<String>,
+
+    /// Raw input and committed text of the last commit, until another key
+    /// is typed
+    last_commit: Option<(String, String)>,
 }
 
 impl BufferMgr {
@@ -77,6 +94,8 @@ impl BufferMgr {
             cand_page: 0,
             pre_committed: String::new(),
             cand_expanded: false,
+            open_puncts: Vec::new(),
+            last_commit: None,
         }
     }
 
@@ -183,7 +202,122 @@ impl BufferMgr {
         Ok(())
     }
 
+    /// Updates the open bracket and quote pairs from committed text
+    pub fn track_punct_pairs(&mut self, committed: &str) {
+        for ch in committed.chars() {
+            let text = ch.to_string();
+            if closing_pair(&text).is_some() {
+                self.open_puncts.push(text);
+            } else if let Some(open) = opening_pair(&text) {
+                if let Some(pos) =
+                    self.open_puncts.iter().rposition(|p| p == open)
+                {
+                    self.open_puncts.truncate(pos);
+                }
+            }
+        }
+    }
+
+    /// Forgets the state kept across commits, e.g. when the caret has been
+    /// moved away by the app
+    pub fn clear_history(&mut self) {
+        self.open_puncts.clear();
+        self.last_commit = None;
+    }
+
+    pub fn raw_text(&self) -> String {
+        self.composition.raw_text()
+    }
+
+    /// Records a commit so that it can be reverted. `raw_input` is the raw
+    /// text of the composition before committing, of which any part still
+    /// being composed is not part of the commit.
+    pub fn remember_commit(&mut self, raw_input: String, committed: &str) {
+        let remaining = self.composition.raw_text();
+        let raw = raw_input
+            .strip_suffix(remaining.as_str())
+            .unwrap_or(&raw_input)
+            .to_string();
+
+        self.last_commit = if raw.is_empty() || committed.is_empty() {
+            None
+        } else {
+            Some((raw, committed.to_string()))
+        };
+    }
+
+    /// Turns converted segments back into the raw keys typed
+    pub fn revert_composition(&mut self, engine: &EngInner) -> Result<()> {
+        if self.edit_state == EditState::ES_EMPTY || !self.is_focused() {
+            return Ok(());
+        }
+
+        let raw_input = self.composition.raw_text();
+        self.restore_raw_input(engine, raw_input)
+    }
+
+    /// Brings the last commit back as a composition, returning the committed
+    /// text to be removed by the app
+    pub fn revert_last_commit(&mut self, engine: &EngInner) -> Result<String> {
+        if self.edit_state != EditState::ES_EMPTY {
+            return Ok(String::new());
+        }
+
+        match self.last_commit.take() {
+            Some((raw_input, committed)) => {
+                self.reset()?;
+                self.restore_raw_input(engine, raw_input)?;
+                Ok(committed)
+            },
+            None => Ok(String::new()),
+        }
+    }
+
+    fn restore_raw_input(
+        &mut self,
+        engine: &EngInner,
+        mut raw_input: String,
+    ) -> Result<()> {
+        self.reset_focus();
+        self.edit_state = EditState::ES_COMPOSING;
+
+        match engine.conf.input_mode() {
+            InputMode::Continuous => {
+                self.build_composition_continuous(engine, raw_input)?;
+            },
+            InputMode::Classic => {
+                if let Some(ch) = raw_input.pop() {
+                    self.build_composition_classic(engine, raw_input, ch)?;
+                }
+            },
+            InputMode::Manual => {
+                self.composition = Buffer::new();
+                for ch in raw_input.chars() {
+                    self.insert_manual(engine, ch)?;
+                }
+            },
+        }
+
+        // Direct output punctuation at the end must not commit again
+        self.edit_state = EditState::ES_COMPOSING;
+        Ok(())
+    }
+
+    /// Offers the closing half of the innermost open pair first
+    fn order_by_open_pair(&mut self) {
+        let close = match self.open_puncts.last() {
+            Some(open) => closing_pair(open),
+            None => None,
+        };
+        if let Some(close) = close {
+            move_to_front(&mut self.candidates, |cand| {
+                cand.display_text() == close
+            });
+        }
+    }
+
     pub fn insert(&mut self, engine: &EngInner, ch: char) -> Result<()> {
+        self.last_commit = None;
         match engine.conf.input_mode() {
             InputMode::Continuous => self.insert_continuous(engine, ch),
             InputMode::Classic => self.insert_classic(engine, ch),
@@ -192,6 +326,7 @@ impl BufferMgr {
     }
 
     pub fn pop(&mut self, engine: &EngInner) -> Result<()> {
+        self.last_commit = None;
         if self.edit_state == EditState::ES_EMPTY {
             return Ok(());
         }
@@ -243,6 +378,8 @@ impl BufferMgr {
             return Ok(raw_text);
         }
 
+        self.learn_candidate(engine, &candidate);
+
         let mut cand_raw_count = candidate.raw_char_count();
         let mut candi_text = candidate.display_text();
         let comp_raw = self.composition.raw_text();
@@ -280,6 +417,18 @@ impl BufferMgr {
         return Ok(ret);
     }
 
+    /// Commits the composition ended by a direct output punctuation key. In
+    /// continuous mode the composition is converted up to the punctuation,
+    /// so its converted text is committed and learned.
+    pub fn commit_direct(&mut self, engine: &EngInner) -> Result<String> {
+        if engine.conf.input_mode() != InputMode::Continuous {
+            return self.commit_all(engine);
+        }
+
+        self.learn_candidate(engine, &self.composition);
+        Ok(self.composition.display_text())
+    }
+
     pub fn commit_candidate_and_comosite_remainder(
         &mut self,
         engine: &EngInner,
@@ -326,6 +475,8 @@ impl BufferMgr {
             .ok_or(anyhow!("Candidate index out of bounds"))?
             .clone();
 
+        self.learn_candidate(engine, &candidate);
+
         let mut cand_raw_count = candidate.raw_char_count();
         let mut candi_text = candidate.display_text();
         let comp_raw = self.composition.raw_text();
@@ -371,25 +522,169 @@ impl BufferMgr {
         Ok(ret)
     }
 
+    fn learn_candidate(&self, engine: &EngInner, candidate: &Buffer) {
+        for conv in candidate.iter().filter_map(|elem| elem.candidate()) {
+            if let Err(e) = engine.db.user_data().learn_conversion(conv) {
+                log::error!("Unable to save user conversion: {}", e);
+            }
+        }
+
+        if candidate.raw_text().starts_with(EMOJI_TRIGGER) {
+            let emoji = candidate.display_text();
+            if let Err(e) = engine.db.user_data().record_emoji(&emoji) {
+                log::error!("Unable to save recent emoji: {}", e);
+            }
+        }
+    }
+
     fn insert_continuous(&mut self, engine: &EngInner, ch: char) -> Result<()> {
         self.edit_state = EditState::ES_COMPOSING;
         debug!("BufferMgr::insert_continuous ({})", ch);
+        let raw_caret = self.composition.raw_caret_from(self.char_caret);
         let mut composition = self.composition.raw_text();
-        composition.push(ch);
+        let at_end = raw_caret >= composition.chars().count();
+
+        // Emoji keywords are typed as they are
+        let end = composition.as_str().to_byte_len(raw_caret);
+        let before = &composition[..end];
+        let (replaced, keys) = if before.contains(EMOJI_TRIGGER) {
+            (0, ch.to_string())
+        } else {
+            to_dictionary_keys(engine, before.chars().last(), ch)
+        };
+        let start = raw_caret - replaced;
+        let range = composition.as_str().to_byte_len(start)..end;
+        composition.replace_range(range, &keys);
+
+        self.build_composition_continuous(engine, composition)?;
+        let raw_caret = start + keys.chars().count();
+        self.char_caret = self.composition.caret_from(raw_caret);
+
+        // The emoji trigger stays in the composition so a keyword can follow
+        let as_typed = replaced == 0 && keys == ch.to_string();
+        if at_end && as_typed && ch != EMOJI_TRIGGER {
+            if let Some(PunctOutput::Direct(_)) = punct_output(&engine.conf, ch)
+            {
+                self.edit_state = EditState::ES_EMPTY;
+            }
+        }
 
-        self.build_composition_continuous(engine, composition)
+        Ok(())
     }
 
     fn pop_continuous(&mut self, engine: &EngInner) -> Result<()> {
         debug!("BufferMgr::pop_continuous ");
+        let raw_caret = self.composition.raw_caret_from(self.char_caret);
+        if raw_caret == 0 {
+            return Ok(());
+        }
+
+        self.erase_raw_char_continuous(engine, raw_caret - 1)
+    }
+
+    /// Removes the raw char at `raw_caret` and places the caret there
+    fn erase_raw_char_continuous(
+        &mut self,
+        engine: &EngInner,
+        raw_caret: usize,
+    ) -> Result<()> {
         let mut composition = self.composition.raw_text();
-        composition.pop();
+        composition.remove(composition.as_str().to_byte_len(raw_caret));
 
         if composition.is_empty() {
             return self.reset();
         }
 
-        self.build_composition_continuous(engine, composition)
+        self.build_composition_continuous(engine, composition)?;
+        self.char_caret = self.composition.caret_from(raw_caret);
+        Ok(())
+    }
+
+    /// The caret can only be moved inside the composition in continuous mode,
+    /// since the other modes build the composition from the end
+    fn can_move_caret(&self, engine: &EngInner) -> bool {
+        engine.conf.input_mode() == InputMode::Continuous
+            && self.edit_state != EditState::ES_EMPTY
+    }
+
+    /// Moves the caret back by one syllable, character, or converted segment
+    pub fn move_caret_left(&mut self, engine: &EngInner) -> Result<()> {
+        if !self.can_move_caret(engine) {
+            return Ok(());
+        }
+
+        let stops = self.composition.caret_stops();
+        if let Some((caret, _)) =
+            stops.iter().rev().find(|(d, _)| *d < self.char_caret)
+        {
+            self.char_caret = *caret;
+        }
+
+        Ok(())
+    }
+
+    /// Moves the caret forward by one syllable, character, or converted
+    /// segment
+    pub fn move_caret_right(&mut self, engine: &EngInner) -> Result<()> {
+        if !self.can_move_caret(engine) {
+            return Ok(());
+        }
+
+        let stops = self.composition.caret_stops();
+        if let Some((caret, _)) =
+            stops.iter().find(|(d, _)| *d > self.char_caret)
+        {
+            self.char_caret = *caret;
+        }
+
+        Ok(())
+    }
+
+    pub fn move_caret_home(&mut self, engine: &EngInner) -> Result<()> {
+        if self.can_move_caret(engine) {
+            self.char_caret = 0;
+        }
+
+        Ok(())
+    }
+
+    pub fn move_caret_end(&mut self, engine: &EngInner) -> Result<()> {
+        if self.can_move_caret(engine) {
+            self.char_caret = self.composition.display_char_count();
+        }
+
+        Ok(())
+    }
+
+    /// Places the caret at the nearest position at or before `caret`, given
+    /// in chars of the preedit displayed text
+    pub fn place_caret(
+        &mut self,
+        engine: &EngInner,
+        caret: usize,
+    ) -> Result<()> {
+        if !self.can_move_caret(engine) {
+            return Ok(());
+        }
+
+        let raw_caret = self.composition.raw_caret_from(caret);
+        self.char_caret = self.composition.caret_from(raw_caret);
+        Ok(())
+    }
+
+    /// Deletes the raw char after the caret
+    pub fn erase_forward(&mut self, engine: &EngInner) -> Result<()> {
+        if !self.can_move_caret(engine) {
+            return Ok(());
+        }
+
+        let raw_caret = self.composition.raw_caret_from(self.char_caret);
+        if raw_caret >= self.composition.raw_char_count() {
+            return Ok(());
+        }
+
+        self.edit_state = EditState::ES_COMPOSING;
+        self.erase_raw_char_continuous(engine, raw_caret)
     }
 
     fn build_composition_continuous(
@@ -401,6 +696,7 @@ impl BufferMgr {
 
         self.composition = convert_all(engine, &composition)?;
         self.candidates = get_candidates(engine, &composition)?;
+        self.order_by_open_pair();
 
         debug!("Number of candidates found: {}", self.candidates.len());
 
@@ -454,6 +750,31 @@ impl BufferMgr {
         self.build_composition_classic(engine, raw_input, ch)
     }
 
+    /// Moves candidates that often follow the previously committed word to the
+    /// front, keeping the existing order otherwise
+    fn sort_by_pre_committed(
+        &self,
+        engine: &EngInner,
+        candidates: &mut [Buffer],
+    ) {
+        let lm = engine.dict.language_model();
+        let lgram = self
+            .pre_committed
+            .trim_matches(|c: char| c == '-' || c.is_whitespace());
+        if lm.is_empty() || lgram.is_empty() {
+            return;
+        }
+
+        candidates.sort_by_cached_key(|cand| {
+            let rgram: String = cand
+                .iter()
+                .filter_map(|elem| elem.candidate())
+                .map(|conv| conv.output.as_str())
+                .collect();
+            std::cmp::Reverse(lm.bigram_count(lgram, &rgram))
+        });
+    }
+
     fn attach_hypen_candicate(&mut self) {
         if self.candidates.is_empty() || self.pre_committed.is_empty() {
             return;
@@ -485,6 +806,15 @@ impl BufferMgr {
         mut raw_input: String,
         ch: char,
     ) -> Result<()> {
+        if raw_input.starts_with(EMOJI_TRIGGER) && ch.is_ascii_alphanumeric() {
+            raw_input.push(ch);
+            self.candidates = get_candidates(engine, &raw_input)?;
+            self.composition = Buffer::new();
+            self.composition.push(StringElem::from(raw_input).into());
+            self.char_caret = self.composition.display_char_count();
+            return Ok(());
+        }
+
         let mut key = ch.to_ascii_lowercase();
         if (key == engine.conf.hyphon()) {
             if engine.conf.khin_mode() == KhinMode::Hyphen {
@@ -571,85 +901,10 @@ impl BufferMgr {
             self.composition.push(StringElem::from(raw_input).into());
             self.char_caret = self.composition.display_char_count();
             return Ok(());
-        } else if (key == '.') {
-            if engine.conf.is_lomaji_first() {
-                raw_input.push('.');
-            } else {
-                raw_input.push('。');
-            }
-            self.composition = Buffer::new();
-            self.composition.push(StringElem::from(raw_input).into());
-            self.char_caret = self.composition.display_char_count();
-            self.edit_state = EditState::ES_EMPTY;
-            return Ok(());
-        } else if (key == '?') {
-            if engine.conf.is_lomaji_first() {
-                raw_input.push('?');
-            } else {
-                raw_input.push('？');
-            }
-            self.composition = Buffer::new();
-            self.composition.push(StringElem::from(raw_input).into());
-            self.char_caret = self.composition.display_char_count();
-            self.edit_state = EditState::ES_EMPTY;
-            return Ok(());
-        } else if (key == ',') {
-            if engine.conf.is_lomaji_first() {
-                raw_input.push(',');
-            } else {
-                raw_input.push('、');
-            }
-            self.composition = Buffer::new();
-            self.composition.push(StringElem::from(raw_input).into());
-            self.char_caret = self.composition.display_char_count();
-            self.edit_state = EditState::ES_EMPTY;
-            return Ok(());
-        } else if (key == '!') {
-            if engine.conf.is_lomaji_first() {
-                raw_input.push('!');
-            } else {
-                raw_input.push('！');
-            }
-            self.composition = Buffer::new();
-            self.composition.push(StringElem::from(raw_input).into());
-            self.char_caret = self.composition.display_char_count();
-            self.edit_state = EditState::ES_EMPTY;
-            return Ok(());
-        } else if (key == ';') {
-            if engine.conf.is_lomaji_first() {
-                raw_input.push(';');
-            } else {
-                raw_input.push('・');
-            }
-            self.composition = Buffer::new();
-            self.composition.push(StringElem::from(raw_input).into());
-            self.char_caret = self.composition.display_char_count();
-            self.edit_state = EditState::ES_EMPTY;
-            return Ok(());
-        } else if (key == '(') {
-            if engine.conf.is_lomaji_first() {
-                raw_input.push('(');
-            } else {
-                raw_input.push('（');
-            }
-            self.composition = Buffer::new();
-            self.composition.push(StringElem::from(raw_input).into());
-            self.char_caret = self.composition.display_char_count();
-            self.edit_state = EditState::ES_EMPTY;
-            return Ok(());
-        } else if (key == ')') {
-            if engine.conf.is_lomaji_first() {
-                raw_input.push(')');
-            } else {
-                raw_input.push('）');
-            }
-            self.composition = Buffer::new();
-            self.composition.push(StringElem::from(raw_input).into());
-            self.char_caret = self.composition.display_char_count();
-            self.edit_state = EditState::ES_EMPTY;
-            return Ok(());
-        } else if ("'\":=[]".contains(key) && engine.conf.is_lomaji_first()) {
-            raw_input.push(key);
+        } else if let Some(PunctOutput::Direct(text)) =
+            punct_output(&engine.conf, key)
+        {
+            raw_input.push_str(&text);
             self.composition = Buffer::new();
             self.composition.push(StringElem::from(raw_input).into());
             self.char_caret = self.composition.display_char_count();
@@ -705,10 +960,12 @@ impl BufferMgr {
             } else {
                 query = raw_input.clone();
                 query.push(ch);
-                if let Ok(candidates) = get_candidates_for_word(engine, &query)
+                if let Ok(mut candidates) =
+                    get_candidates_for_word(engine, &query)
                 {
                     if (!candidates.is_empty()) {
                         raw_input.push(ch);
+                        self.sort_by_pre_committed(engine, &mut candidates);
                         self.candidates = candidates;
                         self.composition = Buffer::new();
                         self.composition
@@ -756,62 +1013,33 @@ impl BufferMgr {
         // add punctuation
         // key = first char of query
         let key = query.chars().next().unwrap();
-        if query.starts_with(":") && engine.conf.is_hanji_first() {
+        if let Some(PunctOutput::Menu(items)) = punct_output(&engine.conf, key)
+        {
             self.candidates = Vec::new();
-            let mut buf = Buffer::new();
-            buf.push(
-                StringElem::from_raw_input(":".to_string(), "：".to_string())
-                    .into(),
-            );
-            self.candidates.push(buf);
-
-            buf = Buffer::new();
-            buf.push(
-                StringElem::from_raw_input(":".to_string(), "⋯⋯".to_string())
-                    .into(),
-            );
-            self.candidates.push(buf);
-        } else if "'\"<>+_".contains(key) && engine.conf.is_lomaji_first() {
-            let lomaji_chars = get_lomaji_chars(key).unwrap();
-            for lomaji_char in lomaji_chars {
-                let mut buf = Buffer::new();
-                buf.push(
-                    StringElem::from_raw_input(
-                        key.to_string(),
-                        lomaji_char.to_string(),
-                    )
-                    .into(),
-                );
-                self.candidates.push(buf);
-            }
-        } else if "'\"<>+_=[]".contains(key) && engine.conf.is_hanji_first() {
-            let hanji_chars = get_hanji_chars(key).unwrap();
-            for hanji_char in hanji_chars {
+            for item in items {
                 let mut buf = Buffer::new();
                 buf.push(
-                    StringElem::from_raw_input(
-                        key.to_string(),
-                        hanji_char.to_string(),
-                    )
-                    .into(),
+                    StringElem::from_raw_input(key.to_string(), item).into(),
                 );
                 self.candidates.push(buf);
             }
+            self.order_by_open_pair();
         } else {
             let size = query.chars().count();
             let mut found = false;
             for i in (0..size).rev() {
                 let end = i + 1;
                 let substr = &query[0..end];
-                // substr ends "'\"<>+_=[]"
-                if substr.ends_with(|c: char| "'\":<>+_=[]".contains(c)) {
+                if substr.ends_with(is_punctuation_key) {
                     break;
                 }
-                if let Ok(candidates) = get_candidates_for_word(engine, substr)
+                if let Ok(mut candidates) =
+                    get_candidates_for_word(engine, substr)
                 {
                     if (!candidates.is_empty()) {
                         if !found {
                             found = true;
+                            self.sort_by_pre_committed(engine, &mut candidates);
                             self.candidates = candidates;
                         } else if self.cand_expanded {
                             // expand candidate
@@ -995,6 +1223,29 @@ impl BufferMgr {
         Ok(())
     }
 
+    /// Focuses a candidate by its id in the candidate list, being its index
+    /// across all pages
+    pub fn focus_candidate_by_id(
+        &mut self,
+        engine: &EngInner,
+        id: usize,
+    ) -> Result<()> {
+        if id >= self.candidates.len() {
+            return Err(CommandError::invalid_request(format!(
+                "Unknown candidate {}",
+                id
+            ))
+            .into());
+        }
+
+        if self.edit_state == EditState::ES_COMPOSING {
+            self.edit_state = EditState::ES_SELECTING;
+        }
+
+        self.cand_page = id / 9;
+        self.focus_candidate_by_index(engine, id % 9)
+    }
+
     pub fn focus_next_candidate(&mut self, engine: &EngInner) -> Result<()> {
         if self.edit_state == EditState::ES_COMPOSING {
             self.edit_state = EditState::ES_SELECTING;
@@ -1086,9 +1337,11 @@ impl BufferMgr {
     // displayed in the preedit.
     //
     // Steps:
-    // 1. Get the raw text from the candidate
-    // 2. Get the remaining text after removing the candidate from the current
-    //    composition
+    // 1. Keep the segments before the focused segment
+    // 2. Get the raw text from the candidate
+    // 3. Get the remaining text after removing the candidate from the current
+    //    composition. If the candidate replaces exactly the focused segment,
+    //    the following segments are kept as they are.
     // 4. Make a new composition
     // 5. Add the candidate into the composition
     // 6. Auto-split the remaining text into a Buffer
@@ -1101,6 +1354,7 @@ impl BufferMgr {
         if (engine.conf.input_mode() == InputMode::Classic) {
             return self.focus_candidate_classic(engine, index);
         }
+        let segment = self.focused_segment();
         self.composition.clear_autospace();
         let candidate = self
             .candidates
@@ -1108,14 +1362,25 @@ impl BufferMgr {
             .ok_or(anyhow!("Candidate index out of bounds"))?
             .clone();
 
-        let cand_raw_count = candidate.raw_char_count();
-        let comp_raw = self.composition.raw_text();
-        let mut remainder =
-            comp_raw.char_substr(cand_raw_count, comp_raw.chars().count());
+        let mut new_comp = self.composition.clone();
+        let mut following = new_comp.split_off(segment.min(new_comp.len()));
+        let focused_raw_count =
+            following.first().map_or(0, |elem| elem.raw_char_count());
 
-        let mut new_comp = candidate;
-        let remainder_split = convert_all(engine, &remainder)?;
+        let cand_raw_count = candidate.raw_char_count();
+        let remainder_split =
+            if !following.is_empty() && cand_raw_count == focused_raw_count {
+                following.split_off(1)
+            } else {
+                let comp_raw = self.composition.raw_text();
+                let remainder = comp_raw.char_substr(
+                    new_comp.raw_char_count() + cand_raw_count,
+                    comp_raw.chars().count(),
+                );
+                convert_all(engine, &remainder)?
+            };
 
+        new_comp.extend(candidate);
         new_comp.extend(remainder_split);
 
         self.composition = new_comp;
@@ -1123,11 +1388,195 @@ impl BufferMgr {
         self.focused_cand_idx = Some(index);
         self.cand_page = index / 9;
         self.composition.autospace();
+        self.focused_elem_idx = self.composition_index(segment);
+        self.char_caret = self.composition.display_char_count();
+
+        Ok(())
+    }
+
+    /// Index of the focused element, not counting spacers
+    fn focused_segment(&self) -> usize {
+        self.composition
+            .iter()
+            .take(self.focused_elem_idx)
+            .filter(|elem| !matches!(elem, BufferElementEnum::Spacer(_)))
+            .count()
+    }
+
+    /// Index in the composition of a segment, skipping over spacers
+    fn composition_index(&self, segment: usize) -> usize {
+        self.composition
+            .iter()
+            .enumerate()
+            .filter(|(_, elem)| !matches!(elem, BufferElementEnum::Spacer(_)))
+            .nth(segment)
+            .map_or(0, |(i, _)| i)
+    }
+
+    /// Segments can be focused in continuous mode once a candidate has been
+    /// focused, which converts the composition
+    fn can_focus_segments(&self, engine: &EngInner) -> bool {
+        engine.conf.input_mode() == InputMode::Continuous && self.is_focused()
+    }
+
+    pub fn focus_prev_segment(&mut self, engine: &EngInner) -> Result<()> {
+        if !self.can_focus_segments(engine) {
+            return Ok(());
+        }
+
+        let segment = self.focused_segment();
+        if segment > 0 {
+            self.focus_segment(engine, segment - 1)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn focused_segment_is_last(&self) -> bool {
+        let count = self
+            .composition
+            .iter()
+            .filter(|elem| !matches!(elem, BufferElementEnum::Spacer(_)))
+            .count();
+        self.focused_segment() + 1 >= count
+    }
+
+    pub fn focus_next_segment(&mut self, engine: &EngInner) -> Result<()> {
+        if !self.can_focus_segments(engine) {
+            return Ok(());
+        }
+
+        if !self.focused_segment_is_last() {
+            self.focus_segment(engine, self.focused_segment() + 1)?;
+        }
+
+        Ok(())
+    }
+
+    /// Moves the end of the focused segment one syllable to the right
+    pub fn grow_focused_segment(&mut self, engine: &EngInner) -> Result<()> {
+        self.resize_focused_segment(engine, true)
+    }
+
+    /// Moves the end of the focused segment one syllable to the left
+    pub fn shrink_focused_segment(&mut self, engine: &EngInner) -> Result<()> {
+        self.resize_focused_segment(engine, false)
+    }
+
+    /// Converts the whole composition, focuses one of its segments, and lists
+    /// the alternatives that cover exactly that segment
+    fn focus_segment(
+        &mut self,
+        engine: &EngInner,
+        segment: usize,
+    ) -> Result<()> {
+        self.composition.clear_autospace();
+        self.composition.set_converted(true);
+
+        let start = self.composition[..segment]
+            .iter()
+            .fold(0, |acc, elem| acc + elem.raw_char_count());
+        let elem = self
+            .composition
+            .get(segment)
+            .ok_or(anyhow!("Segment index out of bounds"))?
+            .clone();
+        let comp_raw = self.composition.raw_text();
+        let query = comp_raw.char_substr(start, comp_raw.chars().count());
+
+        let mut candidates = get_candidates(engine, &query)?;
+        let len = elem.raw_char_count();
+        candidates.retain(|cand| cand.raw_char_count() == len);
+
+        let current: Buffer = elem.into();
+        let index = match candidates.iter().position(|c| c.eq_display(&current))
+        {
+            Some(i) => i,
+            None => {
+                candidates.insert(0, current);
+                0
+            },
+        };
+
+        self.candidates = candidates;
+        for c in self.candidates.iter_mut() {
+            c.set_converted(true);
+        }
+
+        self.edit_state = EditState::ES_SELECTING;
+        self.focused_cand_idx = Some(index);
+        self.cand_page = index / 9;
+        self.composition.autospace();
+        self.focused_elem_idx = self.composition_index(segment);
         self.char_caret = self.composition.display_char_count();
 
         Ok(())
     }
 
+    fn resize_focused_segment(
+        &mut self,
+        engine: &EngInner,
+        grow: bool,
+    ) -> Result<()> {
+        if !self.can_focus_segments(engine) {
+            return Ok(());
+        }
+
+        let segment = self.focused_segment();
+        self.composition.clear_autospace();
+
+        // Syllable boundaries, as raw char offsets into an element
+        let syllable_ends = |elem: &BufferElementEnum| -> Vec<usize> {
+            let mut elem = elem.clone();
+            elem.set_converted(false);
+            elem.caret_stops().into_iter().map(|(_, raw)| raw).collect()
+        };
+
+        let focused = &self.composition[segment];
+        let len = focused.raw_char_count();
+        let new_len = if grow {
+            match self.composition.get(segment + 1) {
+                Some(next) => syllable_ends(next)
+                    .into_iter()
+                    .find(|raw| *raw > 0)
+                    .map(|raw| len + raw),
+                None => None,
+            }
+        } else {
+            syllable_ends(focused)
+                .into_iter()
+                .rev()
+                .find(|raw| *raw > 0 && *raw < len)
+        };
+
+        let new_len = match new_len {
+            Some(new_len) => new_len,
+            None => {
+                self.composition.autospace();
+                return Ok(());
+            },
+        };
+
+        let mut new_comp = self.composition.clone();
+        new_comp.truncate(segment);
+        let start = new_comp.raw_char_count();
+        let comp_raw = self.composition.raw_text();
+        let resized = comp_raw.char_substr(start, start + new_len);
+        let following =
+            comp_raw.char_substr(start + new_len, comp_raw.chars().count());
+
+        let mut candidates = get_candidates(engine, &resized)?;
+        candidates.retain(|cand| cand.raw_char_count() == new_len);
+        match candidates.into_iter().next() {
+            Some(cand) => new_comp.extend(cand),
+            None => new_comp.extend(convert_all(engine, &resized)?),
+        }
+        new_comp.extend(convert_all(engine, &following)?);
+
+        self.composition = new_comp;
+        self.focus_segment(engine, segment)
+    }
+
     fn focus_candidate_classic(
         &mut self,
         engine: &EngInner,
@@ -1282,6 +1731,9 @@ mod tests {
     use khiin_ji::contains_hanji;
 
     use super::*;
+    use crate::config::KeyConfig;
+    use crate::config::OutputMode;
+    use crate::config::ToneMode;
     use crate::tests::*;
     use crate::utils::Unique;
 
@@ -1307,6 +1759,159 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn it_suggests_and_records_emojis() -> Result<()> {
+        let (e, mut buf) = test_harness();
+        for ch in ":chhio".chars() {
+            buf.insert_continuous(&e, ch)?;
+        }
+        assert!(buf.candidates.iter().any(|c| c.display_text() == "😀"));
+        let index = buf
+            .candidates
+            .iter()
+            .position(|c| c.display_text() == "😀")
+            .unwrap();
+        buf.focus_candidate(&e, index)?;
+        let committed = buf.commit_all(&e)?;
+        assert_eq!(committed, "😀");
+        assert_eq!(e.db.user_data().select_recent_emojis(1)?, vec!["😀"]);
+        Ok(())
+    }
+
+    #[test]
+    fn it_opens_punctuation_menus_with_pairs() -> Result<()> {
+        let (mut e, mut buf) = test_harness();
+        e.conf.set_input_mode(InputMode::Classic);
+        e.conf.set_output_mode(OutputMode::Hanji);
+        buf.insert(&e, '\'')?;
+        assert_eq!(buf.edit_state, EditState::ES_COMPOSING);
+        assert_eq!(buf.candidates[0].display_text(), "「");
+        buf.focus_candidate(&e, 0)?;
+        let committed = buf.commit_all(&e)?;
+        buf.track_punct_pairs(&committed);
+        buf.reset()?;
+        buf.insert(&e, '\'')?;
+        assert_eq!(buf.candidates[0].display_text(), "」");
+        buf.track_punct_pairs("」");
+        buf.reset()?;
+        buf.insert(&e, '\'')?;
+        assert_eq!(buf.candidates[0].display_text(), "「");
+        Ok(())
+    }
+
+    #[test]
+    fn it_commits_direct_punctuation() -> Result<()> {
+        let (mut e, mut buf) = test_harness();
+        e.conf.set_input_mode(InputMode::Classic);
+        buf.insert(&e, ';')?;
+        assert_eq!(buf.edit_state, EditState::ES_EMPTY);
+        assert_eq!(buf.commit_all(&e)?, ";");
+        buf.reset()?;
+        e.conf.set_input_mode(InputMode::Continuous);
+        e.conf.set_output_mode(OutputMode::Hanji);
+        buf.insert(&e, 'a')?;
+        buf.insert(&e, '.')?;
+        assert_eq!(buf.edit_state, EditState::ES_EMPTY);
+        assert!(buf.commit_direct(&e)?.ends_with("。"));
+        Ok(())
+    }
+
+    #[test]
+    fn it_commits_the_raw_text_without_a_focused_candidate() -> Result<()> {
+        let (mut e, mut buf) = test_harness();
+        e.conf.set_input_mode(InputMode::Continuous);
+        for ch in "ho2".chars() {
+            buf.insert(&e, ch)?;
+        }
+        assert_ne!(buf.composition.display_text(), "ho2");
+        assert_eq!(buf.commit_all(&e)?, "ho2");
+        Ok(())
+    }
+
+    #[test]
+    fn it_uses_configured_keys_in_continuous_mode() -> Result<()> {
+        let (mut e, _) = test_harness();
+        e.conf.set_input_mode(InputMode::Continuous);
+        e.conf.set_tone_mode(ToneMode::Telex);
+        let dict = Dictionary::new(e.db.as_ref(), ToneMode::Telex)?;
+        e.dict = std::sync::Arc::new(dict);
+        e.conf.set_key_config(KeyConfig {
+            t2: 'z',
+            nasal: 'q',
+            dots_below: Some('y'),
+            ..Default::default()
+        })?;
+
+        let typed = |input: &str| -> Result<(String, Vec<String>)> {
+            let mut buf = BufferMgr::new();
+            for ch in input.chars() {
+                buf.insert(&e, ch)?;
+            }
+            let candidates =
+                buf.candidates.iter().map(|c| c.display_text()).collect();
+            Ok((buf.raw_text(), candidates))
+        };
+        assert!(!typed("hoz")?.1.is_empty());
+        assert_eq!(typed("hoz")?, typed("hos")?);
+        assert_eq!(typed("saq")?, typed("sann")?);
+        assert_eq!(to_dictionary_keys(&e, Some('O'), 'Y'), (1, "EO".into()));
+        assert_eq!(to_dictionary_keys(&e, Some(':'), 'z'), (0, "z".into()));
+        Ok(())
+    }
+
+    #[test]
+    fn it_focuses_and_resizes_segments() -> Result<()> {
+        let (mut e, mut buf) = test_harness();
+        e.conf.set_input_mode(InputMode::Continuous);
+        for ch in "lihopengan".chars() {
+            buf.insert(&e, ch)?;
+        }
+        buf.focus_next_candidate(&e)?;
+        assert_eq!(buf.focused_segment(), 0);
+        buf.focus_next_segment(&e)?;
+        assert_eq!(buf.focused_segment(), 1);
+        assert_eq!(buf.edit_state, EditState::ES_SELECTING);
+        assert!(buf.candidates.iter().all(|c| c.raw_char_count() == 6));
+        let segment_raw_count = |buf: &BufferMgr| {
+            buf.composition[buf.focused_elem_idx].raw_char_count()
+        };
+        buf.shrink_focused_segment(&e)?;
+        assert_eq!(segment_raw_count(&buf), 4);
+        assert_eq!(buf.composition.raw_text(), "lihopengan");
+        buf.grow_focused_segment(&e)?;
+        assert_eq!(segment_raw_count(&buf), 6);
+        buf.focus_prev_segment(&e)?;
+        assert_eq!(buf.focused_segment(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn it_edits_at_the_caret() -> Result<()> {
+        let (mut e, mut buf) = test_harness();
+        e.conf.set_input_mode(InputMode::Continuous);
+        for ch in "zzz".chars() {
+            buf.insert(&e, ch)?;
+        }
+        assert_eq!(buf.char_caret, 3);
+        buf.move_caret_left(&e)?;
+        assert_eq!(buf.char_caret, 2);
+        buf.insert(&e, 'q')?;
+        assert_eq!(buf.composition.raw_text(), "zzqz");
+        assert_eq!(buf.char_caret, 3);
+        buf.move_caret_home(&e)?;
+        buf.erase_forward(&e)?;
+        assert_eq!(buf.composition.raw_text(), "zqz");
+        assert_eq!(buf.char_caret, 0);
+        buf.pop(&e)?;
+        assert_eq!(buf.composition.raw_text(), "zqz");
+        buf.move_caret_right(&e)?;
+        buf.pop(&e)?;
+        assert_eq!(buf.composition.raw_text(), "qz");
+        buf.move_caret_end(&e)?;
+        assert_eq!(buf.char_caret, 2);
+        Ok(())
+    }
+
     #[test]
     fn it_focuses_the_first_candidate() -> Result<()> {
         let (e, mut buf) = test_harness();
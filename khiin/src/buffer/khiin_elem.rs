@@ -2,6 +2,8 @@ use anyhow::anyhow;
 use anyhow::Result;
 use regex::Regex;
 
+use crate::buffer::buffer_element::caret_from_stops;
+use crate::buffer::buffer_element::raw_caret_from_stops;
 use crate::buffer::BufferElement;
 use crate::db::models::KeyConversion;
 use crate::input::Syllable;
@@ -109,7 +111,7 @@ impl KhiinElem {
     }
 
     fn raw_caret_from_composed(&self, caret: usize) -> usize {
-        0
+        raw_caret_from_stops(&self.caret_stops(), caret)
     }
 
     // converted 平安
@@ -229,7 +231,32 @@ impl BufferElement for KhiinElem {
     }
 
     fn caret_from(&self, raw_caret: usize) -> usize {
-        todo!()
+        caret_from_stops(&self.caret_stops(), raw_caret)
+    }
+
+    // While composing, the caret moves between syllables. Once converted,
+    // the element is a single segment.
+    fn caret_stops(&self) -> Vec<(usize, usize)> {
+        if self.converted {
+            return vec![(self.display_char_count(), self.raw_char_count())];
+        }
+
+        let mut stops = Vec::new();
+        let mut display = 0;
+        let mut raw = 0;
+
+        for elem in self.value.iter() {
+            match elem {
+                Khiin::Syllable(s) => {
+                    display += s.compose().chars().count();
+                    raw += s.raw_input.chars().count();
+                },
+                Khiin::Separator(_) => display += 1,
+            }
+            stops.push((display, raw));
+        }
+
+        stops
     }
 
     fn set_converted(&mut self, converted: bool) {
@@ -318,5 +345,12 @@ mod test {
     fn it_gets_raw_caret_from_composed() {
         let c = mock_conversion("hó bô", "好無");
         let elem = KhiinElem::from_conversion("hobo", &c).unwrap();
+        assert_eq!(elem.caret_stops(), vec![(2, 2), (3, 2), (5, 4)]);
+        assert_eq!(elem.raw_caret_from_composed(0), 0);
+        assert_eq!(elem.raw_caret_from_composed(1), 0);
+        assert_eq!(elem.raw_caret_from_composed(3), 2);
+        assert_eq!(elem.raw_caret_from_composed(5), 4);
+        assert_eq!(elem.caret_from(2), 2);
+        assert_eq!(elem.caret_from(3), 5);
     }
 }
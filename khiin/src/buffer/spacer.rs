@@ -57,6 +57,10 @@ impl BufferElement for Spacer {
         raw_caret
     }
 
+    fn caret_stops(&self) -> Vec<(usize, usize)> {
+        vec![(self.display_char_count(), 0)]
+    }
+
     fn set_converted(&mut self, converted: bool) {
         self.converted = converted;
     }
@@ -93,6 +93,18 @@ impl BufferElement for StringElem {
         raw_caret
     }
 
+    // Plain text moves one char at a time, unless the displayed text differs
+    // from the keys typed (e.g. punctuation), which is kept whole
+    fn caret_stops(&self) -> Vec<(usize, usize)> {
+        let raw_count = self.raw_char_count();
+        let display_count = self.display_char_count();
+        if raw_count == display_count {
+            (1..=raw_count).map(|i| (i, i)).collect()
+        } else {
+            vec![(display_count, raw_count)]
+        }
+    }
+
     fn is_converted(&self) -> bool {
         self.converted
     }
@@ -2,6 +2,9 @@ pub(crate) mod conf;
 
 pub(crate) use conf::Config;
 pub(crate) use conf::InputMode;
+pub(crate) use conf::KeyConfig;
 pub(crate) use conf::ToneMode;
 pub(crate) use conf::OutputMode;
-pub(crate) use conf::KhinMode;
\ No newline at end of file
+pub(crate) use conf::KhinMode;
+pub(crate) use conf::PunctWidth;
+pub(crate) use conf::NUMERIC_TONES;
\ No newline at end of file
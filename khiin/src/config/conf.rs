@@ -1,3 +1,6 @@
+use anyhow::anyhow;
+use anyhow::Result;
+
 use crate::db::models::InputType;
 
 #[derive(PartialEq, Copy, Clone)]
@@ -26,6 +29,15 @@ pub enum KhinMode {
     Dot,
 }
 
+/// Width of direct output punctuation. `Auto` follows the output mode:
+/// full width for Hanji, half width for Lomaji.
+#[derive(PartialEq, Copy, Clone)]
+pub enum PunctWidth {
+    Auto,
+    Half,
+    Whole,
+}
+
 impl Into<InputType> for ToneMode {
     fn into(self) -> InputType {
         match self {
@@ -35,6 +47,14 @@ impl Into<InputType> for ToneMode {
     }
 }
 
+/// Digits that are tone keys in Numeric mode
+pub const NUMERIC_TONES: &str = "2356789";
+
+/// Letters that spell syllable bodies, which cannot double as tone, khin,
+/// hyphen or done keys
+const BODY_LETTERS: &str = "aeioumngptkh";
+
+#[derive(Debug, PartialEq, Copy, Clone)]
 pub struct KeyConfig {
     pub t2: char,
     pub t3: char,
@@ -46,13 +66,107 @@ pub struct KeyConfig {
     pub khin: char,
     pub hyphon: char,
     pub done: char,
+    /// Typed after a syllable to add ⁿ, `n` for "nn"
+    pub nasal: char,
+    /// Typed after `o` to make o͘, `u` for "ou". "oo" is always accepted.
+    pub dot_above_right: char,
+    /// Typed after `o` or `u` to make o̤ or ṳ. Leading "e" as in "eo" is
+    /// always accepted.
+    pub dots_below: Option<char>,
+    /// Whether "NN" composes to ᴺ rather than ⁿ
+    pub uppercase_nasal: bool,
+    /// Whether digits still work as tone keys in Telex mode
+    pub digits_as_fallback: bool,
+}
+
+impl Default for KeyConfig {
+    fn default() -> Self {
+        Self {
+            t2: 's',
+            t3: 'f',
+            t5: 'l',
+            t6: 'x',
+            t7: 'j',
+            t8: 'j',
+            t9: 'w',
+            khin: 'v',
+            hyphon: 'd',
+            done: 'r',
+            nasal: 'n',
+            dot_above_right: 'u',
+            dots_below: None,
+            uppercase_nasal: true,
+            digits_as_fallback: false,
+        }
+    }
 }
+
+impl KeyConfig {
+    /// Checks that no key has two roles and that no role takes a letter
+    /// needed to spell syllables. The T8 key may be shared with another
+    /// tone, the tone is then picked from the syllable ending.
+    pub fn validate(&self) -> Result<()> {
+        let tones = [self.t2, self.t3, self.t5, self.t6, self.t7, self.t9];
+        // (role, key, body letters the role is allowed to reuse)
+        let mut roles = vec![
+            ("t2", self.t2, ""),
+            ("t3", self.t3, ""),
+            ("t5", self.t5, ""),
+            ("t6", self.t6, ""),
+            ("t7", self.t7, ""),
+            ("t9", self.t9, ""),
+            ("khin", self.khin, ""),
+            ("hyphen", self.hyphon, ""),
+            ("done", self.done, ""),
+            ("nasal", self.nasal, "n"),
+            ("dot above right", self.dot_above_right, "uo"),
+        ];
+        if !tones.contains(&self.t8) {
+            roles.push(("t8", self.t8, ""));
+        }
+        if let Some(key) = self.dots_below {
+            roles.push(("dots below", key, ""));
+        }
+
+        for (i, &(role, key, allowed)) in roles.iter().enumerate() {
+            if !key.is_ascii_graphic() || key.is_ascii_uppercase() {
+                return Err(anyhow!("Invalid {} key: {:?}", role, key));
+            }
+            if BODY_LETTERS.contains(key) && !allowed.contains(key) {
+                return Err(anyhow!(
+                    "The {} key {:?} is needed to spell syllables",
+                    role,
+                    key
+                ));
+            }
+            if self.digits_as_fallback && key.is_ascii_digit() {
+                return Err(anyhow!(
+                    "The {} key {:?} is needed as a fallback tone key",
+                    role,
+                    key
+                ));
+            }
+            if let Some(other) = roles[..i].iter().find(|r| r.1 == key) {
+                return Err(anyhow!(
+                    "The {} and {} keys are both {:?}",
+                    other.0,
+                    role,
+                    key
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
 pub struct Config {
     enabled: bool,
     input_mode: InputMode,
     tone_mode: ToneMode,
     output_mode: OutputMode,
     khin_mode: KhinMode,
+    punct_width: PunctWidth,
     key_config: KeyConfig,
 }
 
@@ -64,18 +178,8 @@ impl Config {
             tone_mode: ToneMode::Telex,
             output_mode: OutputMode::Lomaji,
             khin_mode: KhinMode::Hyphen,
-            key_config: KeyConfig {
-                t2: 's',
-                t3: 'f',
-                t5: 'l',
-                t6: 'x',
-                t7: 'j',
-                t8: 'j',
-                t9: 'w',
-                khin: 'v',
-                hyphon: 'd',
-                done: 'r',
-            },
+            punct_width: PunctWidth::Auto,
+            key_config: KeyConfig::default(),
         }
     }
 
@@ -95,6 +199,10 @@ impl Config {
         self.khin_mode
     }
 
+    pub fn punct_width(&self) -> PunctWidth {
+        self.punct_width
+    }
+
     pub fn t2(&self) -> char {
         if self.tone_mode == ToneMode::Numeric {
             '2'
@@ -163,6 +271,30 @@ impl Config {
         self.key_config.done
     }
 
+    pub fn nasal(&self) -> char {
+        self.key_config.nasal
+    }
+
+    pub fn dot_above_right(&self) -> char {
+        self.key_config.dot_above_right
+    }
+
+    pub fn dots_below(&self) -> Option<char> {
+        self.key_config.dots_below
+    }
+
+    pub fn uppercase_nasal(&self) -> bool {
+        self.key_config.uppercase_nasal
+    }
+
+    pub fn digits_as_fallback(&self) -> bool {
+        self.key_config.digits_as_fallback
+    }
+
+    pub fn key_config(&self) -> &KeyConfig {
+        &self.key_config
+    }
+
     pub fn is_reserved_char(&self, ch: char) -> bool {
         if ch == self.key_config.khin {
             true
@@ -205,7 +337,7 @@ impl Config {
         } else if ch == self.t9() {
             true
         } else {
-            false
+            self.digits_as_fallback() && NUMERIC_TONES.contains(ch)
         }
     }
 
@@ -240,4 +372,44 @@ impl Config {
     pub fn set_khin_mode(&mut self, mode: KhinMode) {
         self.khin_mode = mode;
     }
+
+    // set punct_width
+    pub fn set_punct_width(&mut self, width: PunctWidth) {
+        self.punct_width = width;
+    }
+
+    // set key_config, leaving the current one in place if invalid
+    pub fn set_key_config(&mut self, key_config: KeyConfig) -> Result<()> {
+        key_config.validate()?;
+        self.key_config = key_config;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_validates_key_configs() {
+        let valid = |keys: KeyConfig| keys.validate().is_ok();
+        let defaults = KeyConfig::default();
+        assert!(valid(defaults));
+        assert!(valid(KeyConfig { t8: 's', ..defaults }));
+        assert!(valid(KeyConfig { nasal: 'q', ..defaults }));
+        assert!(valid(KeyConfig { dot_above_right: 'o', ..defaults }));
+        assert!(!valid(KeyConfig { t3: 's', ..defaults }));
+        assert!(!valid(KeyConfig { khin: 'w', ..defaults }));
+        assert!(!valid(KeyConfig { t2: 'a', ..defaults }));
+        assert!(!valid(KeyConfig { t8: 'h', ..defaults }));
+        assert!(!valid(KeyConfig { nasal: 'm', ..defaults }));
+        assert!(!valid(KeyConfig { dots_below: Some('d'), ..defaults }));
+        assert!(!valid(KeyConfig { t2: 'S', ..defaults }));
+        assert!(valid(KeyConfig { t2: '2', ..defaults }));
+        assert!(!valid(KeyConfig {
+            t2: '2',
+            digits_as_fallback: true,
+            ..defaults
+        }));
+    }
 }
@@ -1,9 +1,15 @@
+pub(crate) mod compiled_index;
 pub(crate) mod dictionary;
+pub(crate) mod language_model;
 pub(crate) mod segmenter;
 pub(crate) mod trie;
 pub(crate) mod syllable_trie;
 
+pub use compiled_index::CompiledIndex;
+pub(crate) use compiled_index::CompiledWords;
 pub(crate) use dictionary::Dictionary;
+pub use dictionary::SharedDictionary;
+pub(crate) use language_model::LanguageModel;
 pub(crate) use segmenter::Segmenter;
 pub(crate) use trie::Trie;
 pub(crate) use syllable_trie::SyllableTrie;
\ No newline at end of file
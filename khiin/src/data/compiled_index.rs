@@ -0,0 +1,635 @@
+use std::collections::BTreeMap;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::Write;
+use std::ops::Range;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use anyhow::Result;
+use fst::Automaton;
+use fst::IntoStreamer;
+use fst::Map;
+use fst::MapBuilder;
+use fst::Streamer;
+#[cfg(not(target_arch = "wasm32"))]
+use memmap2::Mmap;
+
+use crate::data::language_model::bigram_key;
+use crate::data::LanguageModel;
+use crate::data::Segmenter;
+use crate::db::models::Bigram;
+use crate::db::models::InputType;
+use crate::db::DictionaryBackend;
+
+const MAGIC: &[u8; 4] = b"KHIX";
+const VERSION: u32 = 2;
+
+/// Extension of the index file written next to the database by `dbgen`
+pub const INDEX_EXTENSION: &str = "idx";
+
+/// Input types with a section in the index, one per `ToneMode`
+const SECTIONS: [InputType; 2] = [InputType::Numeric, InputType::Telex];
+
+const HEADER_LEN: usize = 36;
+const SECTION_LEN: usize = 56;
+
+/// The index for a database file, e.g. `khiin.idx` for `khiin.db`
+pub fn index_path<P>(db_filename: P) -> PathBuf
+where
+    P: AsRef<Path>,
+{
+    db_filename.as_ref().with_extension(INDEX_EXTENSION)
+}
+
+/// The word list and bigram counts of a dictionary, precompiled by `dbgen`
+/// so that they can be memory-mapped instead of being loaded from the
+/// database at startup.
+///
+/// The file starts with a header and a table of sections, one for each input
+/// type. The header holds an FST of the output bigram counts. Each section
+/// holds an FST mapping key sequences to offsets in a block of values, where
+/// each value is the segmentation cost of the key sequence followed by the
+/// ids of its inputs, and an FST of the key sequence bigram counts. Bigrams
+/// are keyed by `lgram`, a NUL byte, and `rgram`. All numbers are
+/// little-endian.
+///
+/// ```text
+/// header:  magic "KHIX", version u32, built_at i64,
+///          bigrams (offset u64, len u64), n_sections u32
+/// section: input_type u32, max_word_length u32,
+///          keys (offset u64, len u64), values (offset u64, len u64),
+///          key_bigrams (offset u64, len u64)
+/// value:   cost f64, n_ids u32, input_id u32 * n_ids
+/// ```
+#[derive(Clone)]
+pub struct CompiledIndex {
+    data: Arc<IndexData>,
+    built_at: i64,
+    bigrams: Range<usize>,
+    sections: Vec<Section>,
+}
+
+enum IndexData {
+    Owned(Vec<u8>),
+    #[cfg(not(target_arch = "wasm32"))]
+    Mapped(Mmap),
+}
+
+impl AsRef<[u8]> for IndexData {
+    fn as_ref(&self) -> &[u8] {
+        match self {
+            IndexData::Owned(bytes) => bytes.as_slice(),
+            #[cfg(not(target_arch = "wasm32"))]
+            IndexData::Mapped(mmap) => &mmap[..],
+        }
+    }
+}
+
+/// A range of the index data, which the FSTs read from directly
+#[derive(Clone)]
+pub(crate) struct IndexBytes {
+    data: Arc<IndexData>,
+    range: Range<usize>,
+}
+
+impl AsRef<[u8]> for IndexBytes {
+    fn as_ref(&self) -> &[u8] {
+        let bytes: &[u8] = (*self.data).as_ref();
+        &bytes[self.range.clone()]
+    }
+}
+
+#[derive(Clone)]
+struct Section {
+    input_type: InputType,
+    max_word_length: usize,
+    keys: Range<usize>,
+    values: Range<usize>,
+    key_bigrams: Range<usize>,
+}
+
+impl CompiledIndex {
+    /// Compiles the word lists and bigram counts of `db` into the bytes of
+    /// an index file
+    pub fn build(db: &dyn DictionaryBackend) -> Result<Vec<u8>> {
+        let built_at = built_at(db)?;
+        let bigrams = build_bigrams(db.select_bigrams()?)?;
+        let mut blocks = Vec::new();
+
+        for input_type in SECTIONS {
+            blocks.push(build_section(db, input_type)?);
+        }
+
+        let mut offset = HEADER_LEN + SECTION_LEN * blocks.len();
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&built_at.to_le_bytes());
+        push_range(&mut bytes, offset, bigrams.len());
+        bytes.extend_from_slice(&(blocks.len() as u32).to_le_bytes());
+        offset += bigrams.len();
+
+        for (input_type, block) in SECTIONS.iter().zip(blocks.iter()) {
+            bytes.extend_from_slice(&(*input_type as u32).to_le_bytes());
+            bytes.extend_from_slice(&block.max_word_length.to_le_bytes());
+            for part in [&block.keys, &block.values, &block.key_bigrams] {
+                push_range(&mut bytes, offset, part.len());
+                offset += part.len();
+            }
+        }
+
+        bytes.extend(bigrams);
+        for block in blocks {
+            bytes.extend(block.keys);
+            bytes.extend(block.values);
+            bytes.extend(block.key_bigrams);
+        }
+
+        Ok(bytes)
+    }
+
+    /// Compiles the word lists of `db` into the index file `filename`
+    pub fn write<P>(db: &dyn DictionaryBackend, filename: P) -> Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let bytes = Self::build(db)?;
+        let mut file = File::create(filename)?;
+        file.write_all(&bytes)?;
+        Ok(())
+    }
+
+    /// Maps the index file into memory, without reading it
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn open<P>(filename: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let file = File::open(filename)?;
+        // Safety: the index is only ever written by `dbgen`, and never
+        // modified while the engine is running.
+        let mmap = unsafe { Mmap::map(&file)? };
+        Self::from_data(IndexData::Mapped(mmap))
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub fn open<P>(filename: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        Self::from_bytes(std::fs::read(filename)?)
+    }
+
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self> {
+        Self::from_data(IndexData::Owned(bytes))
+    }
+
+    /// Opens the index next to the database file `db_filename`, if there
+    /// is one and it was compiled from the same build of the database
+    pub fn find<P>(db_filename: P, db: &dyn DictionaryBackend) -> Option<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let filename = index_path(db_filename);
+        if !filename.exists() {
+            return None;
+        }
+
+        let index = match Self::open(&filename) {
+            Ok(index) => index,
+            Err(e) => {
+                log::error!("Unable to open index {:?}: {}", filename, e);
+                return None;
+            },
+        };
+
+        match built_at(db) {
+            Ok(built_at) if built_at == index.built_at => {
+                log::debug!("Index loaded from: {:?}", filename);
+                Some(index)
+            },
+            Ok(_) => {
+                log::warn!("Index {:?} does not match the database", filename);
+                None
+            },
+            Err(e) => {
+                log::error!("Unable to read database metadata: {}", e);
+                None
+            },
+        }
+    }
+
+    /// The `built_at` metadata of the database the index was compiled from
+    pub fn built_at(&self) -> i64 {
+        self.built_at
+    }
+
+    pub(crate) fn words(&self, input_type: InputType) -> Result<CompiledWords> {
+        let section = self.section(input_type)?;
+        let keys = Map::new(self.bytes(section.keys.clone()))?;
+        let values = self.bytes(section.values.clone());
+
+        Ok(CompiledWords {
+            keys,
+            values,
+            max_word_length: section.max_word_length,
+        })
+    }
+
+    /// Reads the bigram counts for `input_type`, which the database would
+    /// otherwise have to aggregate at startup
+    pub(crate) fn language_model(
+        &self,
+        input_type: InputType,
+    ) -> Result<LanguageModel> {
+        let section = self.section(input_type)?;
+        let bigrams = Map::new(self.bytes(self.bigrams.clone()))?;
+        let key_bigrams = Map::new(self.bytes(section.key_bigrams.clone()))?;
+        Ok(LanguageModel::compiled(bigrams, key_bigrams))
+    }
+
+    fn section(&self, input_type: InputType) -> Result<&Section> {
+        self.sections
+            .iter()
+            .find(|s| s.input_type == input_type)
+            .ok_or(anyhow!("Index has no section for {:?}", input_type))
+    }
+
+    fn from_data(data: IndexData) -> Result<Self> {
+        let bytes = data.as_ref();
+        let mut reader = Reader { bytes, pos: 0 };
+
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(anyhow!("Not a dictionary index"));
+        }
+
+        let version = reader.u32()?;
+        if version != VERSION {
+            return Err(anyhow!("Unsupported index version {}", version));
+        }
+
+        let built_at = reader.i64()?;
+        let bigrams = reader.range(bytes.len())?;
+        let n_sections = reader.u32()?;
+        let mut sections = Vec::new();
+
+        for _ in 0..n_sections {
+            let input_type = match reader.u32()? {
+                1 => InputType::Numeric,
+                2 => InputType::Telex,
+                ty => return Err(anyhow!("Unknown input type {}", ty)),
+            };
+            let max_word_length = reader.u32()? as usize;
+            let keys = reader.range(bytes.len())?;
+            let values = reader.range(bytes.len())?;
+            let key_bigrams = reader.range(bytes.len())?;
+            sections.push(Section {
+                input_type,
+                max_word_length,
+                keys,
+                values,
+                key_bigrams,
+            });
+        }
+
+        Ok(Self {
+            data: Arc::new(data),
+            built_at,
+            bigrams,
+            sections,
+        })
+    }
+
+    fn bytes(&self, range: Range<usize>) -> IndexBytes {
+        IndexBytes {
+            data: self.data.clone(),
+            range,
+        }
+    }
+}
+
+/// The words of one section of a `CompiledIndex`, answering the same
+/// queries as a `Trie` and a `Segmenter` built from the database
+pub(crate) struct CompiledWords {
+    keys: Map<IndexBytes>,
+    values: IndexBytes,
+    max_word_length: usize,
+}
+
+impl CompiledWords {
+    pub fn find_words_by_prefix(&self, query: &str) -> Vec<i64> {
+        let matcher = fst::automaton::Str::new(query).starts_with();
+        let mut stream = self.keys.search(matcher).into_stream();
+        let mut result = HashSet::new();
+
+        while let Some((_, offset)) = stream.next() {
+            result.extend(self.input_ids(offset));
+        }
+
+        let mut v: Vec<i64> = result.into_iter().collect();
+        v.sort_unstable();
+        v
+    }
+
+    pub fn find_words_from_start<'a>(&self, query: &'a str) -> Vec<&'a str> {
+        let fst = self.keys.as_fst();
+        let mut node = fst.root();
+        let mut found = Vec::new();
+
+        for (i, b) in query.bytes().enumerate() {
+            match node.find_input(b) {
+                Some(t) => node = fst.node(node.transition(t).addr),
+                None => break,
+            }
+
+            if node.is_final() && query.is_char_boundary(i + 1) {
+                found.push(&query[0..(i + 1)]);
+            }
+        }
+
+        found
+    }
+
+    pub fn contains(&self, query: &str) -> bool {
+        self.keys.contains_key(query)
+    }
+
+    pub fn segment_with_bigrams<F>(
+        &self,
+        input: &str,
+        bigram_count: F,
+    ) -> Result<Vec<String>>
+    where
+        F: Fn(&str, &str) -> u32,
+    {
+        Segmenter::segment_by_cost(
+            input,
+            self.max_word_length,
+            |chunk| self.cost(chunk),
+            bigram_count,
+        )
+    }
+
+    fn cost(&self, query: &str) -> Option<f64> {
+        let offset = usize::try_from(self.keys.get(query)?).ok()?;
+        let bytes = self.values.as_ref().get(offset..offset.checked_add(8)?)?;
+        Some(f64::from_le_bytes(bytes.try_into().ok()?))
+    }
+
+    fn input_ids(&self, offset: u64) -> Vec<i64> {
+        let pos = usize::try_from(offset).ok().and_then(|o| o.checked_add(8));
+        let pos = match pos {
+            Some(pos) => pos,
+            None => return Vec::new(),
+        };
+        let bytes = self.values.as_ref();
+        let mut reader = Reader { bytes, pos };
+
+        let n_ids = reader.u32().unwrap_or(0);
+        (0..n_ids)
+            .map_while(|_| reader.u32().ok())
+            .map(|id| id as i64)
+            .collect()
+    }
+}
+
+struct SectionBlock {
+    max_word_length: u32,
+    keys: Vec<u8>,
+    values: Vec<u8>,
+    key_bigrams: Vec<u8>,
+}
+
+/// Keeps the first (most frequent) cost of each key sequence and every
+/// input id, as `Segmenter::new` and `Trie::new` do
+fn build_section(
+    db: &dyn DictionaryBackend,
+    input_type: InputType,
+) -> Result<SectionBlock> {
+    let words = db.select_all_words_by_freq(input_type)?;
+    let mut entries: BTreeMap<&str, (f64, Vec<u32>)> = BTreeMap::new();
+    let mut max_word_length = 0;
+
+    for word in words.iter() {
+        let input_id = u32::try_from(word.input_id)?;
+        let (_, ids) = entries.entry(word.keys.as_str()).or_insert_with(|| {
+            max_word_length =
+                std::cmp::max(max_word_length, word.keys.chars().count());
+            (Segmenter::word_cost(word), Vec::new())
+        });
+
+        if !ids.contains(&input_id) {
+            ids.push(input_id);
+        }
+    }
+
+    let mut keys = MapBuilder::memory();
+    let mut values = Vec::new();
+
+    for (key, (cost, ids)) in entries {
+        keys.insert(key, values.len() as u64)?;
+        values.extend_from_slice(&cost.to_le_bytes());
+        values.extend_from_slice(&(ids.len() as u32).to_le_bytes());
+        for id in ids {
+            values.extend_from_slice(&id.to_le_bytes());
+        }
+    }
+
+    Ok(SectionBlock {
+        max_word_length: max_word_length as u32,
+        keys: keys.into_inner()?,
+        values,
+        key_bigrams: build_bigrams(db.select_key_bigrams(input_type)?)?,
+    })
+}
+
+/// Sums the counts of each bigram, as `LanguageModel::from_bigrams` does
+fn build_bigrams(bigrams: Vec<Bigram>) -> Result<Vec<u8>> {
+    let mut counts: BTreeMap<String, u64> = BTreeMap::new();
+
+    for Bigram { lgram, rgram, n } in bigrams.into_iter() {
+        let n = n.clamp(0, u32::MAX as i64) as u64;
+        *counts.entry(bigram_key(&lgram, &rgram)).or_default() += n;
+    }
+
+    let mut map = MapBuilder::memory();
+    for (key, n) in counts {
+        map.insert(key, n.min(u32::MAX as u64))?;
+    }
+
+    Ok(map.into_inner()?)
+}
+
+fn push_range(bytes: &mut Vec<u8>, offset: usize, len: usize) {
+    bytes.extend_from_slice(&(offset as u64).to_le_bytes());
+    bytes.extend_from_slice(&(len as u64).to_le_bytes());
+}
+
+fn built_at(db: &dyn DictionaryBackend) -> Result<i64> {
+    Ok(db
+        .select_metadata()?
+        .into_iter()
+        .find(|(key, _)| key == "built_at")
+        .map(|(_, value)| value)
+        .unwrap_or(0))
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .pos
+            .checked_add(len)
+            .and_then(|end| self.bytes.get(self.pos..end))
+            .ok_or(anyhow!("Unexpected end of index"))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into()?))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+
+    fn i64(&mut self) -> Result<i64> {
+        Ok(i64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+
+    /// An offset and length, which must lie within the first `len` bytes
+    fn range(&mut self, len: usize) -> Result<Range<usize>> {
+        let start = usize::try_from(self.u64()?)?;
+        let size = usize::try_from(self.u64()?)?;
+        let end = start
+            .checked_add(size)
+            .filter(|&end| end <= len)
+            .ok_or(anyhow!("Index section out of bounds"))?;
+        Ok(start..end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::ToneMode;
+    use crate::data::Dictionary;
+    use crate::data::Trie;
+    use crate::tests::get_db;
+
+    use super::*;
+
+    fn setup() -> CompiledWords {
+        let db = get_db();
+        let bytes = CompiledIndex::build(&db).unwrap();
+        let index = CompiledIndex::from_bytes(bytes).unwrap();
+        index.words(InputType::Numeric).unwrap()
+    }
+
+    #[test]
+    fn it_matches_the_loaded_dictionary() {
+        let db = get_db();
+        let words = setup();
+        let dict = Dictionary::new(&db, ToneMode::Numeric).unwrap();
+
+        for query in ["goa", "e", "chh", "li2ho"] {
+            assert_eq!(
+                words.find_words_by_prefix(query),
+                dict.find_words_by_prefix(query)
+            );
+        }
+
+        let query = "lihopengan";
+        assert_eq!(
+            words.find_words_from_start(query),
+            dict.all_words_from_start(query)
+        );
+
+        let inputs = db.select_all_words_by_freq(InputType::Numeric).unwrap();
+        let trie = Trie::new(&inputs).unwrap();
+        let segmenter = Segmenter::new(inputs).unwrap();
+        let no_bigrams = |_: &str, _: &str| 0;
+        assert_eq!(
+            words.segment_with_bigrams(query, no_bigrams).unwrap(),
+            segmenter.segment_with_bigrams(query, no_bigrams).unwrap()
+        );
+
+        for query in ["pengan", "penga", "peng", "lihopengan", ""] {
+            assert_eq!(words.contains(query), trie.contains(query));
+        }
+    }
+
+    #[test]
+    fn it_matches_the_loaded_language_model() {
+        let db = get_db();
+        db.execute("insert into bigrams values ('我', '好', 5)", [])
+            .unwrap();
+        let bytes = CompiledIndex::build(&db).unwrap();
+        let index = CompiledIndex::from_bytes(bytes).unwrap();
+        let compiled = index.language_model(InputType::Numeric).unwrap();
+        let loaded = LanguageModel::new(&db, InputType::Numeric).unwrap();
+
+        assert_eq!(compiled.is_empty(), loaded.is_empty());
+        for (lgram, rgram) in [("我", "好"), ("好", "我"), ("我", "")] {
+            assert_eq!(
+                compiled.bigram_count(lgram, rgram),
+                loaded.bigram_count(lgram, rgram)
+            );
+        }
+
+        let pairs = [("goa2", "ho2"), ("goa", "ho"), ("ho2", "goa2")];
+        for (lgram, rgram) in pairs {
+            assert_eq!(
+                compiled.key_bigram_count(lgram, rgram),
+                loaded.key_bigram_count(lgram, rgram)
+            );
+        }
+
+        let dict = Dictionary::from_index(ToneMode::Numeric, &index).unwrap();
+        let loaded_dict = Dictionary::new(&db, ToneMode::Numeric).unwrap();
+        for query in ["lihopengan", "goaho", "goa2ho2"] {
+            assert_eq!(
+                dict.segment(query).unwrap(),
+                loaded_dict.segment(query).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn it_rejects_other_files() {
+        let bytes = b"SQLite format 3".to_vec();
+        assert!(CompiledIndex::from_bytes(bytes).is_err());
+        assert!(CompiledIndex::from_bytes(Vec::new()).is_err());
+    }
+
+    #[test]
+    fn it_rejects_corrupt_sections() {
+        let db = get_db();
+        let bytes = CompiledIndex::build(&db).unwrap();
+        let truncated = bytes[..HEADER_LEN + 12].to_vec();
+        assert!(CompiledIndex::from_bytes(truncated).is_err());
+
+        let keys = HEADER_LEN + 8;
+        for (offset, len) in [(u64::MAX, 1), (1, u64::MAX)] {
+            let mut corrupt = bytes.clone();
+            corrupt[keys..keys + 8].copy_from_slice(&offset.to_le_bytes());
+            corrupt[keys + 8..keys + 16].copy_from_slice(&len.to_le_bytes());
+            assert!(CompiledIndex::from_bytes(corrupt).is_err());
+        }
+    }
+
+    #[test]
+    fn it_records_the_database_build() {
+        let db = get_db();
+        let bytes = CompiledIndex::build(&db).unwrap();
+        let index = CompiledIndex::from_bytes(bytes).unwrap();
+        assert_eq!(index.built_at(), built_at(&db).unwrap());
+    }
+}
@@ -1,43 +1,174 @@
+use std::fmt::Debug;
+use std::path::Path;
+use std::sync::Arc;
+
 use anyhow::Result;
+use once_cell::sync::OnceCell;
 
+use crate::config::Config;
 use crate::config::ToneMode;
+use crate::data::CompiledIndex;
+use crate::data::CompiledWords;
+use crate::data::LanguageModel;
 use crate::data::Segmenter;
 use crate::data::Trie;
 use crate::data::SyllableTrie;
+#[cfg(feature = "sqlite")]
 use crate::db::Database;
+#[cfg(feature = "sqlite")]
+use crate::db::OpenMode;
+use crate::db::DictionaryBackend;
+
+/// Read-only dictionary data built once and shared between engines,
+/// which may run on different threads. Each engine still opens its own
+/// database connection. There is one dictionary per tone mode, each built
+/// the first time an engine needs it, from the `CompiledIndex` if there is
+/// one.
+#[derive(Clone, Default)]
+pub struct SharedDictionary {
+    inner: Arc<ToneDictionaries>,
+}
+
+#[derive(Default)]
+struct ToneDictionaries {
+    index: Option<CompiledIndex>,
+    numeric: OnceCell<Arc<Dictionary>>,
+    telex: OnceCell<Arc<Dictionary>>,
+}
+
+impl SharedDictionary {
+    /// Loads the dictionary for the default tone mode right away
+    #[cfg(feature = "sqlite")]
+    pub fn new<P>(filename: P) -> Result<Self>
+    where
+        P: AsRef<Path> + Debug,
+    {
+        let db = Database::new(&filename, OpenMode::Memory)?;
+        log::debug!("Database loaded from: {:?}", filename);
+        let this = Self::for_database(&filename, &db);
+        this.get(&db, Config::new().tone_mode())?;
+        Ok(this)
+    }
+
+    /// Builds each dictionary from `index` instead of the database
+    pub fn with_index(index: CompiledIndex) -> Self {
+        let inner = ToneDictionaries {
+            index: Some(index),
+            ..Default::default()
+        };
+        Self {
+            inner: Arc::new(inner),
+        }
+    }
+
+    /// Uses the index next to the database file `filename`, if it is up
+    /// to date
+    #[cfg(feature = "sqlite")]
+    pub(crate) fn for_database<P>(
+        filename: P,
+        db: &dyn DictionaryBackend,
+    ) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        match CompiledIndex::find(filename, db) {
+            Some(index) => Self::with_index(index),
+            None => Self::default(),
+        }
+    }
+
+    pub(crate) fn get(
+        &self,
+        db: &dyn DictionaryBackend,
+        tone_mode: ToneMode,
+    ) -> Result<Arc<Dictionary>> {
+        let cell = match tone_mode {
+            ToneMode::Numeric => &self.inner.numeric,
+            ToneMode::Telex => &self.inner.telex,
+        };
+        let dict = cell.get_or_try_init(|| {
+            let dict = match &self.inner.index {
+                Some(index) => Dictionary::from_index(tone_mode, index),
+                None => Dictionary::new(db, tone_mode),
+            };
+            dict.map(Arc::new)
+        })?;
+        Ok(dict.clone())
+    }
+}
 
 pub(crate) struct Dictionary {
-    word_trie: Trie,
+    words: Words,
     syllable_trie: SyllableTrie,
-    segmenter: Segmenter,
+    language_model: LanguageModel,
+}
+
+/// The key sequences of the dictionary, either loaded from the database or
+/// read from a `CompiledIndex`
+enum Words {
+    Loaded { trie: Trie, segmenter: Segmenter },
+    Compiled(CompiledWords),
 }
 
 impl Dictionary {
-    pub fn new(db: &Database, tone_mode: ToneMode) -> Result<Self> {
+    pub fn new(
+        db: &dyn DictionaryBackend,
+        tone_mode: ToneMode,
+    ) -> Result<Self> {
         log::debug!("Initializing Dictionary");
         let inputs = db.select_all_words_by_freq(tone_mode.into())?;
         log::debug!("Database query successful");
 
-        let word_trie = Trie::new(&inputs)?;
+        let trie = Trie::new(&inputs)?;
         log::debug!("Word trie loaded");
-        let syllable_trie = SyllableTrie::new();
-        log::debug!("Syllable trie loaded");
         let segmenter = Segmenter::new(inputs)?;
         log::debug!("Segmenter loaded");
 
-        Ok(Self {
-            word_trie,
+        let language_model = LanguageModel::new(db, tone_mode.into())?;
+        log::debug!("Language model loaded");
+
+        Ok(Self::with_words(
+            Words::Loaded { trie, segmenter },
+            language_model,
+        ))
+    }
+
+    /// Reads the key sequences and bigram counts from `index`, without
+    /// querying the database
+    pub fn from_index(
+        tone_mode: ToneMode,
+        index: &CompiledIndex,
+    ) -> Result<Self> {
+        log::debug!("Initializing Dictionary from index");
+        let words = index.words(tone_mode.into())?;
+        let language_model = index.language_model(tone_mode.into())?;
+
+        Ok(Self::with_words(Words::Compiled(words), language_model))
+    }
+
+    fn with_words(words: Words, language_model: LanguageModel) -> Self {
+        let syllable_trie = SyllableTrie::new();
+        log::debug!("Syllable trie loaded");
+
+        Self {
+            words,
             syllable_trie,
-            segmenter,
-        })
+            language_model,
+        }
     }
 
     pub fn find_words_by_prefix(&self, query: &str) -> Vec<i64> {
-        self.word_trie.find_words_by_prefix(query)
+        match &self.words {
+            Words::Loaded { trie, .. } => trie.find_words_by_prefix(query),
+            Words::Compiled(words) => words.find_words_by_prefix(query),
+        }
     }
 
     pub fn all_words_from_start<'a>(&self, query: &'a str) -> Vec<&'a str> {
-        self.word_trie.find_words_from_start(query)
+        match &self.words {
+            Words::Loaded { trie, .. } => trie.find_words_from_start(query),
+            Words::Compiled(words) => words.find_words_from_start(query),
+        }
     }
 
     pub fn is_legal_syllable_prefix(&self, query: &str) -> bool {
@@ -49,7 +180,29 @@ impl Dictionary {
     }
 
     pub fn segment(&self, query: &str) -> Result<Vec<String>> {
-        self.segmenter.segment(query)
+        let bigram_count = |lgram: &str, rgram: &str| {
+            self.language_model.key_bigram_count(lgram, rgram)
+        };
+
+        match &self.words {
+            Words::Loaded { segmenter, .. } => {
+                segmenter.segment_with_bigrams(query, bigram_count)
+            },
+            Words::Compiled(words) => {
+                words.segment_with_bigrams(query, bigram_count)
+            },
+        }
+    }
+
+    fn contains(&self, query: &str) -> bool {
+        match &self.words {
+            Words::Loaded { trie, .. } => trie.contains(query),
+            Words::Compiled(words) => words.contains(query),
+        }
+    }
+
+    pub fn language_model(&self) -> &LanguageModel {
+        &self.language_model
     }
 
     pub fn can_segment(&self, query: &str) -> bool {
@@ -61,7 +214,7 @@ impl Dictionary {
             return true;
         }
 
-        let is_word = |s: &str| *&self.word_trie.contains(&s);
+        let is_word = |s: &str| self.contains(s);
         Segmenter::can_segment(is_word, query)
     }
 
@@ -70,7 +223,7 @@ impl Dictionary {
             return 0;
         }
 
-        let is_word = |s: &str| *&self.word_trie.contains(&s);
+        let is_word = |s: &str| self.contains(s);
         Segmenter::can_segment_max(is_word, query)
     }
 }
@@ -127,6 +280,20 @@ mod tests {
         assert_eq!(dict.is_legal_syllable("chhiapo"), false);
     }
 
+    #[test]
+    fn it_loads_from_an_index() {
+        let db = get_db();
+        let bytes = CompiledIndex::build(&db).unwrap();
+        let index = CompiledIndex::from_bytes(bytes).unwrap();
+        let dict = Dictionary::from_index(ToneMode::Numeric, &index)
+            .expect("Could not load dictionary");
+        let result =
+            dict.segment("lihopengan").expect("Could not segment text");
+        assert_eq!(result, vec!["liho", "pengan"]);
+        assert!(dict.can_segment("lihopengan"));
+        assert!(dict.find_words_by_prefix("goa").len() > 0);
+    }
+
     #[test]
     fn it_segments_words() {
         let dict = setup();
@@ -0,0 +1,145 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use fst::Map;
+
+use crate::data::compiled_index::IndexBytes;
+use crate::db::models::Bigram;
+use crate::db::models::InputType;
+use crate::db::DictionaryBackend;
+
+type BigramMap = HashMap<String, HashMap<String, u32>>;
+
+/// Bigram counts loaded from the database, kept both by output (for ranking
+/// candidates after a committed word) and by key sequence (for segmenting raw
+/// input, before any output has been chosen).
+#[derive(Default)]
+pub(crate) struct LanguageModel {
+    bigrams: Counts,
+    key_bigrams: Counts,
+}
+
+/// Counts of one kind of bigram, either loaded into maps or read from a
+/// `CompiledIndex`, where they are keyed by `bigram_key`
+enum Counts {
+    Loaded(BigramMap),
+    Compiled(Map<IndexBytes>),
+}
+
+impl Default for Counts {
+    fn default() -> Self {
+        Counts::Loaded(HashMap::new())
+    }
+}
+
+impl Counts {
+    fn get(&self, lgram: &str, rgram: &str) -> u32 {
+        match self {
+            Counts::Loaded(map) => map
+                .get(lgram)
+                .and_then(|rgrams| rgrams.get(rgram))
+                .copied()
+                .unwrap_or(0),
+            Counts::Compiled(map) => map
+                .get(bigram_key(lgram, rgram))
+                .map(|n| n.min(u32::MAX as u64) as u32)
+                .unwrap_or(0),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        match self {
+            Counts::Loaded(map) => map.is_empty(),
+            Counts::Compiled(map) => map.is_empty(),
+        }
+    }
+}
+
+fn to_map(bigrams: Vec<Bigram>) -> BigramMap {
+    let mut map: BigramMap = HashMap::new();
+
+    for Bigram { lgram, rgram, n } in bigrams.into_iter() {
+        let n = n.clamp(0, u32::MAX as i64) as u32;
+        *map.entry(lgram).or_default().entry(rgram).or_default() += n;
+    }
+
+    map
+}
+
+/// The key of a bigram in a compiled map. Neither outputs nor key sequences
+/// contain NUL.
+pub(crate) fn bigram_key(lgram: &str, rgram: &str) -> String {
+    format!("{}\0{}", lgram, rgram)
+}
+
+impl LanguageModel {
+    pub fn new(
+        db: &dyn DictionaryBackend,
+        input_type: InputType,
+    ) -> Result<Self> {
+        Ok(Self::from_bigrams(
+            db.select_bigrams()?,
+            db.select_key_bigrams(input_type)?,
+        ))
+    }
+
+    pub fn from_bigrams(
+        bigrams: Vec<Bigram>,
+        key_bigrams: Vec<Bigram>,
+    ) -> Self {
+        Self {
+            bigrams: Counts::Loaded(to_map(bigrams)),
+            key_bigrams: Counts::Loaded(to_map(key_bigrams)),
+        }
+    }
+
+    /// Reads the counts from the maps of a `CompiledIndex`
+    pub(crate) fn compiled(
+        bigrams: Map<IndexBytes>,
+        key_bigrams: Map<IndexBytes>,
+    ) -> Self {
+        Self {
+            bigrams: Counts::Compiled(bigrams),
+            key_bigrams: Counts::Compiled(key_bigrams),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bigrams.is_empty()
+    }
+
+    /// Number of times the output `rgram` followed the output `lgram`
+    pub fn bigram_count(&self, lgram: &str, rgram: &str) -> u32 {
+        self.bigrams.get(lgram, rgram)
+    }
+
+    /// Number of times the key sequence `rgram` followed `lgram`
+    pub fn key_bigram_count(&self, lgram: &str, rgram: &str) -> u32 {
+        self.key_bigrams.get(lgram, rgram)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bigram(lgram: &str, rgram: &str, n: i64) -> Bigram {
+        Bigram {
+            lgram: lgram.into(),
+            rgram: rgram.into(),
+            n,
+        }
+    }
+
+    #[test]
+    fn it_counts_bigrams() {
+        let lm = LanguageModel::from_bigrams(
+            vec![bigram("我", "好", 3), bigram("我", "好", 2)],
+            vec![bigram("goa2", "ho2", 5)],
+        );
+        assert_eq!(lm.bigram_count("我", "好"), 5);
+        assert_eq!(lm.bigram_count("好", "我"), 0);
+        assert_eq!(lm.key_bigram_count("goa2", "ho2"), 5);
+        assert_eq!(lm.key_bigram_count("goa", "ho"), 0);
+    }
+}
@@ -27,6 +27,11 @@ const LETTER_COUNT_BIAS: f64 = 0.2;
 /// syllable "hoan". A higher number would be more likely to use "hoan".
 const SYLLABLE_COUNT_BIAS: f64 = 0.2;
 
+/// A number 0.0 or greater. When a word is known to follow the previous word
+/// in the corpus, its cost is divided by `1 + BIGRAM_BIAS * ln(1 + count)`. If
+/// set to 0.0, the segmentation only uses the unigram costs.
+const BIGRAM_BIAS: f64 = 0.5;
+
 const BIG: f64 = 1e10;
 
 pub struct Segmenter {
@@ -78,18 +83,7 @@ impl Segmenter {
 
             let word_len = word.keys.chars().count();
             max_word_length = std::cmp::max(max_word_length, word_len);
-
-            let p = if word.p <= 0.0 {
-                1e-5 / 10f64.powf(word_len as f64)
-            } else {
-                word.p
-            };
-
-            // Apply the cost biases
-            let mut cost = (1.0 / p.powf(FREQUENCY_BIAS)).ln();
-            let bias = (word_len as f64).powf(LETTER_COUNT_BIAS);
-            let syl_bias = (word.n_syls as f64).powf(SYLLABLE_COUNT_BIAS);
-            cost = cost / bias * syl_bias;
+            let cost = Self::word_cost(&word);
             cost_map.insert(word.keys, cost);
         }
 
@@ -103,6 +97,23 @@ impl Segmenter {
         })
     }
 
+    /// The cost of using `word` as a segment, lower is better
+    pub fn word_cost(word: &KeySequence) -> f64 {
+        let word_len = word.keys.chars().count();
+
+        let p = if word.p <= 0.0 {
+            1e-5 / 10f64.powf(word_len as f64)
+        } else {
+            word.p
+        };
+
+        // Apply the cost biases
+        let cost = (1.0 / p.powf(FREQUENCY_BIAS)).ln();
+        let bias = (word_len as f64).powf(LETTER_COUNT_BIAS);
+        let syl_bias = (word.n_syls as f64).powf(SYLLABLE_COUNT_BIAS);
+        cost / bias * syl_bias
+    }
+
     pub fn segment(&self, input: &str) -> Result<Vec<String>> {
         Ok(segment_min_cost(
             input,
@@ -111,6 +122,40 @@ impl Segmenter {
         ))
     }
 
+    /// Same as `segment`, but lowers the cost of each word by the number of
+    /// times it follows the previous word, as given by `bigram_count`.
+    pub fn segment_with_bigrams<F>(
+        &self,
+        input: &str,
+        bigram_count: F,
+    ) -> Result<Vec<String>>
+    where
+        F: Fn(&str, &str) -> u32,
+    {
+        Ok(segment_min_cost_with_bigrams(
+            input,
+            &self.cost_map,
+            self.max_word_length,
+            bigram_count,
+        ))
+    }
+
+    /// Same as `segment_with_bigrams`, for words kept outside of a
+    /// `Segmenter`, such as in a `CompiledIndex`. `cost` returns `None` for
+    /// anything that is not a word.
+    pub fn segment_by_cost<C, F>(
+        input: &str,
+        max_word_len: usize,
+        cost: C,
+        bigram_count: F,
+    ) -> Result<Vec<String>>
+    where
+        C: Fn(&str) -> Option<f64>,
+        F: Fn(&str, &str) -> u32,
+    {
+        Ok(segment_by_cost(input, max_word_len, cost, bigram_count))
+    }
+
     pub fn can_segment<T>(is_word: T, query: &str) -> bool
     where
         T: Fn(&str) -> bool,
@@ -194,6 +239,43 @@ fn segment_min_cost(
     cost_map: &HashMap<String, f64>,
     max_word_len: usize,
 ) -> Vec<String> {
+    segment_min_cost_with_bigrams(input, cost_map, max_word_len, |_, _| 0)
+}
+
+/// Extends `segment_min_cost` with bigram counts. Only the best path found so
+/// far is known at each index, so the previous word is taken from that path
+/// rather than from every possible split (a first-order approximation).
+///
+/// COST = ln (1 / 𝓟) / (1 + BIGRAM_BIAS * ln (1 + 𝓒))
+///
+/// where `𝓒` is the number of times the chunk follows the previous word.
+fn segment_min_cost_with_bigrams<F>(
+    input: &str,
+    cost_map: &HashMap<String, f64>,
+    max_word_len: usize,
+    bigram_count: F,
+) -> Vec<String>
+where
+    F: Fn(&str, &str) -> u32,
+{
+    segment_by_cost(
+        input,
+        max_word_len,
+        |chunk| cost_map.get(chunk).copied(),
+        bigram_count,
+    )
+}
+
+fn segment_by_cost<C, F>(
+    input: &str,
+    max_word_len: usize,
+    cost: C,
+    bigram_count: F,
+) -> Vec<String>
+where
+    C: Fn(&str) -> Option<f64>,
+    F: Fn(&str, &str) -> u32,
+{
     let len = input.chars().count();
     let mut costs: Vec<(f64, i32)> = Vec::new();
     costs.push((0.0, -1));
@@ -210,13 +292,22 @@ fn segment_min_cost(
 
             log::debug!("chunk: {}", chunk);
 
-            if !cost_map.contains_key(chunk) {
-                continue;
-            }
+            let mut chunk_cost = match cost(chunk) {
+                Some(chunk_cost) => chunk_cost,
+                None => continue,
+            };
 
-            log::debug!("chunk cost: {}", cost_map.get(chunk).unwrap());
+            log::debug!("chunk cost: {}", chunk_cost);
+
+            if j > 0 {
+                let prev = &input[costs[j].1 as usize..j];
+                let n = bigram_count(prev, chunk);
+                if n > 0 {
+                    chunk_cost /= 1.0 + BIGRAM_BIAS * (n as f64).ln_1p();
+                }
+            }
 
-            curr_cost = costs[j].0 + cost_map.get(chunk).unwrap();
+            curr_cost = costs[j].0 + chunk_cost;
             if curr_cost <= min_cost {
                 min_cost = curr_cost;
                 min_cost_idx = j;
@@ -276,6 +367,29 @@ mod tests {
         assert_eq!(result.len(), 4);
     }
 
+    #[test]
+    fn it_uses_bigrams_to_split() {
+        let input = "abc";
+        let cost_map: HashMap<String, f64> = collection!(
+            "a".into() => 10.0,
+            "ab".into() => 10.0,
+            "b".into() => 10.0,
+            "bc".into() => 10.0,
+            "c".into() => 5.0,
+        );
+        let result = segment_min_cost(input, &cost_map, 2);
+        assert_eq!(result, vec!["ab", "c"]);
+
+        let result =
+            segment_min_cost_with_bigrams(input, &cost_map, 2, |l, r| {
+                match (l, r) {
+                    ("a", "bc") => 1000,
+                    _ => 0,
+                }
+            });
+        assert_eq!(result, vec!["a", "bc"]);
+    }
+
     #[test]
     fn it_splits_using_a_word_list() {
         let words = vec![
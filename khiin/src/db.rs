@@ -1,7 +1,24 @@
+pub mod backend;
+#[cfg(feature = "sqlite")]
 pub mod database;
 pub mod init;
+pub mod memory;
 pub mod models;
+#[cfg(feature = "sqlite")]
+pub mod user_database;
 
+pub use backend::DictionaryBackend;
+pub use backend::LearnedStats;
+pub use backend::UserData;
+#[cfg(feature = "sqlite")]
 pub use database::Database;
+#[cfg(feature = "sqlite")]
+pub use database::OpenMode;
 pub use init::csv::CsvFiles;
+#[cfg(feature = "sqlite")]
 pub use init::sql_gen;
+pub use init::tables::Tables;
+pub use memory::MemoryDatabase;
+pub use memory::MemoryUserDatabase;
+#[cfg(feature = "sqlite")]
+pub use user_database::UserDatabase;
@@ -0,0 +1,160 @@
+use anyhow::Result;
+
+use super::models::Bigram;
+use super::models::Emoji;
+use super::models::InputType;
+use super::models::KeyConversion;
+use super::models::KeySequence;
+use super::models::Reading;
+use super::models::Symbol;
+
+/// Usage statistics for a conversion the user has committed before.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct LearnedStats {
+    pub n: i64,
+    pub last_used: i64,
+}
+
+/// Writable store for data learned from the user.
+///
+/// Rows are keyed by the `input` and `output` text of a conversion rather
+/// than by database ids, which are not stable between dictionary builds.
+pub trait UserData {
+    fn learn_conversion(&self, conversion: &KeyConversion) -> Result<()>;
+
+    fn learned_stats(&self, conversion: &KeyConversion)
+        -> Result<LearnedStats>;
+
+    fn record_emoji(&self, emoji: &str) -> Result<()>;
+
+    /// Most recently used emojis first
+    fn select_recent_emojis(&self, limit: usize) -> Result<Vec<String>>;
+
+    /// Returns the number of learned conversions removed
+    fn clear_learned_conversions(&self) -> Result<usize>;
+
+    /// Returns the number of user words removed
+    fn clear_user_words(&self) -> Result<usize>;
+
+    /// Returns the number of recent emojis removed
+    fn clear_recent_emojis(&self) -> Result<usize>;
+
+    /// Stable sort so that more frequently (then more recently) learned
+    /// conversions come first, within groups that share the same `group`
+    /// key. Conversions the user has never chosen keep their order.
+    fn rerank_by<K, F>(
+        &self,
+        conversions: &mut Vec<KeyConversion>,
+        group: F,
+    ) -> Result<()>
+    where
+        Self: Sized,
+        K: Ord,
+        F: Fn(&KeyConversion) -> K,
+    {
+        let mut ranked = Vec::with_capacity(conversions.len());
+        for conv in conversions.drain(..) {
+            let stats = self.learned_stats(&conv)?;
+            ranked.push((group(&conv), std::cmp::Reverse(stats), conv));
+        }
+
+        ranked.sort_by(|a, b| (&a.0, &a.1).cmp(&(&b.0, &b.1)));
+        conversions.extend(ranked.into_iter().map(|(_, _, conv)| conv));
+        Ok(())
+    }
+
+    fn rerank(&self, conversions: &mut Vec<KeyConversion>) -> Result<()>
+    where
+        Self: Sized,
+    {
+        self.rerank_by(conversions, |_| ())
+    }
+}
+
+/// Source of the dictionary data used by the engine. `Database` reads it
+/// from SQLite, and `MemoryDatabase` keeps it in plain Rust collections for
+/// targets without SQLite, such as `wasm32`.
+///
+/// Conversions are returned re-ranked by the backend's `UserData`.
+pub trait DictionaryBackend: Send {
+    fn user_data(&self) -> &dyn UserData;
+
+    fn select_all_words_by_freq(
+        &self,
+        input_type: InputType,
+    ) -> Result<Vec<KeySequence>>;
+
+    /// Key and value pairs describing the dictionary build
+    fn select_metadata(&self) -> Result<Vec<(String, i64)>>;
+
+    fn select_bigrams(&self) -> Result<Vec<Bigram>>;
+
+    /// Bigram counts projected from outputs onto the key sequences of their
+    /// inputs. An output with several readings contributes its count to each
+    /// of them.
+    fn select_key_bigrams(&self, input_type: InputType) -> Result<Vec<Bigram>>;
+
+    /// Ordered by category
+    fn select_emojis(&self) -> Result<Vec<Emoji>>;
+
+    /// Emojis with a keyword starting with `query`, exact matches first
+    fn select_emojis_by_keyword(
+        &self,
+        input_type: InputType,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<Emoji>>;
+
+    /// Ordered by category
+    fn select_symbols(&self) -> Result<Vec<Symbol>>;
+
+    /// Conversions of the key sequence `query`, most frequent first
+    fn select_conversions(
+        &self,
+        input_type: InputType,
+        query: &str,
+        limit: Option<usize>,
+    ) -> Result<Vec<KeyConversion>>;
+
+    /// The best conversion of `query`, preferring Hanji or Lomaji outputs
+    fn select_conversions_by_hanlo(
+        &self,
+        input_type: InputType,
+        query: &str,
+        is_hanji_first: bool,
+        is_khinless: bool,
+    ) -> Result<Vec<KeyConversion>>;
+
+    /// Conversions of `query` with exactly the tones that were typed
+    fn select_conversions_for_tone(
+        &self,
+        input_type: InputType,
+        query: &str,
+        is_hanji_first: bool,
+        is_khinless: bool,
+    ) -> Result<Vec<KeyConversion>>;
+
+    /// Single syllable conversions of `query`, and words of several
+    /// syllables matching `detoned_query`. All rows are returned with
+    /// `detoned_query` as their key sequence.
+    fn select_conversions_for_word(
+        &self,
+        input_type: InputType,
+        query: &str,
+        detoned_query: &str,
+        is_hanji_first: bool,
+        is_khinless: bool,
+    ) -> Result<Vec<KeyConversion>>;
+
+    /// The best conversion of each output for any of `words`, longest
+    /// matches first
+    fn select_conversions_for_multiple(
+        &self,
+        input_type: InputType,
+        words: &[&str],
+    ) -> Result<Vec<KeyConversion>>;
+
+    /// Readings of any of `outputs`, longest outputs first, and the most
+    /// frequent reading of each output first
+    fn select_readings(&self, outputs: &[&str]) -> Result<Vec<Reading>>;
+}
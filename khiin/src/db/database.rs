@@ -1,9 +1,11 @@
+use std::cmp::Ordering;
 use std::fs::create_dir_all;
 use std::ops::Deref;
 use std::ops::DerefMut;
 use std::path::Path;
 use std::path::PathBuf;
 
+use anyhow::anyhow;
 use anyhow::Result;
 use once_cell::sync::Lazy;
 use rusqlite::backup::Progress;
@@ -11,24 +13,57 @@ use rusqlite::named_params;
 use rusqlite::params_from_iter;
 use rusqlite::Connection;
 use rusqlite::DatabaseName;
+use rusqlite::OpenFlags;
 use rusqlite::Row;
 use rusqlite_migration::Migrations;
 use rusqlite_migration::M;
 
+use super::backend::DictionaryBackend;
+use super::backend::UserData;
 use super::init::sql_gen::build_sql;
+use super::models::Bigram;
+use super::models::emoji::keyword_stem;
+use super::models::Emoji;
 use super::models::InputType;
 use super::models::KeyConversion;
 use super::models::KeySequence;
+use super::models::Reading;
+use super::models::Symbol;
+use super::UserDatabase;
 
-static MIGRATIONS: Lazy<Migrations> = Lazy::new(|| {
-    Migrations::new(vec![M::up(include_str!("migrations/001/up.sql"))])
-});
+const MIGRATION_SQL: [&str; 4] = [
+    include_str!("migrations/001/up.sql"),
+    include_str!("migrations/002/up.sql"),
+    include_str!("migrations/003/up.sql"),
+    include_str!("migrations/004/up.sql"),
+];
+
+static MIGRATIONS: Lazy<Migrations> =
+    Lazy::new(|| Migrations::new(Vec::from(MIGRATION_SQL.map(M::up))));
+
+/// Largest region of a read-only database file that SQLite may map
+const MMAP_SIZE: i64 = 256 * 1024 * 1024;
 
 type Noop = Box<dyn Fn(Progress)>;
 
+/// How `Database::new` opens the dictionary file
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OpenMode {
+    /// Copies the whole file into an in-memory connection, first building it
+    /// from the bundled CSVs if it does not exist
+    #[default]
+    Memory,
+    /// Reads the file in place through SQLite's memory-mapped I/O, without
+    /// ever writing to it, e.g. from a read-only app bundle. The file must
+    /// exist. A file from an older schema is copied into memory and migrated
+    /// there, as with `Memory`.
+    ReadOnly,
+}
+
 pub struct Database {
     conn: Connection,
     file: PathBuf,
+    user: UserDatabase,
 }
 
 impl Deref for Database {
@@ -46,13 +81,42 @@ impl DerefMut for Database {
 }
 
 impl Database {
-    pub fn new<P>(file: P) -> Result<Self>
+    /// User data is kept in memory until a `UserDatabase` is set with
+    /// `set_user_db`
+    pub fn new<P>(file: P, mode: OpenMode) -> Result<Self>
     where
         P: AsRef<Path>,
     {
-        let conn = Connection::open_in_memory()?;
         let file = file.as_ref().to_path_buf();
-        let this = Self { conn, file };
+        let user = UserDatabase::new_in_memory()?;
+
+        if mode == OpenMode::ReadOnly {
+            let conn = open_read_only(&file)?;
+            let version = schema_version(&conn)?;
+            let expected = MIGRATION_SQL.len() as i64;
+
+            match version.cmp(&expected) {
+                Ordering::Equal => return Ok(Self { conn, file, user }),
+                Ordering::Less => log::warn!(
+                    "Database {:?} has schema version {}, expected {}, \
+                     migrating it in memory instead",
+                    file,
+                    version,
+                    expected
+                ),
+                Ordering::Greater => {
+                    return Err(anyhow!(
+                        "Database {:?} has schema version {}, newer than {}",
+                        file,
+                        version,
+                        expected
+                    ));
+                },
+            }
+        }
+
+        let conn = Connection::open_in_memory()?;
+        let this = Self { conn, file, user };
 
         if this.file.exists() {
             this.open()
@@ -61,6 +125,14 @@ impl Database {
         }
     }
 
+    pub fn user_db(&self) -> &UserDatabase {
+        &self.user
+    }
+
+    pub fn set_user_db(&mut self, user: UserDatabase) {
+        self.user = user;
+    }
+
     fn init(mut self) -> Result<Self> {
         self.set_pragmas()?;
         self.migrate_to_latest()?;
@@ -97,8 +169,14 @@ impl Database {
             .restore(DatabaseName::Main, &self.file, None::<Noop>)?;
         Ok(())
     }
+}
 
-    pub fn select_all_words_by_freq(
+impl DictionaryBackend for Database {
+    fn user_data(&self) -> &dyn UserData {
+        &self.user
+    }
+
+    fn select_all_words_by_freq(
         &self,
         input_type: InputType,
     ) -> Result<Vec<KeySequence>> {
@@ -115,21 +193,116 @@ impl Database {
         Ok(result)
     }
 
-    pub fn select_conversions(
+    fn select_metadata(&self) -> Result<Vec<(String, i64)>> {
+        let sql = include_str!("sql/select_metadata.sql");
+
+        let mut result = Vec::new();
+        let mut stmt = self.conn.prepare(sql)?;
+        let mut rows = stmt.query([])?;
+
+        while let Some(row) = rows.next()? {
+            result.push((row.get("key")?, row.get("value")?));
+        }
+
+        Ok(result)
+    }
+
+    fn select_bigrams(&self) -> Result<Vec<Bigram>> {
+        let sql = include_str!("sql/select_bigrams.sql");
+
+        let mut result = Vec::new();
+        let mut stmt = self.conn.prepare(sql)?;
+        let mut rows = stmt.query([])?;
+
+        while let Some(row) = rows.next()? {
+            result.push(row.try_into()?);
+        }
+
+        Ok(result)
+    }
+
+    fn select_key_bigrams(
+        &self,
+        input_type: InputType,
+    ) -> Result<Vec<Bigram>> {
+        let sql = include_str!("sql/select_key_bigrams.sql");
+
+        let mut result = Vec::new();
+        let mut stmt = self.conn.prepare(sql)?;
+        let mut rows = stmt.query(named_params! {
+            ":input_type": input_type as i64,
+        })?;
+
+        while let Some(row) = rows.next()? {
+            result.push(row.try_into()?);
+        }
+
+        Ok(result)
+    }
+
+    fn select_emojis(&self) -> Result<Vec<Emoji>> {
+        let sql = include_str!("sql/select_emojis.sql");
+
+        let mut result = Vec::new();
+        let mut stmt = self.conn.prepare(sql)?;
+        let mut rows = stmt.query([])?;
+
+        while let Some(row) = rows.next()? {
+            result.push(row.try_into()?);
+        }
+
+        Ok(result)
+    }
+
+    fn select_emojis_by_keyword(
+        &self,
+        input_type: InputType,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<Emoji>> {
+        let sql = include_str!("sql/select_emojis_by_keyword.sql");
+        let query = query.to_ascii_lowercase();
+
+        let mut result = Vec::new();
+        let mut stmt = self.conn.prepare(sql)?;
+        let mut rows = stmt.query(named_params! {
+            ":query": query,
+            ":stem": keyword_stem(&query),
+            ":input_type": input_type as i64,
+            ":limit": limit as i64,
+        })?;
+
+        while let Some(row) = rows.next()? {
+            result.push(row.try_into()?);
+        }
+
+        Ok(result)
+    }
+
+    fn select_symbols(&self) -> Result<Vec<Symbol>> {
+        let sql = include_str!("sql/select_symbols.sql");
+
+        let mut result = Vec::new();
+        let mut stmt = self.conn.prepare(sql)?;
+        let mut rows = stmt.query([])?;
+
+        while let Some(row) = rows.next()? {
+            result.push(row.try_into()?);
+        }
+
+        Ok(result)
+    }
+
+    fn select_conversions(
         &self,
         input_type: InputType,
         query: &str,
         limit: Option<usize>,
     ) -> Result<Vec<KeyConversion>> {
-        let sql = match limit {
-            Some(n) => format!(
-                include_str!("sql/select_conversions.sql"),
-                limit = format!("limit {}", n)
-            ),
-            None => {
-                format!(include_str!("sql/select_conversions.sql"), limit = "")
-            },
-        };
+        // The limit is applied after re-ranking so that learned
+        // conversions outside of the top results can still surface
+        let sql =
+            format!(include_str!("sql/select_conversions.sql"), limit = "");
 
         let mut stmt = self.conn.prepare(&sql)?;
         let mut rows = stmt.query(named_params! {
@@ -142,10 +315,15 @@ impl Database {
             result.push(row.try_into()?);
         }
 
+        self.user.rerank(&mut result)?;
+        if let Some(n) = limit {
+            result.truncate(n);
+        }
+
         Ok(result)
     }
 
-    pub fn select_conversions_by_hanlo(
+    fn select_conversions_by_hanlo(
         &self,
         input_type: InputType,
         query: &str,
@@ -155,7 +333,7 @@ impl Database {
         let sql = if is_hanji_first {
             format!(
                 include_str!("sql/select_conversions_by_hanji.sql"),
-                limit = "limit 1",
+                limit = "",
                 khin_mode = if is_khinless {
                     "khinless_ok"
                 } else {
@@ -165,7 +343,7 @@ impl Database {
         } else {
             format!(
                 include_str!("sql/select_conversions_by_lomaji.sql"),
-                limit = "limit 1",
+                limit = "",
                 khin_mode = if is_khinless {
                     "khinless_ok"
                 } else {
@@ -185,10 +363,13 @@ impl Database {
             result.push(row.try_into()?);
         }
 
+        self.user.rerank(&mut result)?;
+        result.truncate(1);
+
         Ok(result)
     }
 
-    pub fn select_conversions_for_tone(
+    fn select_conversions_for_tone(
         &self,
         input_type: InputType,
         query: &str,
@@ -227,10 +408,12 @@ impl Database {
             result.push(row.try_into()?);
         }
 
+        self.user.rerank(&mut result)?;
+
         Ok(result)
     }
 
-    pub fn select_conversions_for_word(
+    fn select_conversions_for_word(
         &self,
         input_type: InputType,
         query: &str,
@@ -273,13 +456,15 @@ impl Database {
             result.push(detoned_row);
         }
 
+        self.user.rerank(&mut result)?;
+
         Ok(result)
     }
 
-    pub fn select_conversions_for_multiple(
+    fn select_conversions_for_multiple(
         &self,
         input_type: InputType,
-        words: &Vec<&str>,
+        words: &[&str],
     ) -> Result<Vec<KeyConversion>> {
         let sql = format!(
             include_str!("sql/select_conversions_for_multiple.sql"),
@@ -296,10 +481,43 @@ impl Database {
             result.push(row.try_into()?)
         }
 
+        // Longer matches stay ahead of shorter ones
+        self.user.rerank_by(&mut result, |c| {
+            std::cmp::Reverse(c.key_sequence.len())
+        })?;
+
+        Ok(result)
+    }
+
+    fn select_readings(&self, outputs: &[&str]) -> Result<Vec<Reading>> {
+        let sql = format!(
+            include_str!("sql/select_readings.sql"),
+            vars = repeat_vars(outputs.len()),
+        );
+
+        let mut stmt = self.conn.prepare(&sql)?;
+        let mut rows = stmt.query(params_from_iter(outputs))?;
+        let mut result = Vec::new();
+        while let Some(row) = rows.next()? {
+            result.push(row.try_into()?)
+        }
+
         Ok(result)
     }
 }
 
+impl TryFrom<&Row<'_>> for Reading {
+    type Error = rusqlite::Error;
+
+    fn try_from(row: &Row<'_>) -> std::result::Result<Self, Self::Error> {
+        Ok(Reading {
+            output: row.get("output")?,
+            input: row.get("input")?,
+            weight: row.get("weight")?,
+        })
+    }
+}
+
 impl TryFrom<&Row<'_>> for KeySequence {
     type Error = rusqlite::Error;
 
@@ -314,6 +532,45 @@ impl TryFrom<&Row<'_>> for KeySequence {
     }
 }
 
+impl TryFrom<&Row<'_>> for Bigram {
+    type Error = rusqlite::Error;
+
+    fn try_from(row: &Row<'_>) -> std::result::Result<Self, Self::Error> {
+        Ok(Bigram {
+            lgram: row.get("lgram")?,
+            rgram: row.get("rgram")?,
+            n: row.get("n")?,
+        })
+    }
+}
+
+impl TryFrom<&Row<'_>> for Emoji {
+    type Error = rusqlite::Error;
+
+    fn try_from(row: &Row<'_>) -> std::result::Result<Self, Self::Error> {
+        Ok(Emoji {
+            id: row.get("id")?,
+            emoji: row.get("emoji")?,
+            short_name: row.get("short_name")?,
+            category: row.get("category")?,
+            recent: row.get("recent")?,
+            code: row.get("code")?,
+        })
+    }
+}
+
+impl TryFrom<&Row<'_>> for Symbol {
+    type Error = rusqlite::Error;
+
+    fn try_from(row: &Row<'_>) -> std::result::Result<Self, Self::Error> {
+        Ok(Symbol {
+            input: row.get("input")?,
+            output: row.get("output")?,
+            category: row.get("category")?,
+        })
+    }
+}
+
 impl TryFrom<&Row<'_>> for KeyConversion {
     type Error = rusqlite::Error;
 
@@ -351,12 +608,14 @@ mod cli {
     use rusqlite::Connection;
 
     use super::Database;
+    use crate::db::UserDatabase;
 
     impl Database {
         pub fn from_csv(db_file: &str, csv_files: CsvFiles) -> Result<Self> {
             let conn = Connection::open_in_memory()?;
             let file = PathBuf::from(db_file);
-            let mut db = Self { conn, file };
+            let user = UserDatabase::new_in_memory()?;
+            let mut db = Self { conn, file, user };
 
             db.set_pragmas()?;
             db.migrate_to_latest()?;
@@ -367,7 +626,49 @@ mod cli {
     }
 }
 
-fn ensure_dirs(db_file: &PathBuf) -> Result<()> {
+fn open_read_only(file: &Path) -> Result<Connection> {
+    if !file.exists() {
+        return Err(anyhow!("Database file {:?} not found", file));
+    }
+
+    let flags = OpenFlags::SQLITE_OPEN_READ_ONLY
+        | OpenFlags::SQLITE_OPEN_NO_MUTEX
+        | OpenFlags::SQLITE_OPEN_URI;
+    let conn = Connection::open_with_flags(immutable_uri(file), flags)?;
+    conn.pragma_update(None, "mmap_size", MMAP_SIZE)?;
+
+    Ok(conn)
+}
+
+fn schema_version(conn: &Connection) -> Result<i64> {
+    Ok(conn.pragma_query_value(None, "user_version", |row| row.get(0))?)
+}
+
+/// An `immutable` URI lets SQLite skip locking, and read a file in WAL mode
+/// without creating `-wal` and `-shm` files next to it
+fn immutable_uri(file: &Path) -> String {
+    let path = file.to_string_lossy().replace('\\', "/");
+    let mut uri = String::from("file:");
+
+    if path.as_bytes().get(1) == Some(&b':') {
+        // Windows drive letters need an empty authority
+        uri.push_str("///");
+    }
+
+    for ch in path.chars() {
+        match ch {
+            '%' => uri.push_str("%25"),
+            '?' => uri.push_str("%3f"),
+            '#' => uri.push_str("%23"),
+            _ => uri.push(ch),
+        }
+    }
+
+    uri.push_str("?immutable=1");
+    uri
+}
+
+pub(super) fn ensure_dirs(db_file: &PathBuf) -> Result<()> {
     if !db_file.exists() {
         if let Some(p) = db_file.parent() {
             create_dir_all(p)?;
@@ -391,13 +692,60 @@ mod tests {
 
     #[test]
     fn it_loads_the_db_file() {
-        let db = Database::new(&debug_db_path());
+        let db = Database::new(&debug_db_path(), OpenMode::Memory);
         assert!(db.is_ok());
     }
 
+    #[test]
+    fn it_reads_the_db_file_in_place() -> Result<()> {
+        let db = Database::new(&debug_db_path(), OpenMode::ReadOnly)?;
+        let words = db.select_all_words_by_freq(InputType::Numeric)?;
+        assert!(words.len() > 100);
+        assert!(db.execute("delete from metadata", []).is_err());
+
+        let missing = debug_db_path().with_file_name("missing.db");
+        assert!(Database::new(&missing, OpenMode::ReadOnly).is_err());
+        assert!(!missing.exists());
+        Ok(())
+    }
+
+    #[test]
+    fn it_migrates_older_files_in_memory() -> Result<()> {
+        let file = std::env::temp_dir()
+            .join(format!("khiin-schema-{}.db", std::process::id()));
+        std::fs::copy(debug_db_path(), &file)?;
+        let set_version = |version: i64| -> Result<()> {
+            let conn = Connection::open(&file)?;
+            conn.pragma_update(None, "user_version", version)?;
+            Ok(())
+        };
+
+        set_version(MIGRATION_SQL.len() as i64 - 1)?;
+        let db = Database::new(&file, OpenMode::ReadOnly)?;
+        assert_eq!(schema_version(&db)?, MIGRATION_SQL.len() as i64);
+        assert!(db.execute("delete from metadata", []).is_ok());
+        drop(db);
+
+        set_version(MIGRATION_SQL.len() as i64 + 1)?;
+        assert!(Database::new(&file, OpenMode::ReadOnly).is_err());
+
+        std::fs::remove_file(&file)?;
+        Ok(())
+    }
+
+    #[test]
+    fn it_escapes_file_uris() {
+        let uri = immutable_uri(Path::new("/data/100%/khiin?.db"));
+        assert_eq!(uri, "file:/data/100%25/khiin%3f.db?immutable=1");
+        let uri = immutable_uri(Path::new("C:\\Khiin\\khiin.db"));
+        assert_eq!(uri, "file:///C:/Khiin/khiin.db?immutable=1");
+        let uri = immutable_uri(Path::new("khiin.db"));
+        assert_eq!(uri, "file:khiin.db?immutable=1");
+    }
+
     #[test_log::test]
     fn it_loads_results() {
-        let db = Database::new(&debug_db_path()).expect("Could not load DB");
+        let db = get_db();
         let res = db.select_all_words_by_freq(InputType::Numeric);
         assert!(res.is_ok());
         let res = res.unwrap();
@@ -431,4 +779,60 @@ mod tests {
             .unwrap();
         assert!(res.len() >= 20);
     }
+
+    #[test]
+    fn it_loads_emojis_and_symbols() {
+        let db = get_db();
+        let emojis = db.select_emojis().unwrap();
+        assert!(emojis.len() > 1000);
+        assert_eq!(emojis[0].emoji, "😀");
+        assert_eq!(emojis[0].category, 1);
+        assert!(emojis.iter().any(|e| e.recent));
+        let symbols = db.select_symbols().unwrap();
+        assert!(symbols.iter().any(|s| s.input == "!" && s.output == "！"));
+        assert!(symbols.iter().any(|s| s.input == "\"" && s.output == "“”"));
+    }
+
+    #[test]
+    fn it_finds_emojis_by_keyword() {
+        let db = get_db();
+        let res = db
+            .select_emojis_by_keyword(InputType::Numeric, "smile", 10)
+            .unwrap();
+        assert!(res.iter().any(|e| e.emoji == "😼"));
+        assert!(res.iter().any(|e| e.emoji == "😄"));
+        let res = db
+            .select_emojis_by_keyword(InputType::Numeric, "chhio3", 10)
+            .unwrap();
+        assert_eq!(res[0].emoji, "😀");
+        let res = db
+            .select_emojis_by_keyword(InputType::Telex, "chhio", 10)
+            .unwrap();
+        assert_eq!(res[0].emoji, "😀");
+    }
+
+    #[test]
+    fn it_projects_bigrams_onto_key_sequences() {
+        let db = get_db();
+        db.execute("insert into bigrams values ('我', '好', 5)", [])
+            .unwrap();
+        let res = db.select_bigrams().unwrap();
+        assert!(res.iter().any(|b| b.lgram == "我" && b.rgram == "好"));
+        let res = db.select_key_bigrams(InputType::Numeric).unwrap();
+        assert!(res
+            .iter()
+            .any(|b| b.lgram == "goa2" && b.rgram == "ho2" && b.n >= 5));
+    }
+
+    #[test]
+    fn it_records_build_metadata() {
+        let db = get_db();
+        let metadata = db.select_metadata().unwrap();
+        let value = |key: &str| {
+            metadata.iter().find(|(k, _)| k == key).map(|(_, v)| *v)
+        };
+        assert!(value("built_at").unwrap() > 0);
+        assert!(value("inputs").unwrap() > 100);
+        assert!(value("emojis").unwrap() > 1000);
+    }
 }
@@ -1,3 +1,5 @@
 pub mod csv;
 pub mod data;
+#[cfg(feature = "sqlite")]
 pub mod sql_gen;
+pub mod tables;
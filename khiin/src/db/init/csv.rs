@@ -4,17 +4,29 @@ use std::hash::Hash;
 
 use anyhow::Result;
 use csv::Reader;
+use csv::ReaderBuilder;
 use khiin_ji::IsHanji;
 use protobuf::well_known_types::wrappers::BoolValue;
 use serde::Deserialize;
 
+use crate::db::models::emoji::english_keywords;
+use crate::db::models::generate_key_sequences;
+use crate::db::models::Bigram;
 use crate::db::models::Conversion;
+use crate::db::models::Emoji;
+use crate::db::models::EmojiKeyword;
 use crate::db::models::Input;
 use crate::db::models::InputLookup;
+use crate::db::models::InputType;
+use crate::db::models::Symbol;
 
 pub struct CsvFiles<'a> {
     pub input_csv: Cow<'a, str>,
     pub conversion_csv: Cow<'a, str>,
+    pub bigram_csv: Option<Cow<'a, str>>,
+    pub emoji_csv: Cow<'a, str>,
+    pub emoji_keyword_csv: Cow<'a, str>,
+    pub symbol_tsv: Cow<'a, str>,
 }
 
 impl<'a> CsvFiles<'a> {
@@ -22,8 +34,17 @@ impl<'a> CsvFiles<'a> {
         Self {
             input_csv: csv_data,
             conversion_csv: conversion_data,
+            bigram_csv: None,
+            emoji_csv: Cow::Borrowed(khiin_data::EMOJI_CSV),
+            emoji_keyword_csv: Cow::Borrowed(khiin_data::EMOJI_KEYWORDS_CSV),
+            symbol_tsv: Cow::Borrowed(khiin_data::SYMBOLS_TSV),
         }
     }
+
+    pub fn with_bigrams(mut self, bigram_data: Cow<'a, str>) -> Self {
+        self.bigram_csv = Some(bigram_data);
+        self
+    }
 }
 
 #[derive(Debug, Deserialize, Eq, PartialEq, Hash)]
@@ -47,6 +68,37 @@ pub struct CsvConversion {
     pub khinless_ok: bool,
 }
 
+#[derive(Debug, Deserialize, Eq, PartialEq, Hash)]
+pub struct CsvBigram {
+    pub lgram: String,
+    pub rgram: String,
+    #[serde(rename = "count")]
+    pub n: i64,
+}
+
+#[derive(Debug, Deserialize, Eq, PartialEq, Hash)]
+pub struct CsvEmoji {
+    pub id: i64,
+    pub emoji: String,
+    pub short_name: String,
+    pub category: i64,
+    pub recent: i64,
+    pub code: String,
+}
+
+#[derive(Debug, Deserialize, Eq, PartialEq, Hash)]
+pub struct CsvEmojiKeyword {
+    pub emoji: String,
+    pub keyword: String,
+}
+
+#[derive(Debug, Deserialize, Eq, PartialEq, Hash)]
+pub struct CsvSymbol {
+    pub input: String,
+    pub output: String,
+    pub category: i64,
+}
+
 fn load_freq_records(csv_data: Cow<str>) -> Result<(Vec<CsvFrequency>, i64)> {
     let reader = Reader::from_reader(csv_data.as_bytes());
     let mut total_count = 0;
@@ -151,3 +203,117 @@ pub fn conversions_from_csv(
 
     Ok(records)
 }
+
+pub fn bigrams_from_csv(csv_data: Cow<str>) -> Result<Vec<Bigram>> {
+    let reader = Reader::from_reader(csv_data.as_bytes());
+    let mut records = vec![];
+
+    for result in reader.into_deserialize() {
+        let CsvBigram { lgram, rgram, n } = result?;
+
+        if n > 0 {
+            records.push(Bigram { lgram, rgram, n });
+        }
+    }
+
+    log::debug!("Total bigrams: {}", records.len());
+
+    Ok(records)
+}
+
+pub fn emojis_from_csv(csv_data: Cow<str>) -> Result<Vec<Emoji>> {
+    let reader = Reader::from_reader(csv_data.as_bytes());
+    let mut records = vec![];
+
+    for result in reader.into_deserialize() {
+        let CsvEmoji {
+            id,
+            emoji,
+            short_name,
+            category,
+            recent,
+            code,
+        } = result?;
+
+        records.push(Emoji {
+            id,
+            emoji,
+            short_name,
+            category,
+            recent: recent != 0,
+            code,
+        });
+    }
+
+    log::debug!("Total emojis: {}", records.len());
+
+    Ok(records)
+}
+
+/// Collects English keywords from the emoji short names, and the key sequences
+/// of the Taiwanese keywords in the CSV, which are written like the inputs in
+/// the frequency file (lowercase, syllables separated by spaces).
+pub fn emoji_keywords_from_csv(
+    csv_data: Cow<str>,
+    emojis: &[Emoji],
+) -> Result<Vec<EmojiKeyword>> {
+    let mut records = vec![];
+
+    for emoji in emojis {
+        for keyword in english_keywords(&emoji.short_name) {
+            records.push(EmojiKeyword {
+                keyword,
+                emoji: emoji.emoji.clone(),
+                input_type: InputType::Detoned,
+            });
+        }
+    }
+
+    let reader = Reader::from_reader(csv_data.as_bytes());
+    for result in reader.into_deserialize() {
+        let CsvEmojiKeyword { emoji, keyword } = result?;
+
+        let input = Input {
+            n_syls: keyword.split(' ').count(),
+            input: keyword,
+            ..Default::default()
+        };
+
+        for key_sequence in generate_key_sequences(&vec![input])? {
+            records.push(EmojiKeyword {
+                keyword: key_sequence.keys,
+                emoji: emoji.clone(),
+                input_type: key_sequence.input_type,
+            });
+        }
+    }
+
+    log::debug!("Total emoji keywords: {}", records.len());
+
+    Ok(records)
+}
+
+pub fn symbols_from_tsv(tsv_data: Cow<str>) -> Result<Vec<Symbol>> {
+    let reader = ReaderBuilder::new()
+        .delimiter(b'\t')
+        .from_reader(tsv_data.as_bytes());
+    let mut records = vec![];
+
+    for result in reader.into_deserialize() {
+        let CsvSymbol {
+            input,
+            output,
+            category,
+        } = result?;
+
+        records.push(Symbol {
+            input,
+            output,
+            category,
+        });
+    }
+
+    log::debug!("Total symbols: {}", records.len());
+
+    Ok(records)
+}
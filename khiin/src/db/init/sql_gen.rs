@@ -1,34 +1,20 @@
-use std::borrow::Cow;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
 
 use anyhow::Result;
 use rusqlite::params;
 use rusqlite::Connection;
 
-use crate::db::models::generate_key_sequences;
+use crate::db::models::Bigram;
 use crate::db::models::Conversion;
+use crate::db::models::Emoji;
+use crate::db::models::EmojiKeyword;
 use crate::db::models::Input;
 use crate::db::models::KeySequence;
+use crate::db::models::Symbol;
 
-use super::csv::conversions_from_csv;
-use super::csv::frequencies_from_csv;
 use super::csv::CsvFiles;
-
-pub(crate) fn collect_data(
-    csv_files: CsvFiles,
-) -> Result<(Vec<Input>, Vec<Conversion>, Vec<KeySequence>)> {
-    let CsvFiles {
-        input_csv,
-        conversion_csv,
-    } = csv_files;
-
-    let (inputs, input_lookup) = frequencies_from_csv(input_csv)?;
-    let conversions = conversions_from_csv(conversion_csv, &input_lookup)?;
-    let key_sequences = generate_key_sequences(&inputs)?;
-
-    log::debug!("Total key sequences: {}", key_sequences.len());
-
-    Ok((inputs, conversions, key_sequences))
-}
+use super::tables::Tables;
 
 pub(crate) fn insert_inputs(
     conn: &mut Connection,
@@ -101,26 +87,130 @@ pub(crate) fn insert_key_sequences(
     Ok(())
 }
 
-pub(crate) fn build_sql(conn: &mut Connection) -> Result<()> {
-    let csv_files = CsvFiles::new(
-        Cow::Borrowed(khiin_data::INPUTS_CSV),
-        Cow::Borrowed(khiin_data::CONVERSIONS_CSV),
-    );
+pub(crate) fn insert_emojis(
+    conn: &mut Connection,
+    emojis: Vec<Emoji>,
+) -> Result<()> {
+    let tx = conn.transaction()?;
+    let mut stmt = tx.prepare(include_str!("../sql/insert_emojis.sql"))?;
+
+    for row in emojis {
+        stmt.execute(params![
+            row.id,
+            row.emoji,
+            row.short_name,
+            row.category,
+            row.recent,
+            row.code,
+        ])?;
+    }
+
+    drop(stmt);
+    tx.commit()?;
+
+    Ok(())
+}
+
+pub(crate) fn insert_emoji_keywords(
+    conn: &mut Connection,
+    keywords: Vec<EmojiKeyword>,
+) -> Result<()> {
+    let tx = conn.transaction()?;
+    let mut stmt =
+        tx.prepare(include_str!("../sql/insert_emoji_keywords.sql"))?;
+
+    for row in keywords {
+        stmt.execute(params![row.keyword, row.emoji, row.input_type])?;
+    }
+
+    drop(stmt);
+    tx.commit()?;
+
+    Ok(())
+}
+
+pub(crate) fn insert_symbols(
+    conn: &mut Connection,
+    symbols: Vec<Symbol>,
+) -> Result<()> {
+    let tx = conn.transaction()?;
+    let mut stmt = tx.prepare(include_str!("../sql/insert_symbols.sql"))?;
+
+    for row in symbols {
+        stmt.execute(params![row.input, row.output, row.category])?;
+    }
+
+    drop(stmt);
+    tx.commit()?;
+
+    Ok(())
+}
+
+/// Describes the build in the `metadata` table, e.g. the number of rows in
+/// each table and when the database was built
+pub(crate) fn insert_metadata(
+    conn: &mut Connection,
+    metadata: &[(&str, i64)],
+) -> Result<()> {
+    let tx = conn.transaction()?;
+    let mut stmt = tx.prepare(include_str!("../sql/insert_metadata.sql"))?;
+
+    for (key, value) in metadata {
+        stmt.execute(params![key, value])?;
+    }
+
+    drop(stmt);
+    tx.commit()?;
 
-    build_sql_from_csv(conn, csv_files)
+    Ok(())
+}
+
+/// Unigram counts are derived from the bigrams, with each bigram counting
+/// towards both of its grams.
+pub(crate) fn insert_ngrams(
+    conn: &mut Connection,
+    bigrams: Vec<Bigram>,
+) -> Result<()> {
+    let tx = conn.transaction()?;
+    let mut stmt = tx.prepare(include_str!("../sql/insert_bigrams.sql"))?;
+
+    for row in bigrams {
+        stmt.execute(params![row.lgram, row.rgram, row.n])?;
+    }
+
+    drop(stmt);
+    tx.execute(include_str!("../sql/insert_unigrams_from_bigrams.sql"), [])?;
+    tx.commit()?;
+
+    Ok(())
+}
+
+pub(crate) fn build_sql(conn: &mut Connection) -> Result<()> {
+    insert_tables(conn, Tables::bundled()?)
 }
 
 pub(crate) fn build_sql_from_csv(
     conn: &mut Connection,
     csv_files: CsvFiles,
 ) -> Result<()> {
-    let (inputs, mut conversions, mut key_sequences) = collect_data(csv_files)?;
-
-    conversions.sort_by(|a, b| a.input_id.cmp(&b.input_id));
-    key_sequences.sort_by(|a, b| a.input_id.cmp(&b.input_id));
+    insert_tables(conn, Tables::from_csv(csv_files)?)
+}
 
-    insert_inputs(conn, inputs)?;
-    insert_conversions(conn, conversions)?;
-    insert_key_sequences(conn, key_sequences)?;
+fn insert_tables(conn: &mut Connection, tables: Tables) -> Result<()> {
+    let built_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default();
+    let mut metadata = vec![("built_at", built_at)];
+    metadata.extend(tables.row_counts());
+
+    insert_inputs(conn, tables.inputs)?;
+    insert_conversions(conn, tables.conversions)?;
+    insert_key_sequences(conn, tables.key_sequences)?;
+    insert_ngrams(conn, tables.bigrams)?;
+    insert_emojis(conn, tables.emojis)?;
+    insert_emoji_keywords(conn, tables.emoji_keywords)?;
+    insert_symbols(conn, tables.symbols)?;
+    insert_metadata(conn, &metadata)?;
     Ok(())
 }
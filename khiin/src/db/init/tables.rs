@@ -0,0 +1,102 @@
+use std::borrow::Cow;
+
+use anyhow::Result;
+
+use crate::db::models::generate_key_sequences;
+use crate::db::models::Bigram;
+use crate::db::models::Conversion;
+use crate::db::models::Emoji;
+use crate::db::models::EmojiKeyword;
+use crate::db::models::Input;
+use crate::db::models::KeySequence;
+use crate::db::models::Symbol;
+
+use super::csv::bigrams_from_csv;
+use super::csv::conversions_from_csv;
+use super::csv::emoji_keywords_from_csv;
+use super::csv::emojis_from_csv;
+use super::csv::frequencies_from_csv;
+use super::csv::symbols_from_tsv;
+use super::csv::CsvFiles;
+
+/// Rows of every dictionary table, read from the CSV files. Both the SQLite
+/// database and the in-memory dictionary are built from these.
+pub struct Tables {
+    pub inputs: Vec<Input>,
+    pub conversions: Vec<Conversion>,
+    pub key_sequences: Vec<KeySequence>,
+    pub bigrams: Vec<Bigram>,
+    pub emojis: Vec<Emoji>,
+    pub emoji_keywords: Vec<EmojiKeyword>,
+    pub symbols: Vec<Symbol>,
+}
+
+impl Tables {
+    /// Reads the CSV files bundled with `khiin_data`
+    pub fn bundled() -> Result<Self> {
+        Self::from_csv(CsvFiles::new(
+            Cow::Borrowed(khiin_data::INPUTS_CSV),
+            Cow::Borrowed(khiin_data::CONVERSIONS_CSV),
+        ))
+    }
+
+    /// Conversions and key sequences are ordered by input, as they are
+    /// inserted into the database
+    pub fn from_csv(csv_files: CsvFiles) -> Result<Self> {
+        let bigrams = match csv_files.bigram_csv.clone() {
+            Some(bigram_csv) => bigrams_from_csv(bigram_csv)?,
+            None => Vec::new(),
+        };
+        let emojis = emojis_from_csv(csv_files.emoji_csv.clone())?;
+        let emoji_keywords = emoji_keywords_from_csv(
+            csv_files.emoji_keyword_csv.clone(),
+            &emojis,
+        )?;
+        let symbols = symbols_from_tsv(csv_files.symbol_tsv.clone())?;
+        let (inputs, mut conversions, mut key_sequences) =
+            collect_data(csv_files)?;
+
+        conversions.sort_by_key(|c| c.input_id);
+        key_sequences.sort_by_key(|k| k.input_id);
+
+        Ok(Self {
+            inputs,
+            conversions,
+            key_sequences,
+            bigrams,
+            emojis,
+            emoji_keywords,
+            symbols,
+        })
+    }
+
+    /// Number of rows in each table, for the build metadata
+    pub fn row_counts(&self) -> Vec<(&'static str, i64)> {
+        vec![
+            ("inputs", self.inputs.len() as i64),
+            ("conversions", self.conversions.len() as i64),
+            ("key_sequences", self.key_sequences.len() as i64),
+            ("bigrams", self.bigrams.len() as i64),
+            ("emojis", self.emojis.len() as i64),
+            ("symbols", self.symbols.len() as i64),
+        ]
+    }
+}
+
+pub(crate) fn collect_data(
+    csv_files: CsvFiles,
+) -> Result<(Vec<Input>, Vec<Conversion>, Vec<KeySequence>)> {
+    let CsvFiles {
+        input_csv,
+        conversion_csv,
+        ..
+    } = csv_files;
+
+    let (inputs, input_lookup) = frequencies_from_csv(input_csv)?;
+    let conversions = conversions_from_csv(conversion_csv, &input_lookup)?;
+    let key_sequences = generate_key_sequences(&inputs)?;
+
+    log::debug!("Total key sequences: {}", key_sequences.len());
+
+    Ok((inputs, conversions, key_sequences))
+}
@@ -0,0 +1,803 @@
+use std::cell::Cell;
+use std::cell::RefCell;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use anyhow::Result;
+
+use super::backend::DictionaryBackend;
+use super::backend::LearnedStats;
+use super::backend::UserData;
+use super::init::csv::CsvFiles;
+use super::init::tables::Tables;
+use super::models::emoji::keyword_stem;
+use super::models::Bigram;
+use super::models::Conversion;
+use super::models::Emoji;
+use super::models::EmojiKeyword;
+use super::models::InputType;
+use super::models::KeyConversion;
+use super::models::KeySequence;
+use super::models::Reading;
+use super::models::Symbol;
+
+/// Learned data that lives only as long as the engine, for hosts that have
+/// nowhere to save it.
+#[derive(Default)]
+pub struct MemoryUserDatabase {
+    conversions: RefCell<HashMap<(String, String), LearnedStats>>,
+    emojis: RefCell<HashMap<String, LearnedStats>>,
+
+    /// Counts every use, and stands in for the time of last use since not
+    /// every target has a clock
+    clock: Cell<i64>,
+}
+
+impl MemoryUserDatabase {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn record<K>(&self, map: &RefCell<HashMap<K, LearnedStats>>, key: K)
+    where
+        K: Eq + Hash,
+    {
+        let now = self.clock.get() + 1;
+        self.clock.set(now);
+
+        let mut map = map.borrow_mut();
+        let stats = map.entry(key).or_default();
+        stats.n += 1;
+        stats.last_used = now;
+    }
+}
+
+fn clear<K>(map: &RefCell<HashMap<K, LearnedStats>>) -> usize {
+    map.borrow_mut().drain().count()
+}
+
+impl UserData for MemoryUserDatabase {
+    fn learn_conversion(&self, conversion: &KeyConversion) -> Result<()> {
+        let key = (conversion.input.clone(), conversion.output.clone());
+        self.record(&self.conversions, key);
+        Ok(())
+    }
+
+    fn learned_stats(
+        &self,
+        conversion: &KeyConversion,
+    ) -> Result<LearnedStats> {
+        let key = (conversion.input.clone(), conversion.output.clone());
+        let stats = self.conversions.borrow().get(&key).copied();
+        Ok(stats.unwrap_or_default())
+    }
+
+    fn record_emoji(&self, emoji: &str) -> Result<()> {
+        self.record(&self.emojis, emoji.to_string());
+        Ok(())
+    }
+
+    fn select_recent_emojis(&self, limit: usize) -> Result<Vec<String>> {
+        let emojis = self.emojis.borrow();
+        let mut recent: Vec<_> = emojis.iter().collect();
+        recent.sort_by_key(|(_, stats)| Reverse((stats.last_used, stats.n)));
+
+        Ok(recent
+            .into_iter()
+            .take(limit)
+            .map(|(emoji, _)| emoji.clone())
+            .collect())
+    }
+
+    fn clear_learned_conversions(&self) -> Result<usize> {
+        Ok(clear(&self.conversions))
+    }
+
+    /// There is no way to add user words yet
+    fn clear_user_words(&self) -> Result<usize> {
+        Ok(0)
+    }
+
+    fn clear_recent_emojis(&self) -> Result<usize> {
+        Ok(clear(&self.emojis))
+    }
+}
+
+/// Dictionary held in plain Rust collections, built from the same CSV data
+/// as the SQLite `Database`. It needs no file system or native libraries,
+/// so it can be used on any target, including `wasm32`.
+///
+/// Queries give the same results as the SQL in `sql/`, which they follow
+/// closely. Learned data is kept in a `MemoryUserDatabase`.
+pub struct MemoryDatabase {
+    inputs: HashMap<i64, String>,
+    conversions: Vec<Conversion>,
+    key_sequences: Vec<KeySequence>,
+    unigrams: HashMap<String, i64>,
+    bigrams: Vec<Bigram>,
+    emojis: Vec<Emoji>,
+    emoji_keywords: Vec<EmojiKeyword>,
+    symbols: Vec<Symbol>,
+    metadata: Vec<(String, i64)>,
+
+    // Row indexes, standing in for the database indexes
+    key_sequences_by_keys: HashMap<String, Vec<usize>>,
+    key_sequences_by_input: HashMap<i64, Vec<usize>>,
+    conversions_by_input: HashMap<i64, Vec<usize>>,
+    conversions_by_output: HashMap<String, Vec<usize>>,
+    emojis_by_value: HashMap<String, usize>,
+
+    user: MemoryUserDatabase,
+}
+
+/// A row of the `conversion_lookups` view: one key sequence of an input,
+/// with one of the conversions of the same input
+#[derive(Clone, Copy)]
+struct Lookup<'a> {
+    key_sequence: &'a KeySequence,
+    conversion: &'a Conversion,
+}
+
+impl Lookup<'_> {
+    /// Toneless key sequences are valid in every input mode
+    fn is_input_type(&self, input_type: InputType) -> bool {
+        self.key_sequence.input_type == input_type
+            || self.key_sequence.input_type == InputType::Detoned
+    }
+
+    fn is_khin_ok(&self, is_khinless: bool) -> bool {
+        if is_khinless {
+            self.conversion.khinless_ok
+        } else {
+            self.conversion.khin_ok
+        }
+    }
+
+    /// Sorts Hanji before Lomaji, or the other way around
+    fn hanlo_order(&self, is_hanji_first: bool) -> bool {
+        self.conversion.is_hanji != is_hanji_first
+    }
+
+    fn key_len(&self) -> usize {
+        self.key_sequence.keys.chars().count()
+    }
+}
+
+fn index_by<K, F>(len: usize, key: F) -> HashMap<K, Vec<usize>>
+where
+    K: Eq + Hash,
+    F: Fn(usize) -> K,
+{
+    let mut index: HashMap<K, Vec<usize>> = HashMap::new();
+    for i in 0..len {
+        index.entry(key(i)).or_default().push(i);
+    }
+    index
+}
+
+impl MemoryDatabase {
+    /// Builds the dictionary from the CSV files bundled with `khiin_data`
+    pub fn new() -> Result<Self> {
+        Ok(Self::from_tables(Tables::bundled()?))
+    }
+
+    pub fn from_csv(csv_files: CsvFiles) -> Result<Self> {
+        Ok(Self::from_tables(Tables::from_csv(csv_files)?))
+    }
+
+    pub fn from_tables(tables: Tables) -> Self {
+        let mut metadata: Vec<(String, i64)> = tables
+            .row_counts()
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect();
+        metadata.sort();
+
+        let Tables {
+            inputs,
+            conversions,
+            key_sequences,
+            bigrams,
+            mut emojis,
+            emoji_keywords,
+            mut symbols,
+        } = tables;
+
+        let inputs = inputs.into_iter().map(|i| (i.id, i.input)).collect();
+
+        let mut unigrams: HashMap<String, i64> = HashMap::new();
+        for bigram in bigrams.iter() {
+            *unigrams.entry(bigram.lgram.clone()).or_default() += bigram.n;
+            *unigrams.entry(bigram.rgram.clone()).or_default() += bigram.n;
+        }
+
+        emojis.sort_by_key(|e| (e.category, e.id));
+        symbols.sort_by_key(|s| s.category);
+
+        let key_sequences_by_keys =
+            index_by(key_sequences.len(), |i| key_sequences[i].keys.clone());
+        let key_sequences_by_input =
+            index_by(key_sequences.len(), |i| key_sequences[i].input_id);
+        let mut conversions_by_input =
+            index_by(conversions.len(), |i| conversions[i].input_id);
+        let conversions_by_output =
+            index_by(conversions.len(), |i| conversions[i].output.clone());
+        let emojis_by_value = emojis
+            .iter()
+            .enumerate()
+            .map(|(i, e)| (e.emoji.clone(), i))
+            .collect();
+
+        // Conversions are looked up through an index on input and output
+        for rows in conversions_by_input.values_mut() {
+            rows.sort_by(|&a, &b| {
+                conversions[a].output.cmp(&conversions[b].output)
+            });
+        }
+
+        Self {
+            inputs,
+            conversions,
+            key_sequences,
+            unigrams,
+            bigrams,
+            emojis,
+            emoji_keywords,
+            symbols,
+            metadata,
+            key_sequences_by_keys,
+            key_sequences_by_input,
+            conversions_by_input,
+            conversions_by_output,
+            emojis_by_value,
+            user: MemoryUserDatabase::new(),
+        }
+    }
+
+    pub fn user_db(&self) -> &MemoryUserDatabase {
+        &self.user
+    }
+
+    pub fn set_user_db(&mut self, user: MemoryUserDatabase) {
+        self.user = user;
+    }
+
+    fn rows<'a>(
+        &'a self,
+        key_rows: &'a [usize],
+    ) -> impl Iterator<Item = Lookup<'a>> + 'a {
+        key_rows.iter().flat_map(move |&k| {
+            let key_sequence = &self.key_sequences[k];
+            self.conversions_by_input
+                .get(&key_sequence.input_id)
+                .map_or(&[][..], Vec::as_slice)
+                .iter()
+                .map(move |&c| Lookup {
+                    key_sequence,
+                    conversion: &self.conversions[c],
+                })
+        })
+    }
+
+    /// Lookups with the key sequence `keys`
+    fn lookups(&self, keys: &str) -> Vec<Lookup<'_>> {
+        match self.key_sequences_by_keys.get(keys) {
+            Some(key_rows) => self.rows(key_rows).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Key sequences of every input that converts to `output`
+    fn keys_of_output(&self, output: &str, input_type: InputType) -> Vec<&str> {
+        let mut result = Vec::new();
+
+        for &c in self.conversions_by_output.get(output).into_iter().flatten() {
+            let input_id = self.conversions[c].input_id;
+            let key_rows = self.key_sequences_by_input.get(&input_id);
+
+            for &k in key_rows.into_iter().flatten() {
+                let key_sequence = &self.key_sequences[k];
+                if key_sequence.input_type == input_type
+                    || key_sequence.input_type == InputType::Detoned
+                {
+                    result.push(key_sequence.keys.as_str());
+                }
+            }
+        }
+
+        result
+    }
+
+    fn unigram_count(&self, row: &Lookup) -> Option<i64> {
+        self.unigrams.get(&row.conversion.output).copied()
+    }
+
+    fn key_conversion(&self, row: &Lookup) -> KeyConversion {
+        let input_id = row.key_sequence.input_id;
+
+        KeyConversion {
+            key_sequence: row.key_sequence.keys.clone(),
+            input_type: row.key_sequence.input_type,
+            input: self.inputs.get(&input_id).cloned().unwrap_or_default(),
+            input_id: input_id as u32,
+            output: row.conversion.output.clone(),
+            weight: row.conversion.weight as i32,
+            khin_ok: row.conversion.khin_ok,
+            khinless_ok: row.conversion.khinless_ok,
+            annotation: row.conversion.annotation.clone(),
+        }
+    }
+
+    fn key_conversions(&self, rows: &[Lookup]) -> Vec<KeyConversion> {
+        rows.iter().map(|row| self.key_conversion(row)).collect()
+    }
+}
+
+impl DictionaryBackend for MemoryDatabase {
+    fn user_data(&self) -> &dyn UserData {
+        &self.user
+    }
+
+    fn select_all_words_by_freq(
+        &self,
+        input_type: InputType,
+    ) -> Result<Vec<KeySequence>> {
+        let mut result: Vec<KeySequence> = self
+            .key_sequences
+            .iter()
+            .filter(|k| {
+                k.input_type == input_type || k.input_type == InputType::Detoned
+            })
+            .cloned()
+            .collect();
+
+        result.sort_by(|a, b| b.p.total_cmp(&a.p));
+        Ok(result)
+    }
+
+    fn select_metadata(&self) -> Result<Vec<(String, i64)>> {
+        Ok(self.metadata.clone())
+    }
+
+    fn select_bigrams(&self) -> Result<Vec<Bigram>> {
+        Ok(self.bigrams.clone())
+    }
+
+    fn select_key_bigrams(&self, input_type: InputType) -> Result<Vec<Bigram>> {
+        let mut counts: BTreeMap<(&str, &str), i64> = BTreeMap::new();
+
+        for bigram in self.bigrams.iter() {
+            let lkeys = self.keys_of_output(&bigram.lgram, input_type);
+            let rkeys = self.keys_of_output(&bigram.rgram, input_type);
+
+            for &lgram in lkeys.iter() {
+                for &rgram in rkeys.iter() {
+                    *counts.entry((lgram, rgram)).or_default() += bigram.n;
+                }
+            }
+        }
+
+        Ok(counts
+            .into_iter()
+            .map(|((lgram, rgram), n)| Bigram {
+                lgram: lgram.to_string(),
+                rgram: rgram.to_string(),
+                n,
+            })
+            .collect())
+    }
+
+    fn select_emojis(&self) -> Result<Vec<Emoji>> {
+        Ok(self.emojis.clone())
+    }
+
+    fn select_emojis_by_keyword(
+        &self,
+        input_type: InputType,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<Emoji>> {
+        let query = query.to_ascii_lowercase();
+        let stem = keyword_stem(&query);
+
+        // Best match of any keyword for each emoji: whether it is the query,
+        // whether it is the stem, and the length of the shortest one
+        let mut matches: HashMap<usize, (bool, bool, usize)> = HashMap::new();
+
+        for keyword in self.emoji_keywords.iter() {
+            if keyword.input_type != input_type
+                && keyword.input_type != InputType::Detoned
+            {
+                continue;
+            }
+
+            let word = keyword.keyword.as_str();
+            if !word.starts_with(query.as_str()) && !word.starts_with(stem) {
+                continue;
+            }
+
+            let Some(&i) = self.emojis_by_value.get(&keyword.emoji) else {
+                continue;
+            };
+
+            let m = matches.entry(i).or_insert((false, false, usize::MAX));
+            m.0 |= word == query;
+            m.1 |= word == stem;
+            m.2 = m.2.min(word.chars().count());
+        }
+
+        let mut result: Vec<_> = matches.into_iter().collect();
+        result.sort_by_key(|&(i, (is_query, is_stem, len))| {
+            (Reverse(is_query), Reverse(is_stem), len, self.emojis[i].id)
+        });
+
+        Ok(result
+            .into_iter()
+            .take(limit)
+            .map(|(i, _)| self.emojis[i].clone())
+            .collect())
+    }
+
+    fn select_symbols(&self) -> Result<Vec<Symbol>> {
+        Ok(self.symbols.clone())
+    }
+
+    fn select_conversions(
+        &self,
+        input_type: InputType,
+        query: &str,
+        limit: Option<usize>,
+    ) -> Result<Vec<KeyConversion>> {
+        let mut rows = self.lookups(query);
+        rows.retain(|row| row.is_input_type(input_type));
+        rows.sort_by_key(|row| {
+            (
+                Reverse(self.unigram_count(row)),
+                Reverse(row.conversion.weight),
+            )
+        });
+
+        let mut result = self.key_conversions(&rows);
+        self.user.rerank(&mut result)?;
+        if let Some(n) = limit {
+            result.truncate(n);
+        }
+
+        Ok(result)
+    }
+
+    fn select_conversions_by_hanlo(
+        &self,
+        input_type: InputType,
+        query: &str,
+        is_hanji_first: bool,
+        is_khinless: bool,
+    ) -> Result<Vec<KeyConversion>> {
+        let mut rows = self.lookups(query);
+        rows.retain(|row| {
+            row.is_input_type(input_type) && row.is_khin_ok(is_khinless)
+        });
+        rows.sort_by_key(|row| {
+            (
+                row.hanlo_order(is_hanji_first),
+                Reverse(self.unigram_count(row)),
+                Reverse(row.conversion.weight),
+            )
+        });
+
+        let mut result = self.key_conversions(&rows);
+        self.user.rerank(&mut result)?;
+        result.truncate(1);
+
+        Ok(result)
+    }
+
+    fn select_conversions_for_tone(
+        &self,
+        input_type: InputType,
+        query: &str,
+        is_hanji_first: bool,
+        is_khinless: bool,
+    ) -> Result<Vec<KeyConversion>> {
+        let mut rows = self.lookups(query);
+        rows.retain(|row| {
+            row.key_sequence.input_type == input_type
+                && row.is_khin_ok(is_khinless)
+        });
+        rows.sort_by_key(|row| {
+            (
+                row.hanlo_order(is_hanji_first),
+                Reverse(row.conversion.weight),
+            )
+        });
+
+        let mut result = self.key_conversions(&rows);
+        self.user.rerank(&mut result)?;
+
+        Ok(result)
+    }
+
+    fn select_conversions_for_word(
+        &self,
+        input_type: InputType,
+        query: &str,
+        detoned_query: &str,
+        is_hanji_first: bool,
+        is_khinless: bool,
+    ) -> Result<Vec<KeyConversion>> {
+        let mut rows = self.lookups(query);
+        rows.retain(|row| {
+            row.key_sequence.input_type == input_type
+                && row.key_sequence.n_syls == 1
+                && row.is_khin_ok(is_khinless)
+        });
+        rows.extend(self.lookups(detoned_query).into_iter().filter(|row| {
+            row.key_sequence.input_type == InputType::Detoned
+                && row.key_sequence.n_syls > 1
+                && row.is_khin_ok(is_khinless)
+        }));
+        rows.sort_by_key(|row| {
+            (
+                row.hanlo_order(is_hanji_first),
+                Reverse(row.conversion.weight),
+            )
+        });
+
+        let mut result = self.key_conversions(&rows);
+        for row in result.iter_mut() {
+            row.key_sequence = detoned_query.to_string();
+        }
+
+        self.user.rerank(&mut result)?;
+
+        Ok(result)
+    }
+
+    fn select_conversions_for_multiple(
+        &self,
+        input_type: InputType,
+        words: &[&str],
+    ) -> Result<Vec<KeyConversion>> {
+        let mut words = words.to_vec();
+        words.sort();
+        words.dedup();
+
+        // The highest weighted row for each output, then the longest
+        let mut rows: Vec<Lookup> = Vec::new();
+        let mut outputs: HashMap<&str, usize> = HashMap::new();
+
+        for word in words {
+            for row in self.lookups(word) {
+                if !row.is_input_type(input_type) {
+                    continue;
+                }
+
+                let rank =
+                    |row: &Lookup| (row.conversion.weight, row.key_len());
+                match outputs.get(row.conversion.output.as_str()) {
+                    Some(&i) if rank(&row) > rank(&rows[i]) => rows[i] = row,
+                    Some(_) => {},
+                    None => {
+                        outputs.insert(&row.conversion.output, rows.len());
+                        rows.push(row);
+                    },
+                }
+            }
+        }
+
+        rows.sort_by_key(|row| {
+            let n_syls = row.key_sequence.n_syls.max(1) as i64;
+            (
+                Reverse(row.key_len()),
+                Reverse(row.conversion.weight / n_syls),
+            )
+        });
+
+        let mut result = self.key_conversions(&rows);
+
+        // Longer matches stay ahead of shorter ones
+        self.user.rerank_by(&mut result, |c| {
+            std::cmp::Reverse(c.key_sequence.len())
+        })?;
+
+        Ok(result)
+    }
+
+    fn select_readings(&self, outputs: &[&str]) -> Result<Vec<Reading>> {
+        let mut outputs = outputs.to_vec();
+        outputs.sort();
+        outputs.dedup();
+
+        let mut result = Vec::new();
+        for output in outputs {
+            let rows = self.conversions_by_output.get(output);
+
+            for &c in rows.into_iter().flatten() {
+                let conversion = &self.conversions[c];
+                if let Some(input) = self.inputs.get(&conversion.input_id) {
+                    result.push(Reading {
+                        output: conversion.output.clone(),
+                        input: input.clone(),
+                        weight: conversion.weight,
+                    });
+                }
+            }
+        }
+
+        result.sort_by(|a, b| {
+            let key = |r: &Reading| {
+                (Reverse(r.output.chars().count()), r.output.clone())
+            };
+            (key(a), Reverse(a.weight), &a.input)
+                .cmp(&(key(b), Reverse(b.weight), &b.input))
+        });
+
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::*;
+
+    #[test]
+    fn it_keeps_learned_data() {
+        let db = MemoryUserDatabase::new();
+        let a = mock_conversion("hó", "好");
+        let b = mock_conversion("hó", "號");
+        db.learn_conversion(&b).unwrap();
+        db.learn_conversion(&b).unwrap();
+        assert_eq!(db.learned_stats(&a).unwrap().n, 0);
+        assert_eq!(db.learned_stats(&b).unwrap().n, 2);
+
+        let mut convs = vec![a, b];
+        db.rerank(&mut convs).unwrap();
+        assert_eq!(convs[0].output, "號");
+
+        db.record_emoji("😀").unwrap();
+        db.record_emoji("😃").unwrap();
+        db.record_emoji("😀").unwrap();
+        assert_eq!(db.select_recent_emojis(10).unwrap(), vec!["😀", "😃"]);
+        assert_eq!(db.select_recent_emojis(1).unwrap().len(), 1);
+
+        assert_eq!(db.clear_learned_conversions().unwrap(), 1);
+        assert_eq!(db.clear_user_words().unwrap(), 0);
+        assert_eq!(db.clear_recent_emojis().unwrap(), 2);
+        assert!(db.select_recent_emojis(10).unwrap().is_empty());
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn it_matches_the_sqlite_database() {
+        let sqlite = get_db();
+        let memory = MemoryDatabase::new().unwrap();
+        let outputs = |convs: Vec<KeyConversion>| -> Vec<String> {
+            convs.into_iter().map(|c| c.output).collect()
+        };
+        let keys = |seqs: Vec<KeySequence>| -> Vec<String> {
+            seqs.into_iter().take(100).map(|k| k.keys).collect()
+        };
+        let it = InputType::Numeric;
+
+        assert_eq!(
+            keys(memory.select_all_words_by_freq(it).unwrap()),
+            keys(sqlite.select_all_words_by_freq(it).unwrap())
+        );
+
+        for query in ["ho2", "ho", "a", "goa2", "tai5oan5"] {
+            assert_eq!(
+                outputs(memory.select_conversions(it, query, None).unwrap()),
+                outputs(sqlite.select_conversions(it, query, None).unwrap())
+            );
+
+            for (hanji, khinless) in [(true, false), (false, true)] {
+                assert_eq!(
+                    outputs(
+                        memory
+                            .select_conversions_for_tone(
+                                it, query, hanji, khinless
+                            )
+                            .unwrap()
+                    ),
+                    outputs(
+                        sqlite
+                            .select_conversions_for_tone(
+                                it, query, hanji, khinless
+                            )
+                            .unwrap()
+                    )
+                );
+                assert_eq!(
+                    outputs(
+                        memory
+                            .select_conversions_by_hanlo(
+                                it, query, hanji, khinless
+                            )
+                            .unwrap()
+                    ),
+                    outputs(
+                        sqlite
+                            .select_conversions_by_hanlo(
+                                it, query, hanji, khinless
+                            )
+                            .unwrap()
+                    )
+                );
+            }
+        }
+
+        for (query, detoned) in [("ho2", "ho"), ("tai5oan5", "taioan")] {
+            for (hanji, khinless) in [(true, false), (false, true)] {
+                let mem = memory
+                    .select_conversions_for_word(
+                        it, query, detoned, hanji, khinless
+                    )
+                    .unwrap();
+                let sql = sqlite
+                    .select_conversions_for_word(
+                        it, query, detoned, hanji, khinless
+                    )
+                    .unwrap();
+                assert!(mem.iter().all(|c| c.key_sequence == detoned));
+                assert_eq!(outputs(mem), outputs(sql));
+            }
+        }
+
+        // Rows that tie may come in any order from SQLite
+        let words = vec!["ho", "hong", "ho2"];
+        let mut mem =
+            memory.select_conversions_for_multiple(it, &words).unwrap();
+        let mut sql =
+            sqlite.select_conversions_for_multiple(it, &words).unwrap();
+        let lens = |convs: &Vec<KeyConversion>| -> Vec<usize> {
+            convs.iter().map(|c| c.key_sequence.len()).collect()
+        };
+        assert_eq!(lens(&mem), lens(&sql));
+        mem.sort_by(|a, b| a.output.cmp(&b.output));
+        sql.sort_by(|a, b| a.output.cmp(&b.output));
+        assert_eq!(outputs(mem), outputs(sql));
+
+        for query in ["smile", "chhio3"] {
+            assert_eq!(
+                memory.select_emojis_by_keyword(it, query, 10).unwrap(),
+                sqlite.select_emojis_by_keyword(it, query, 10).unwrap()
+            );
+        }
+
+        assert_eq!(
+            memory.select_emojis().unwrap(),
+            sqlite.select_emojis().unwrap()
+        );
+        assert_eq!(
+            memory.select_symbols().unwrap(),
+            sqlite.select_symbols().unwrap()
+        );
+
+        let outputs = vec!["好", "台灣", "食飯"];
+        assert_eq!(
+            memory.select_readings(&outputs).unwrap(),
+            sqlite.select_readings(&outputs).unwrap()
+        );
+
+        let sorted = |mut bigrams: Vec<Bigram>| -> Vec<Bigram> {
+            bigrams.sort_by(|a, b| {
+                (&a.lgram, &a.rgram).cmp(&(&b.lgram, &b.rgram))
+            });
+            bigrams
+        };
+        for it in [InputType::Numeric, InputType::Telex] {
+            assert_eq!(
+                sorted(memory.select_key_bigrams(it).unwrap()),
+                sorted(sqlite.select_key_bigrams(it).unwrap())
+            );
+        }
+
+        // The file also records when it was built
+        let mut metadata = sqlite.select_metadata().unwrap();
+        metadata.retain(|(key, _)| key != "built_at");
+        assert_eq!(memory.select_metadata().unwrap(), metadata);
+    }
+}
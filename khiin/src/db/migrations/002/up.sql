@@ -0,0 +1,24 @@
+drop table if exists emojis;
+
+drop table if exists symbols;
+
+create table
+    emojis (
+        "id" integer primary key,
+        "emoji" text not null,
+        "short_name" text not null,
+        "category" integer not null,
+        "recent" integer not null,
+        "code" text not null,
+        unique ("emoji")
+    );
+
+create table
+    symbols (
+        "input" text not null,
+        "output" text not null,
+        "category" integer not null,
+        unique ("input", "output")
+    );
+
+create index emoji_category_index on emojis ("category", "id");
@@ -0,0 +1,12 @@
+drop table if exists emoji_keywords;
+
+create table
+    emoji_keywords (
+        "keyword" text not null,
+        "emoji" text not null,
+        "input_type" integer not null,
+        unique ("keyword", "emoji", "input_type"),
+        foreign key ("emoji") references "emojis" ("emoji")
+    );
+
+create index emoji_keyword_index on emoji_keywords ("keyword", "input_type");
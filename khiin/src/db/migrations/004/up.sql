@@ -0,0 +1,5 @@
+create index if not exists conversions_output_index on conversions (
+    "output",
+    "weight",
+    "input_id"
+);
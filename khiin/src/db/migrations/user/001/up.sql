@@ -0,0 +1,8 @@
+create table
+    learned_conversions (
+        "input" text not null,
+        "output" text not null,
+        "n" integer not null,
+        "last_used" integer not null,
+        unique ("input", "output")
+    );
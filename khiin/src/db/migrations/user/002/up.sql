@@ -0,0 +1,14 @@
+create table
+    user_words (
+        "input" text not null,
+        "output" text not null,
+        "created" integer not null,
+        unique ("input", "output")
+    );
+
+create table
+    recent_emojis (
+        "emoji" text primary key,
+        "n" integer not null,
+        "last_used" integer not null
+    );
@@ -1,10 +1,16 @@
 pub mod conversion;
+pub mod emoji;
 pub mod input;
 pub mod key_conversion;
 pub mod key_sequence;
 pub mod lookup;
+pub mod ngram;
+pub mod reading;
 
 pub use conversion::Conversion;
+pub use emoji::Emoji;
+pub use emoji::EmojiKeyword;
+pub use emoji::Symbol;
 pub use input::Input;
 pub use key_conversion::KeyConversion;
 pub use key_conversion::CaseType;
@@ -12,3 +18,5 @@ pub use key_sequence::generate_key_sequences;
 pub use key_sequence::InputType;
 pub use key_sequence::KeySequence;
 pub use lookup::InputLookup;
+pub use ngram::Bigram;
+pub use reading::Reading;
@@ -0,0 +1,84 @@
+use super::InputType;
+
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct Emoji {
+    pub id: i64,
+    pub emoji: String,
+    pub short_name: String,
+    pub category: i64,
+    pub recent: bool,
+    pub code: String,
+}
+
+/// Category 0 symbols are half width, category 1 are full width
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub input: String,
+    pub output: String,
+    pub category: i64,
+}
+
+/// A search term for an emoji. English terms are taken from the emoji's
+/// `short_name`, and Taiwanese terms are stored as key sequences so they can be
+/// typed the same way as any other word.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmojiKeyword {
+    pub keyword: String,
+    pub emoji: String,
+    pub input_type: InputType,
+}
+
+/// Strips a few common English suffixes so that e.g. "smile" and "smiling"
+/// share the keyword "smil".
+pub fn keyword_stem(word: &str) -> &str {
+    for suffix in ["ing", "ed", "es", "s", "e"] {
+        if let Some(stem) = word.strip_suffix(suffix) {
+            if stem.len() >= 3 {
+                return stem;
+            }
+        }
+    }
+
+    word
+}
+
+/// Each word of the short name, and its stem, in lowercase
+pub fn english_keywords(short_name: &str) -> Vec<String> {
+    let mut keywords: Vec<String> = Vec::new();
+
+    for word in short_name
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|w| !w.is_empty())
+    {
+        let word = word.to_ascii_lowercase();
+        let stem = keyword_stem(&word).to_string();
+        if !keywords.contains(&stem) {
+            keywords.push(stem);
+        }
+        if !keywords.contains(&word) {
+            keywords.push(word);
+        }
+    }
+
+    keywords
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_stems_english_keywords() {
+        assert_eq!(keyword_stem("smiling"), "smil");
+        assert_eq!(keyword_stem("smile"), "smil");
+        assert_eq!(keyword_stem("cats"), "cat");
+        assert_eq!(keyword_stem("red"), "red");
+        assert_eq!(
+            english_keywords("grinning face with smiling eyes"),
+            vec![
+                "grinn", "grinning", "fac", "face", "with", "smil", "smiling",
+                "eye", "eyes"
+            ]
+        );
+    }
+}
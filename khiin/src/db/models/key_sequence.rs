@@ -4,10 +4,15 @@ use itertools::Itertools;
 use crate::db::models::Input;
 use khiin_ji::poj_syl_to_key_sequences;
 use khiin_ji::poj_syl_to_key_sequences_oo;
+#[cfg(feature = "sqlite")]
 use rusqlite::types::FromSql;
+#[cfg(feature = "sqlite")]
 use rusqlite::types::FromSqlResult;
+#[cfg(feature = "sqlite")]
 use rusqlite::types::ToSqlOutput;
+#[cfg(feature = "sqlite")]
 use rusqlite::types::ValueRef;
+#[cfg(feature = "sqlite")]
 use rusqlite::ToSql;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -26,12 +31,14 @@ impl InputType {
     }
 }
 
+#[cfg(feature = "sqlite")]
 impl ToSql for InputType {
     fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
         Ok(ToSqlOutput::from(self.as_i64()))
     }
 }
 
+#[cfg(feature = "sqlite")]
 impl FromSql for InputType {
     fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
         let ty = match value.as_i64()? {
@@ -43,7 +50,7 @@ impl FromSql for InputType {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct KeySequence {
     pub input_id: i64,
     pub keys: String,
@@ -0,0 +1,11 @@
+/// Number of times `rgram` was seen directly after `lgram` in the corpus.
+///
+/// The grams are either conversion outputs, as stored in the `bigrams` table,
+/// or key sequences when the counts are projected onto inputs for the
+/// segmenter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bigram {
+    pub lgram: String,
+    pub rgram: String,
+    pub n: i64,
+}
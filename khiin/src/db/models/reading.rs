@@ -0,0 +1,8 @@
+/// A POJ `input` that converts to `output`, used to read Hanji or Hanlo text
+/// back out in Lomaji
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reading {
+    pub output: String,
+    pub input: String,
+    pub weight: i64,
+}
@@ -0,0 +1,6 @@
+insert into
+    bigrams (lgram, rgram, n)
+values
+    (?, ?, ?)
+on conflict (lgram, rgram) do update set
+    n = n + excluded.n
\ No newline at end of file
@@ -0,0 +1,4 @@
+insert or ignore into
+    emoji_keywords (keyword, emoji, input_type)
+values
+    (?, ?, ?)
\ No newline at end of file
@@ -0,0 +1,4 @@
+insert into
+    emojis (id, emoji, short_name, category, recent, code)
+values
+    (?, ?, ?, ?, ?, ?)
\ No newline at end of file
@@ -0,0 +1,4 @@
+insert into
+    metadata (key, value)
+values
+    (?, ?)
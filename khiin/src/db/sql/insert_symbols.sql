@@ -0,0 +1,4 @@
+insert into
+    symbols (input, output, category)
+values
+    (?, ?, ?)
\ No newline at end of file
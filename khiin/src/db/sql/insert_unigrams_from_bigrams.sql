@@ -0,0 +1,13 @@
+insert into
+    unigrams (gram, n)
+select
+    gram,
+    sum(n)
+from
+    (
+        select lgram as gram, n from bigrams
+        union all
+        select rgram as gram, n from bigrams
+    )
+group by
+    gram
\ No newline at end of file
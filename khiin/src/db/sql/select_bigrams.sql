@@ -0,0 +1,6 @@
+select
+    lgram,
+    rgram,
+    n
+from
+    bigrams
\ No newline at end of file
@@ -0,0 +1,12 @@
+select
+    id,
+    emoji,
+    short_name,
+    category,
+    recent,
+    code
+from
+    emojis
+order by
+    category,
+    id
\ No newline at end of file
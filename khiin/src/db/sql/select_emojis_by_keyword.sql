@@ -0,0 +1,28 @@
+select
+    e.id,
+    e.emoji,
+    e.short_name,
+    e.category,
+    e.recent,
+    e.code
+from
+    emoji_keywords k
+    join emojis e on e.emoji = k.emoji
+where
+    (
+        k.keyword like :query || '%'
+        or k.keyword like :stem || '%'
+    )
+    and (
+        k.input_type = :input_type
+        or k.input_type = 0
+    )
+group by
+    e.emoji
+order by
+    max(k.keyword = :query) desc,
+    max(k.keyword = :stem) desc,
+    min(length(k.keyword)),
+    e.id
+limit
+    :limit
\ No newline at end of file
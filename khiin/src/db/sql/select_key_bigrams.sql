@@ -0,0 +1,14 @@
+select
+    l.key_sequence as lgram,
+    r.key_sequence as rgram,
+    sum(b.n) as n
+from
+    bigrams b
+    join conversion_lookups l on l.output = b.lgram
+    join conversion_lookups r on r.output = b.rgram
+where
+    (l.input_type = :input_type or l.input_type = 0)
+    and (r.input_type = :input_type or r.input_type = 0)
+group by
+    l.key_sequence,
+    r.key_sequence
\ No newline at end of file
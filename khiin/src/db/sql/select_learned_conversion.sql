@@ -0,0 +1,8 @@
+select
+    n,
+    last_used
+from
+    learned_conversions
+where
+    input = :input
+    and output = :output
\ No newline at end of file
@@ -0,0 +1,7 @@
+select
+    key,
+    value
+from
+    metadata
+order by
+    key
@@ -0,0 +1,14 @@
+select
+    c.output,
+    i.input,
+    c.weight
+from
+    conversions c
+    join inputs i on i.id = c.input_id
+where
+    c.output in ({vars})
+order by
+    length(c.output) desc,
+    c.output,
+    c.weight desc,
+    i.input
@@ -0,0 +1,9 @@
+select
+    emoji
+from
+    recent_emojis
+order by
+    last_used desc,
+    n desc
+limit
+    :limit
\ No newline at end of file
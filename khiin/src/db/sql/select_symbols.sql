@@ -0,0 +1,9 @@
+select
+    input,
+    output,
+    category
+from
+    symbols
+order by
+    category,
+    rowid
\ No newline at end of file
@@ -0,0 +1,7 @@
+insert into
+    learned_conversions (input, output, n, last_used)
+values
+    (:input, :output, 1, strftime('%s', 'now'))
+on conflict (input, output) do update set
+    n = n + 1,
+    last_used = excluded.last_used
\ No newline at end of file
@@ -0,0 +1,7 @@
+insert into
+    recent_emojis (emoji, n, last_used)
+values
+    (:emoji, 1, strftime('%s', 'now'))
+on conflict (emoji) do update set
+    n = n + 1,
+    last_used = excluded.last_used
\ No newline at end of file
@@ -0,0 +1,185 @@
+use std::ops::Deref;
+use std::path::Path;
+
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use rusqlite::named_params;
+use rusqlite::Connection;
+use rusqlite::OptionalExtension;
+use rusqlite_migration::Migrations;
+use rusqlite_migration::M;
+
+use super::backend::LearnedStats;
+use super::backend::UserData;
+use super::database::ensure_dirs;
+use super::models::KeyConversion;
+
+static USER_MIGRATIONS: Lazy<Migrations> = Lazy::new(|| {
+    Migrations::new(vec![
+        M::up(include_str!("migrations/user/001/up.sql")),
+        M::up(include_str!("migrations/user/002/up.sql")),
+    ])
+});
+
+/// Writable store for data learned from the user, kept in its own file
+/// so that regenerating the bundled dictionary never touches it.
+pub struct UserDatabase {
+    conn: Connection,
+}
+
+impl Deref for UserDatabase {
+    type Target = Connection;
+
+    fn deref(&self) -> &Self::Target {
+        &self.conn
+    }
+}
+
+impl UserDatabase {
+    pub fn new<P>(file: P) -> Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        ensure_dirs(&file.as_ref().to_path_buf())?;
+        let conn = Connection::open(file)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        Self::from_connection(conn)
+    }
+
+    pub fn new_in_memory() -> Result<Self> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(mut conn: Connection) -> Result<Self> {
+        USER_MIGRATIONS.to_latest(&mut conn)?;
+        Ok(Self { conn })
+    }
+
+    fn clear_table(&self, table: &str) -> Result<usize> {
+        let sql = format!("delete from {}", table);
+        Ok(self.conn.execute(&sql, [])?)
+    }
+}
+
+impl UserData for UserDatabase {
+    fn learn_conversion(&self, conversion: &KeyConversion) -> Result<()> {
+        let sql = include_str!("sql/upsert_learned_conversion.sql");
+        let mut stmt = self.conn.prepare_cached(sql)?;
+        stmt.execute(named_params! {
+            ":input": conversion.input,
+            ":output": conversion.output,
+        })?;
+        Ok(())
+    }
+
+    fn learned_stats(
+        &self,
+        conversion: &KeyConversion,
+    ) -> Result<LearnedStats> {
+        let sql = include_str!("sql/select_learned_conversion.sql");
+        let mut stmt = self.conn.prepare_cached(sql)?;
+        let stats = stmt
+            .query_row(
+                named_params! {
+                    ":input": conversion.input,
+                    ":output": conversion.output,
+                },
+                |row| {
+                    Ok(LearnedStats {
+                        n: row.get("n")?,
+                        last_used: row.get("last_used")?,
+                    })
+                },
+            )
+            .optional()?;
+
+        Ok(stats.unwrap_or_default())
+    }
+
+    fn record_emoji(&self, emoji: &str) -> Result<()> {
+        let sql = include_str!("sql/upsert_recent_emoji.sql");
+        let mut stmt = self.conn.prepare_cached(sql)?;
+        stmt.execute(named_params! { ":emoji": emoji })?;
+        Ok(())
+    }
+
+    fn select_recent_emojis(&self, limit: usize) -> Result<Vec<String>> {
+        let sql = include_str!("sql/select_recent_emojis.sql");
+        let mut stmt = self.conn.prepare_cached(sql)?;
+        let mut rows = stmt.query(named_params! { ":limit": limit as i64 })?;
+
+        let mut result = Vec::new();
+        while let Some(row) = rows.next()? {
+            result.push(row.get("emoji")?);
+        }
+
+        Ok(result)
+    }
+
+    fn clear_learned_conversions(&self) -> Result<usize> {
+        self.clear_table("learned_conversions")
+    }
+
+    fn clear_user_words(&self) -> Result<usize> {
+        self.clear_table("user_words")
+    }
+
+    fn clear_recent_emojis(&self) -> Result<usize> {
+        self.clear_table("recent_emojis")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::*;
+
+    #[test]
+    fn it_learns_conversions() {
+        let db = UserDatabase::new_in_memory().unwrap();
+        let conv = mock_conversion("hó", "好");
+        assert_eq!(db.learned_stats(&conv).unwrap().n, 0);
+        db.learn_conversion(&conv).unwrap();
+        db.learn_conversion(&conv).unwrap();
+        assert_eq!(db.learned_stats(&conv).unwrap().n, 2);
+    }
+
+    #[test]
+    fn it_reranks_learned_conversions_first() {
+        let db = UserDatabase::new_in_memory().unwrap();
+        let a = mock_conversion("hó", "好");
+        let b = mock_conversion("hó", "hó");
+        let c = mock_conversion("hó", "號");
+        db.learn_conversion(&c).unwrap();
+        let mut convs = vec![a, b, c];
+        db.rerank(&mut convs).unwrap();
+        assert_eq!(convs[0].output, "號");
+        assert_eq!(convs[1].output, "好");
+        assert_eq!(convs[2].output, "hó");
+    }
+
+    #[test]
+    fn it_clears_learned_conversions() {
+        let db = UserDatabase::new_in_memory().unwrap();
+        db.learn_conversion(&mock_conversion("hó", "好")).unwrap();
+        db.learn_conversion(&mock_conversion("hó", "號")).unwrap();
+        db.record_emoji("😀").unwrap();
+        assert_eq!(db.clear_learned_conversions().unwrap(), 2);
+        assert_eq!(db.clear_learned_conversions().unwrap(), 0);
+        assert_eq!(db.clear_user_words().unwrap(), 0);
+        assert_eq!(db.clear_recent_emojis().unwrap(), 1);
+    }
+
+    #[test]
+    fn it_records_recent_emojis() {
+        let db = UserDatabase::new_in_memory().unwrap();
+        db.record_emoji("😀").unwrap();
+        db.record_emoji("😃").unwrap();
+        db.execute("update recent_emojis set last_used = 1", [])
+            .unwrap();
+        db.record_emoji("😃").unwrap();
+        let recent = db.select_recent_emojis(10).unwrap();
+        assert_eq!(recent, vec!["😃", "😀"]);
+        assert_eq!(db.select_recent_emojis(1).unwrap().len(), 1);
+    }
+}
@@ -1,69 +1,338 @@
+pub mod commands;
+pub(crate) mod error;
+
+use std::collections::HashMap;
 use std::fmt::Debug;
+use std::panic;
+use std::panic::AssertUnwindSafe;
 use std::path::Path;
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+#[cfg(target_arch = "wasm32")]
+use web_time::Instant;
 
 use anyhow::anyhow;
 use anyhow::Error;
 use anyhow::Result;
 
+use protobuf::Enum;
 use protobuf::Message;
 
+use khiin_ji::punctuation::auto_closing_pair;
+use khiin_ji::punctuation::is_punctuation_key;
+
+use khiin_protos::command::emoji_category::Category;
 use khiin_protos::command::*;
+use khiin_protos::config::AppConfig;
 use khiin_protos::config::AppInputMode;
 use khiin_protos::config::AppOutputMode;
 use khiin_protos::config::AppKhinMode;
 use khiin_protos::config::BoolValue;
+use khiin_protos::config::DefaultPunctuation;
 
 use crate::buffer::BufferMgr;
 use crate::config::Config;
 use crate::config::InputMode;
+use crate::config::KeyConfig;
 use crate::config::OutputMode;
 use crate::config::KhinMode;
+use crate::config::PunctWidth;
 use crate::config::ToneMode;
 use crate::data::dictionary::Dictionary;
+use crate::data::SharedDictionary;
+#[cfg(feature = "sqlite")]
 use crate::db::Database;
+use crate::db::DictionaryBackend;
+#[cfg(feature = "sqlite")]
+use crate::db::OpenMode;
+#[cfg(feature = "sqlite")]
+use crate::db::UserDatabase;
+use crate::engine::error::error_response;
+use crate::engine::error::CommandError;
+use crate::input::parser::EMOJI_TRIGGER;
+use crate::input::reverse;
+use crate::input::reverse::ReadingSegment;
 
 pub struct Engine {
+    /// Buffer of the session the current request applies to
     buffer_mgr: BufferMgr,
+    session_id: u64,
+    last_used: Instant,
+
+    /// All other sessions, which share `inner` with the active one
+    sessions: HashMap<u64, Session>,
+    next_session_id: u64,
+
+    /// Dictionaries for every tone mode, `inner.dict` is the active one
+    dicts: SharedDictionary,
     inner: EngInner,
 }
 
+struct Session {
+    buffer_mgr: BufferMgr,
+    last_used: Instant,
+}
+
 pub(crate) struct EngInner {
-    pub(crate) db: Database,
-    pub(crate) dict: Dictionary,
+    pub(crate) db: Box<dyn DictionaryBackend>,
+    pub(crate) dict: Arc<Dictionary>,
     pub(crate) conf: Config,
 }
 
+/// Size of the recently used section returned by CMD_LIST_EMOJIS
+const MAX_RECENT_EMOJIS: usize = 40;
+
+/// File name of the user data store, kept next to the dictionary database
+#[cfg(feature = "sqlite")]
+pub const USER_DB_FILENAME: &str = "khiin_user.db";
+
+/// Session used by requests that do not specify one, never expires
+pub const DEFAULT_SESSION_ID: u64 = 0;
+
+/// Sessions that receive no requests for this long are dropped
+const SESSION_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+
+/// Version of the protocol in `command.proto`, reported by
+/// CMD_GET_ENGINE_INFO
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Commands that are answered with UNSUPPORTED_COMMAND
+const UNSUPPORTED_COMMANDS: [CommandType; 4] = [
+    CommandType::CMD_UNSPECIFIED,
+    CommandType::CMD_DISABLE,
+    CommandType::CMD_ENABLE,
+    CommandType::CMD_SHUTDOWN,
+];
+
+/// AppConfig fields applied by CMD_SET_CONFIG, checked against
+/// `config.proto` by `it_lists_every_applied_config_key`
+const CONFIG_KEYS: [&str; 20] = [
+    "input_mode",
+    "output_mode",
+    "khin_mode",
+    "default_punctuation",
+    "telex_enabled",
+    "uppercase_nasal",
+    "key_config.telex_t2",
+    "key_config.telex_t3",
+    "key_config.telex_t5",
+    "key_config.telex_t6",
+    "key_config.telex_t7",
+    "key_config.telex_t8",
+    "key_config.telex_t9",
+    "key_config.telex_khin",
+    "key_config.alt_hyphen",
+    "key_config.nasal",
+    "key_config.dot_above_right",
+    "key_config.dots_below",
+    "key_config.enable_uppercase_nasal",
+    "key_config.use_digits_as_fallback",
+];
+
+#[cfg(feature = "sqlite")]
+fn user_db_path<P>(filename: P) -> PathBuf
+where
+    P: AsRef<Path>,
+{
+    filename
+        .as_ref()
+        .parent()
+        .map(|dir| dir.join(USER_DB_FILENAME))
+        .unwrap_or_else(|| PathBuf::from(USER_DB_FILENAME))
+}
+
+#[cfg(feature = "sqlite")]
+fn open_database<P, Q>(
+    filename: P,
+    user_filename: Q,
+    mode: OpenMode,
+) -> Option<Database>
+where
+    P: AsRef<Path> + Debug + Clone,
+    Q: AsRef<Path> + Debug,
+{
+    let mut db = match Database::new(filename.clone(), mode) {
+        Ok(db) => db,
+        Err(e) => {
+            log::error!("Unable to open database {:?}: {}", filename, e);
+            return None;
+        },
+    };
+    log::debug!("Database loaded from: {:?} ({:?})", filename, mode);
+
+    match UserDatabase::new(&user_filename) {
+        Ok(user_db) => {
+            db.set_user_db(user_db);
+            log::debug!("User database loaded from: {:?}", user_filename);
+        },
+        Err(e) => log::error!(
+            "Unable to open user database {:?}, learned data will not be \
+             saved: {}",
+            user_filename,
+            e
+        ),
+    }
+
+    Some(db)
+}
+
 impl Engine {
+    #[cfg(feature = "sqlite")]
     pub fn new<P>(filename: P) -> Option<Engine>
     where
         P: AsRef<Path> + Debug + Clone,
     {
-        let db = Database::new(filename.clone()).ok()?;
-        log::debug!("Database loaded from: {:?}", filename);
-        let dict = Dictionary::new(&db, ToneMode::Numeric).ok()?;
+        let user_filename = user_db_path(&filename);
+        Engine::with_user_db(filename, user_filename)
+    }
+
+    #[cfg(feature = "sqlite")]
+    pub fn with_user_db<P, Q>(filename: P, user_filename: Q) -> Option<Engine>
+    where
+        P: AsRef<Path> + Debug + Clone,
+        Q: AsRef<Path> + Debug,
+    {
+        Engine::with_open_mode(filename, user_filename, OpenMode::Memory)
+    }
+
+    /// Opens the database at `filename` with `mode`. With
+    /// `OpenMode::ReadOnly`, `user_filename` should be in a writable
+    /// location, since the directory of `filename` may not be.
+    ///
+    /// Uses the precompiled index next to `filename`, such as `khiin.idx`,
+    /// when it is present and up to date.
+    #[cfg(feature = "sqlite")]
+    pub fn with_open_mode<P, Q>(
+        filename: P,
+        user_filename: Q,
+        mode: OpenMode,
+    ) -> Option<Engine>
+    where
+        P: AsRef<Path> + Debug + Clone,
+        Q: AsRef<Path> + Debug,
+    {
+        let db = open_database(filename.clone(), user_filename, mode)?;
+        let dicts = SharedDictionary::for_database(&filename, &db);
+        let engine = Engine::from_parts(Box::new(db), dicts).ok();
         log::debug!("Dictionary initialized");
+        engine
+    }
+
+    /// Creates an engine that reuses an already loaded dictionary instead
+    /// of building its own. The engine still opens its own connections to
+    /// `filename` and to the user database next to it.
+    #[cfg(feature = "sqlite")]
+    pub fn with_dictionary<P>(
+        filename: P,
+        dict: &SharedDictionary,
+    ) -> Option<Engine>
+    where
+        P: AsRef<Path> + Debug + Clone,
+    {
+        let user_filename = user_db_path(&filename);
+        let db = open_database(filename, user_filename, OpenMode::Memory)?;
+        Engine::from_parts(Box::new(db), dict.clone()).ok()
+    }
 
-        Some(Engine {
+    /// Creates an engine on any dictionary backend, such as a
+    /// `MemoryDatabase` where SQLite is not available
+    pub fn with_backend<B>(db: B) -> Option<Engine>
+    where
+        B: DictionaryBackend + 'static,
+    {
+        let db = Box::new(db);
+        let engine = Engine::from_parts(db, SharedDictionary::default()).ok();
+        log::debug!("Dictionary initialized");
+        engine
+    }
+
+    fn from_parts(
+        db: Box<dyn DictionaryBackend>,
+        dicts: SharedDictionary,
+    ) -> Result<Engine> {
+        let conf = Config::new();
+        let dict = dicts.get(db.as_ref(), conf.tone_mode())?;
+        Ok(Engine {
             buffer_mgr: BufferMgr::new(),
-            inner: EngInner {
-                db,
-                dict,
-                conf: Config::new(),
-            },
+            session_id: DEFAULT_SESSION_ID,
+            last_used: Instant::now(),
+            sessions: HashMap::new(),
+            next_session_id: DEFAULT_SESSION_ID + 1,
+            dicts,
+            inner: EngInner { db, dict, conf },
         })
     }
 
+    /// Handles a serialized `Command` and returns it with its `Response`.
+    /// Failures, including malformed input, are reported in the response.
     pub fn send_command_bytes(&mut self, bytes: &[u8]) -> Result<Vec<u8>> {
-        let mut cmd = Command::parse_from_bytes(bytes)?;
-        let req = cmd.request.clone().unwrap();
+        let cmd = match Command::parse_from_bytes(bytes) {
+            Ok(cmd) => self.send_command(cmd),
+            Err(e) => {
+                let err = CommandError::malformed(e.to_string()).into();
+                Command {
+                    response: Some(error_response(&err)).into(),
+                    ..Default::default()
+                }
+            },
+        };
 
-        let res = match req.type_.enum_value_or_default() {
+        cmd.write_to_bytes()
+            .map_err(|_| Error::msg("Failed to write protobuf bytes"))
+    }
+
+    /// Handles `cmd` and returns it with its `Response` filled in. Failures
+    /// are reported in the response, see `commands` for building requests.
+    pub fn send_command(&mut self, mut cmd: Command) -> Command {
+        let res = self.handle_command(&cmd).unwrap_or_else(|e| {
+            log::error!("Command failed: {:#}", e);
+            error_response(&e)
+        });
+        cmd.response = Some(res).into();
+        cmd
+    }
+
+    /// Reads Hanji or mixed Hanlo `text` out in POJ. The text is split into
+    /// the longest words found in the dictionary, each with its readings,
+    /// most likely first. Also available as CMD_HANJI_TO_POJ.
+    pub fn hanji_to_poj(&self, text: &str) -> Result<Vec<ReadingSegment>> {
+        reverse::hanji_to_poj(self.inner.db.as_ref(), text)
+    }
+
+    /// Runs the request in `cmd`, turning any panic into an error so that
+    /// no input can bring down the host app
+    fn handle_command(&mut self, cmd: &Command) -> Result<Response> {
+        let req = cmd
+            .request
+            .as_ref()
+            .ok_or(CommandError::malformed("Command has no request"))?
+            .clone();
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            self.handle_request(req)
+        }));
+        result.unwrap_or_else(|panic| {
+            self.buffer_mgr.reset()?;
+            Err(CommandError::internal(panic).into())
+        })
+    }
+
+    fn handle_request(&mut self, req: Request) -> Result<Response> {
+        let cmd = req.type_.enum_value().map_err(|value| {
+            CommandError::new(
+                ErrorCode::UNSUPPORTED_COMMAND,
+                format!("Unknown command type: {}", value),
+            )
+        })?;
+        self.switch_session(req.session_id)?;
+
+        match cmd {
             CommandType::CMD_UNSPECIFIED => {
-                let mut res = Response::new();
-                let mut cmd = Command::new();
-                res.error = ErrorCode::FAIL.into();
-                Ok(res)
+                Err(CommandError::unsupported(cmd).into())
             },
             CommandType::CMD_SEND_KEY => self.on_send_key(req),
             CommandType::CMD_REVERT => self.on_revert(req),
@@ -85,22 +354,131 @@ impl Engine {
             CommandType::CMD_LIST_EMOJIS => self.on_list_emojis(req),
             CommandType::CMD_RESET_USER_DATA => self.on_reset_user_data(req),
             CommandType::CMD_SHUTDOWN => self.on_shutdown(req),
-        };
+            CommandType::CMD_SELECT_EMOJI => self.on_select_emoji(req),
+            CommandType::CMD_CREATE_SESSION => self.on_create_session(req),
+            CommandType::CMD_DESTROY_SESSION => self.on_destroy_session(req),
+            CommandType::CMD_GET_ENGINE_INFO => self.on_get_engine_info(req),
+            CommandType::CMD_HANJI_TO_POJ => self.on_hanji_to_poj(req),
+        }
+    }
 
-        if let Ok(res) = res {
-            cmd.response = Some(res).into();
+    /// Makes `id` the active session, storing away the previous one
+    fn switch_session(&mut self, id: u64) -> Result<()> {
+        let now = Instant::now();
+        self.expire_sessions(now);
+
+        if id != self.session_id {
+            let session = self
+                .sessions
+                .remove(&id)
+                .ok_or(CommandError::unknown_session(id))?;
+            let previous = Session {
+                buffer_mgr: std::mem::replace(
+                    &mut self.buffer_mgr,
+                    session.buffer_mgr,
+                ),
+                last_used: self.last_used,
+            };
+            self.sessions.insert(self.session_id, previous);
+            self.session_id = id;
+        }
+
+        self.last_used = now;
+        Ok(())
+    }
+
+    fn expire_sessions(&mut self, now: Instant) {
+        self.sessions.retain(|&id, session| {
+            id == DEFAULT_SESSION_ID
+                || now.duration_since(session.last_used) < SESSION_TIMEOUT
+        });
+    }
+
+    /// Clears the composition and commit history of every session, e.g.
+    /// after the input mode has changed, so that a commit made in the old
+    /// mode cannot be reverted into the new one
+    fn reset_sessions(&mut self) -> Result<()> {
+        self.buffer_mgr.reset()?;
+        self.buffer_mgr.clear_history();
+        for session in self.sessions.values_mut() {
+            session.buffer_mgr.reset()?;
+            session.buffer_mgr.clear_history();
+        }
+        Ok(())
+    }
+
+    fn on_create_session(&mut self, req: Request) -> Result<Response> {
+        let id = self.next_session_id;
+        self.next_session_id += 1;
+        self.sessions.insert(
+            id,
+            Session {
+                buffer_mgr: BufferMgr::new(),
+                last_used: Instant::now(),
+            },
+        );
+
+        let mut response = Response::new();
+        response.session_id = id;
+        Ok(response)
+    }
+
+    /// Drops the session in `req.session_id`. The default session is only
+    /// reset.
+    fn on_destroy_session(&mut self, req: Request) -> Result<Response> {
+        let id = req.session_id;
+        if id == DEFAULT_SESSION_ID {
+            self.buffer_mgr.reset()?;
+            self.buffer_mgr.clear_history();
         } else {
-            let mut res = Response::default();
-            res.error = ErrorCode::FAIL.into();
-            cmd.response = Some(res).into();
+            self.switch_session(DEFAULT_SESSION_ID)?;
+            self.sessions.remove(&id);
         }
 
-        cmd.write_to_bytes()
-            .map_err(|_| Error::msg("Failed to write protobuf bytes"))
+        Ok(Response::new())
     }
 
     fn on_send_key(&mut self, req: Request) -> Result<Response> {
         log::debug!("Engine::on_send_key");
+        if !self.would_consume(&req.key_event) {
+            return Ok(Response::default());
+        }
+
+        let mut response = self.handle_key(req)?;
+        response.consumable = true;
+        Ok(response)
+    }
+
+    /// Whether `on_send_key` would act on `key` in the current state. Keys
+    /// with Ctrl or Alt held, and keys that cannot start a composition while
+    /// the buffer is empty, are left to the application.
+    fn would_consume(&self, key: &KeyEvent) -> bool {
+        if has_modifier(key, ModifierKey::MODK_CTRL)
+            || has_modifier(key, ModifierKey::MODK_ALT)
+        {
+            return false;
+        }
+
+        let composing = self.buffer_mgr.edit_state() != EditState::ES_EMPTY;
+        match key.special_key.enum_value_or_default() {
+            SpecialKey::SK_NONE => match ascii_char_from_i32(key.key_code) {
+                Some(ch) => composing || self.starts_composition(ch),
+                None => false,
+            },
+            SpecialKey::SK_PGUP | SpecialKey::SK_PGDN => false,
+            _ => composing,
+        }
+    }
+
+    /// Keys that begin a new composition when nothing is being typed. Digits
+    /// are left out, since numeric tone keys only follow a syllable.
+    fn starts_composition(&self, ch: char) -> bool {
+        ch.is_ascii_alphabetic()
+            || ch == EMOJI_TRIGGER
+            || is_punctuation_key(ch)
+    }
+
+    fn handle_key(&mut self, req: Request) -> Result<Response> {
         match req.key_event.special_key.enum_value_or_default() {
             SpecialKey::SK_NONE => {
                 let ch = ascii_char_from_i32(req.key_event.key_code);
@@ -124,7 +502,7 @@ impl Engine {
                     } else {
                         self.buffer_mgr.insert(&self.inner, ch)?;
                         if self.buffer_mgr.edit_state() == EditState::ES_EMPTY {
-                            return self.on_commit_all(req);
+                            return self.on_commit_direct(req);
                         }
                     }
                 }
@@ -148,7 +526,9 @@ impl Engine {
             SpecialKey::SK_ENTER => {
                 return self.on_enter(req);
             },
-            SpecialKey::SK_ESC => {},
+            SpecialKey::SK_ESC => {
+                self.buffer_mgr.revert_composition(&self.inner)?;
+            },
             SpecialKey::SK_BACKSPACE => {
                 self.buffer_mgr.pop(&self.inner)?;
             },
@@ -163,8 +543,32 @@ impl Engine {
                     self.buffer_mgr.show_next_page_candidate(&self.inner)?;
                 }
             },
-            SpecialKey::SK_LEFT => {},
-            SpecialKey::SK_RIGHT => {},
+            SpecialKey::SK_LEFT => {
+                if (req.key_event.modifier_keys.contains(
+                    &protobuf::EnumOrUnknown::from_i32(
+                        ModifierKey::MODK_SHIFT as i32,
+                    ),
+                )) {
+                    self.buffer_mgr.shrink_focused_segment(&self.inner)?;
+                } else if self.buffer_mgr.is_focused() {
+                    self.buffer_mgr.focus_prev_segment(&self.inner)?;
+                } else {
+                    self.buffer_mgr.move_caret_left(&self.inner)?;
+                }
+            },
+            SpecialKey::SK_RIGHT => {
+                if (req.key_event.modifier_keys.contains(
+                    &protobuf::EnumOrUnknown::from_i32(
+                        ModifierKey::MODK_SHIFT as i32,
+                    ),
+                )) {
+                    self.buffer_mgr.grow_focused_segment(&self.inner)?;
+                } else if self.buffer_mgr.is_focused() {
+                    self.buffer_mgr.focus_next_segment(&self.inner)?;
+                } else {
+                    self.buffer_mgr.move_caret_right(&self.inner)?;
+                }
+            },
             SpecialKey::SK_UP => {
                 self.buffer_mgr.focus_prev_candidate(&self.inner)?;
             },
@@ -173,9 +577,15 @@ impl Engine {
             },
             SpecialKey::SK_PGUP => {},
             SpecialKey::SK_PGDN => {},
-            SpecialKey::SK_HOME => {},
-            SpecialKey::SK_END => {},
-            SpecialKey::SK_DEL => {},
+            SpecialKey::SK_HOME => {
+                self.buffer_mgr.move_caret_home(&self.inner)?;
+            },
+            SpecialKey::SK_END => {
+                self.buffer_mgr.move_caret_end(&self.inner)?;
+            },
+            SpecialKey::SK_DEL => {
+                self.buffer_mgr.erase_forward(&self.inner)?;
+            },
         };
 
         let mut response = Response::default();
@@ -183,22 +593,51 @@ impl Engine {
         Ok(response)
     }
 
-    fn on_revert(&self, req: Request) -> Result<Response> {
-        Err(anyhow!("Not implemented"))
+    /// Reverts converted segments to their raw keys, or if nothing is being
+    /// composed, brings the last commit back into the composition
+    fn on_revert(&mut self, req: Request) -> Result<Response> {
+        let mut reverted_text = String::new();
+        if self.buffer_mgr.edit_state() == EditState::ES_EMPTY {
+            reverted_text = self.buffer_mgr.revert_last_commit(&self.inner)?;
+        } else {
+            self.buffer_mgr.revert_composition(&self.inner)?;
+        }
+
+        let mut response = Response::default();
+        self.attach_buffer_data(&mut response)?;
+        response.reverted_text = reverted_text;
+        Ok(response)
     }
 
     fn on_reset(&mut self, req: Request) -> Result<Response> {
         self.buffer_mgr.reset()?;
+        self.buffer_mgr.clear_history();
         Ok(Response::new())
     }
 
     fn on_commit_all(&mut self, req: Request) -> Result<Response> {
+        let raw_input = self.buffer_mgr.raw_text();
         let committed_text = self.buffer_mgr.commit_all(&self.inner)?;
+        self.finish_commit_all(raw_input, committed_text)
+    }
+
+    /// Commits the composition ended by a direct output punctuation key
+    fn on_commit_direct(&mut self, req: Request) -> Result<Response> {
+        let raw_input = self.buffer_mgr.raw_text();
+        let committed_text = self.buffer_mgr.commit_direct(&self.inner)?;
+        self.finish_commit_all(raw_input, committed_text)
+    }
+
+    fn finish_commit_all(
+        &mut self,
+        raw_input: String,
+        committed_text: String,
+    ) -> Result<Response> {
         let mut response = Response::default();
         self.attach_buffer_data(&mut response)?;
-        response.committed_text = committed_text;
-        response.committed = true;
         self.buffer_mgr.reset()?;
+        self.set_committed_text(&mut response, raw_input, committed_text);
+        response.committed = true;
         if let Some(ref mut p) = response.preedit.as_mut() {
             p.caret = 0;
             p.focused_caret = 0;
@@ -207,6 +646,30 @@ impl Engine {
         Ok(response)
     }
 
+    /// Sets the text committed from `raw_input`. An opening bracket or quote
+    /// at the end is committed with its closing half, and the caret is left
+    /// between the two.
+    fn set_committed_text(
+        &mut self,
+        response: &mut Response,
+        raw_input: String,
+        mut committed_text: String,
+    ) {
+        self.buffer_mgr.remember_commit(raw_input, &committed_text);
+
+        let close = committed_text
+            .chars()
+            .last()
+            .and_then(|ch| auto_closing_pair(&ch.to_string()));
+        if let Some(close) = close {
+            committed_text.push_str(close);
+            response.chars_after_caret = close.chars().count() as u32;
+        }
+
+        self.buffer_mgr.track_punct_pairs(&committed_text);
+        response.committed_text = committed_text;
+    }
+
     fn on_enter(&mut self, req: Request) -> Result<Response> {
         if (self.inner.conf.input_mode() == InputMode::Classic) {
             // check is candidate is action
@@ -223,19 +686,28 @@ impl Engine {
     fn on_commit(&mut self, req: Request) -> Result<Response> {
         if (self.inner.conf.input_mode() == InputMode::Classic) {
             // only classic mode need comosite remainder
+            let raw_input = self.buffer_mgr.raw_text();
             let committed_text = self
                 .buffer_mgr
                 .commit_candidate_and_comosite_remainder(&self.inner)?;
             let mut response = Response::default();
+            self.set_committed_text(&mut response, raw_input, committed_text);
             self.attach_buffer_data(&mut response)?;
-            response.committed_text = committed_text;
             response.committed = true;
             return Ok(response);
         }
         let mut response = Response::new();
         response.committed = true;
         self.attach_preedit(&mut response)?;
+        let committed_text: String = response
+            .preedit
+            .segments
+            .iter()
+            .map(|s| s.value.as_str())
+            .collect();
+        let raw_input = self.buffer_mgr.raw_text();
         self.buffer_mgr.reset()?;
+        self.set_committed_text(&mut response, raw_input, committed_text);
         if let Some(ref mut p) = response.preedit.as_mut() {
             p.caret = 0;
             p.focused_caret = 0;
@@ -244,16 +716,42 @@ impl Engine {
         Ok(response)
     }
 
-    fn on_select_candidate(&self, req: Request) -> Result<Response> {
-        Err(anyhow!("Not implemented"))
+    /// Commits the candidate with `req.candidate_id`. In continuous mode,
+    /// this only fixes the focused segment until the last one is selected.
+    fn on_select_candidate(&mut self, req: Request) -> Result<Response> {
+        let id = candidate_id(&req)?;
+        self.buffer_mgr.focus_candidate_by_id(&self.inner, id)?;
+
+        if self.buffer_mgr.focused_candidate_is_action() {
+            self.buffer_mgr.expand_candidate(&self.inner)?;
+        } else if self.inner.conf.input_mode() != InputMode::Continuous
+            || self.buffer_mgr.focused_segment_is_last()
+        {
+            return self.on_commit(req);
+        } else {
+            self.buffer_mgr.focus_next_segment(&self.inner)?;
+        }
+
+        let mut response = Response::default();
+        self.attach_buffer_data(&mut response)?;
+        Ok(response)
     }
 
-    fn on_focus_candidate(&self, req: Request) -> Result<Response> {
-        Err(anyhow!("Not implemented"))
+    fn on_focus_candidate(&mut self, req: Request) -> Result<Response> {
+        let id = candidate_id(&req)?;
+        self.buffer_mgr.focus_candidate_by_id(&self.inner, id)?;
+
+        if self.buffer_mgr.focused_candidate_is_action() {
+            self.buffer_mgr.expand_candidate(&self.inner)?;
+        }
+
+        let mut response = Response::default();
+        self.attach_buffer_data(&mut response)?;
+        Ok(response)
     }
 
     fn on_switch_input_mode(&mut self, req: Request) -> Result<Response> {
-        self.buffer_mgr.reset();
+        self.reset_sessions()?;
         match req.config.input_mode.enum_value_or_default() {
             AppInputMode::CONTINUOUS => {
                 self.inner.conf.set_input_mode(InputMode::Continuous)
@@ -269,7 +767,7 @@ impl Engine {
     }
 
     fn on_switch_output_mode(&mut self, req: Request) -> Result<Response> {
-        self.buffer_mgr.reset();
+        self.reset_sessions()?;
         match req.config.output_mode.enum_value_or_default() {
             AppOutputMode::LOMAJI => {
                 self.inner.conf.set_output_mode(OutputMode::Lomaji)
@@ -281,20 +779,33 @@ impl Engine {
         Ok(Response::new())
     }
 
-    fn on_place_cursor(&self, req: Request) -> Result<Response> {
-        Err(anyhow!("Not implemented"))
+    fn on_place_cursor(&mut self, req: Request) -> Result<Response> {
+        let caret = req.cursor_position.max(0) as usize;
+        self.buffer_mgr.place_caret(&self.inner, caret)?;
+        let mut response = Response::default();
+        self.attach_buffer_data(&mut response)?;
+        Ok(response)
     }
 
     fn on_disable(&self, req: Request) -> Result<Response> {
-        Err(anyhow!("Not implemented"))
+        let cmd = req.type_.enum_value_or_default();
+        Err(CommandError::unsupported(cmd).into())
     }
 
     fn on_enable(&self, req: Request) -> Result<Response> {
-        Err(anyhow!("Not implemented"))
+        let cmd = req.type_.enum_value_or_default();
+        Err(CommandError::unsupported(cmd).into())
     }
 
     fn on_set_config(&mut self, req: Request) -> Result<Response> {
-        self.buffer_mgr.reset();
+        let key_config =
+            key_config_from_proto(self.inner.conf.key_config(), &req.config)
+                .map_err(CommandError::invalid_config)?;
+        self.inner
+            .conf
+            .set_key_config(key_config)
+            .map_err(CommandError::invalid_config)?;
+        self.reset_sessions()?;
         match req.config.input_mode.enum_value_or_default() {
             AppInputMode::CONTINUOUS => {
                 self.inner.conf.set_input_mode(InputMode::Continuous)
@@ -328,6 +839,18 @@ impl Engine {
             },
         }
 
+        match req.config.default_punctuation.enum_value_or_default() {
+            DefaultPunctuation::PUNCT_UNSPECIFIED => {
+                self.inner.conf.set_punct_width(PunctWidth::Auto)
+            },
+            DefaultPunctuation::PUNCT_HALF => {
+                self.inner.conf.set_punct_width(PunctWidth::Half)
+            },
+            DefaultPunctuation::PUNCT_WHOLE => {
+                self.inner.conf.set_punct_width(PunctWidth::Whole)
+            },
+        }
+
         // let mut telex_enabled = BoolValue::new();
         if let Some(telex_enabled) = req.config.telex_enabled.as_ref() {
             if telex_enabled.value {
@@ -336,24 +859,173 @@ impl Engine {
                 self.inner.conf.set_tone_mode(ToneMode::Numeric)
             }
         }
+        self.inner.dict = self
+            .dicts
+            .get(self.inner.db.as_ref(), self.inner.conf.tone_mode())?;
 
         Ok(Response::new())
     }
 
     fn on_test_send_key(&self, req: Request) -> Result<Response> {
-        Err(anyhow!("Not implemented"))
+        Ok(Response {
+            consumable: self.would_consume(&req.key_event),
+            ..Default::default()
+        })
     }
 
     fn on_list_emojis(&self, req: Request) -> Result<Response> {
-        Err(anyhow!("Not implemented"))
+        let emojis = self.inner.db.select_emojis()?;
+        let symbols = self.inner.db.select_symbols()?;
+        let used = self
+            .inner
+            .db
+            .user_data()
+            .select_recent_emojis(MAX_RECENT_EMOJIS)?;
+
+        let mut recent = EmojiCategory::new();
+        recent.category = Category::ECAT_RECENT.into();
+        for value in used.iter() {
+            match emojis.iter().find(|e| &e.emoji == value) {
+                Some(emoji) => recent.emojis.push(emoji_proto(emoji)),
+                None => {
+                    let mut emoji = Emoji::new();
+                    emoji.value = value.clone();
+                    recent.emojis.push(emoji);
+                },
+            }
+        }
+        for emoji in emojis.iter().filter(|e| e.recent) {
+            if !used.contains(&emoji.emoji) {
+                recent.emojis.push(emoji_proto(emoji));
+            }
+        }
+        recent.emojis.truncate(MAX_RECENT_EMOJIS);
+
+        let mut list = EmojiList::new();
+        list.categories.push(recent);
+
+        for emoji in emojis.iter() {
+            let category = Category::from_i32(emoji.category as i32)
+                .unwrap_or(Category::ECAT_SYMBOLS);
+            push_emoji(&mut list, category, emoji_proto(emoji));
+        }
+
+        for symbol in symbols.iter() {
+            let category = if symbol.category == 0 {
+                Category::ECAT_HALFWIDTH_PUNCTUATION
+            } else {
+                Category::ECAT_FULLWIDTH_PUNCTUATION
+            };
+            let mut emoji = Emoji::new();
+            emoji.value = symbol.output.clone();
+            emoji.short_name = symbol.input.clone();
+            push_emoji(&mut list, category, emoji);
+        }
+
+        let mut response = Response::new();
+        response.emoji_list = Some(list).into();
+        Ok(response)
     }
 
-    fn on_reset_user_data(&self, req: Request) -> Result<Response> {
-        Err(anyhow!("Not implemented"))
+    fn on_select_emoji(&mut self, req: Request) -> Result<Response> {
+        if req.emoji.is_empty() {
+            let err = CommandError::invalid_request("No emoji selected");
+            return Err(err.into());
+        }
+
+        self.inner.db.user_data().record_emoji(&req.emoji)?;
+
+        let mut response = Response::new();
+        response.committed = true;
+        response.committed_text = req.emoji;
+        Ok(response)
+    }
+
+    fn on_reset_user_data(&mut self, req: Request) -> Result<Response> {
+        let scopes: Vec<UserDataScope> = req
+            .user_data_scopes
+            .iter()
+            .map(|s| s.enum_value_or_default())
+            .collect();
+        let clear_all =
+            scopes.is_empty() || scopes.contains(&UserDataScope::UDS_ALL);
+        let user_db = self.inner.db.user_data();
+        let mut removed = UserDataReset::new();
+
+        if clear_all || scopes.contains(&UserDataScope::UDS_FREQUENCIES) {
+            removed.frequencies_removed =
+                user_db.clear_learned_conversions()? as u32;
+        }
+
+        if clear_all || scopes.contains(&UserDataScope::UDS_USER_WORDS) {
+            removed.user_words_removed = user_db.clear_user_words()? as u32;
+        }
+
+        if clear_all || scopes.contains(&UserDataScope::UDS_RECENT_EMOJIS) {
+            removed.recent_emojis_removed =
+                user_db.clear_recent_emojis()? as u32;
+        }
+
+        log::debug!("User data reset: {:?}", removed);
+        self.buffer_mgr.reset()?;
+
+        let mut response = Response::new();
+        response.user_data_reset = Some(removed).into();
+        Ok(response)
     }
 
     fn on_shutdown(&self, req: Request) -> Result<Response> {
-        Err(anyhow!("Not implemented"))
+        let cmd = req.type_.enum_value_or_default();
+        Err(CommandError::unsupported(cmd).into())
+    }
+
+    fn on_get_engine_info(&self, req: Request) -> Result<Response> {
+        let supported_commands = CommandType::VALUES
+            .iter()
+            .filter(|cmd| !UNSUPPORTED_COMMANDS.contains(cmd))
+            .map(|&cmd| cmd.into())
+            .collect();
+
+        let info = EngineInfo {
+            engine_version: env!("CARGO_PKG_VERSION").into(),
+            protocol_version: PROTOCOL_VERSION,
+            dictionary_metadata: self
+                .inner
+                .db
+                .select_metadata()?
+                .into_iter()
+                .collect(),
+            supported_commands,
+            config_keys: CONFIG_KEYS.iter().map(|&key| key.into()).collect(),
+            ..Default::default()
+        };
+
+        Ok(Response {
+            engine_info: Some(info).into(),
+            ..Default::default()
+        })
+    }
+
+    fn on_hanji_to_poj(&self, req: Request) -> Result<Response> {
+        let segments = self
+            .hanji_to_poj(&req.text)?
+            .into_iter()
+            .map(|segment| transliteration::Segment {
+                text: segment.text,
+                readings: segment.readings,
+                ..Default::default()
+            })
+            .collect();
+
+        let transliteration = Transliteration {
+            segments,
+            ..Default::default()
+        };
+
+        Ok(Response {
+            transliteration: Some(transliteration).into(),
+            ..Default::default()
+        })
     }
 
     fn attach_preedit(&self, res: &mut Response) -> Result<()> {
@@ -378,6 +1050,95 @@ impl Engine {
     }
 }
 
+fn emoji_proto(emoji: &crate::db::models::Emoji) -> Emoji {
+    let mut proto = Emoji::new();
+    proto.value = emoji.emoji.clone();
+    proto.short_name = emoji.short_name.clone();
+    proto.code = emoji.code.clone();
+    proto
+}
+
+/// Appends to the last category if it matches, since emojis and symbols are
+/// listed in category order
+fn push_emoji(list: &mut EmojiList, category: Category, emoji: Emoji) {
+    match list.categories.last_mut() {
+        Some(last) if last.category.enum_value_or_default() == category => {
+            last.emojis.push(emoji);
+        },
+        _ => {
+            let mut group = EmojiCategory::new();
+            group.category = category.into();
+            group.emojis.push(emoji);
+            list.categories.push(group);
+        },
+    }
+}
+
+/// Applies the keys set in `config` over `base`. Empty keys and unset flags
+/// keep their value from `base`, except for the optional dots below key,
+/// which an empty value turns off.
+fn key_config_from_proto(
+    base: &KeyConfig,
+    config: &AppConfig,
+) -> Result<KeyConfig> {
+    let mut keys = *base;
+    let key = |value: &str, current: char| -> Result<char> {
+        Ok(parse_key(value)?.unwrap_or(current))
+    };
+
+    if let Some(proto) = config.key_config.as_ref() {
+        keys.t2 = key(&proto.telex_t2, keys.t2)?;
+        keys.t3 = key(&proto.telex_t3, keys.t3)?;
+        keys.t5 = key(&proto.telex_t5, keys.t5)?;
+        keys.t6 = key(&proto.telex_t6, keys.t6)?;
+        keys.t7 = key(&proto.telex_t7, keys.t7)?;
+        keys.t8 = key(&proto.telex_t8, keys.t8)?;
+        keys.t9 = key(&proto.telex_t9, keys.t9)?;
+        keys.khin = key(&proto.telex_khin, keys.khin)?;
+        keys.hyphon = key(&proto.alt_hyphen, keys.hyphon)?;
+        keys.nasal = key(&proto.nasal, keys.nasal)?;
+        keys.dot_above_right =
+            key(&proto.dot_above_right, keys.dot_above_right)?;
+        keys.dots_below = parse_key(&proto.dots_below)?;
+        if let Some(value) = proto.enable_uppercase_nasal.as_ref() {
+            keys.uppercase_nasal = value.value;
+        }
+        if let Some(value) = proto.use_digits_as_fallback.as_ref() {
+            keys.digits_as_fallback = value.value;
+        }
+    }
+
+    if let Some(value) = config.uppercase_nasal.as_ref() {
+        keys.uppercase_nasal = value.value;
+    }
+
+    Ok(keys)
+}
+
+fn candidate_id(req: &Request) -> Result<usize> {
+    usize::try_from(req.candidate_id).map_err(|_| {
+        CommandError::invalid_request(format!(
+            "Unknown candidate {}",
+            req.candidate_id
+        ))
+        .into()
+    })
+}
+
+fn parse_key(key: &str) -> Result<Option<char>> {
+    let mut chars = key.chars();
+    match (chars.next(), chars.next()) {
+        (None, _) => Ok(None),
+        (Some(ch), None) if ch.is_ascii() => Ok(Some(ch.to_ascii_lowercase())),
+        _ => Err(anyhow!("Not a single ASCII key: {:?}", key)),
+    }
+}
+
+fn has_modifier(key: &KeyEvent, modifier: ModifierKey) -> bool {
+    key.modifier_keys
+        .contains(&protobuf::EnumOrUnknown::from_i32(modifier as i32))
+}
+
 fn ascii_char_from_i32(ch: i32) -> Option<char> {
     let ch = ch as u32;
     if let Some(ch) = char::from_u32(ch) {
@@ -395,6 +1156,12 @@ mod tests {
     use super::*;
     use crate::tests::*;
 
+    fn mock_session_key_request(session_id: u64, ch: char) -> Request {
+        let mut req = mock_send_key_request(ch);
+        req.session_id = session_id;
+        req
+    }
+
     #[test]
     fn it_works() {
         let engine = get_engine();
@@ -429,4 +1196,544 @@ mod tests {
         assert_eq!(res.preedit.segments.len(), 1);
         Ok(())
     }
+
+    #[test]
+    fn it_resets_user_data_by_scope() -> Result<()> {
+        let mut engine = get_engine().unwrap();
+        let user_db = engine.inner.db.user_data();
+        user_db.learn_conversion(&mock_conversion("hó", "好"))?;
+        user_db.record_emoji("😀")?;
+
+        let mut req = Request::new();
+        req.type_ = CommandType::CMD_RESET_USER_DATA.into();
+        req.user_data_scopes
+            .push(UserDataScope::UDS_RECENT_EMOJIS.into());
+        let res = engine.on_reset_user_data(req)?;
+        assert_eq!(res.user_data_reset.frequencies_removed, 0);
+        assert_eq!(res.user_data_reset.recent_emojis_removed, 1);
+
+        let mut req = Request::new();
+        req.type_ = CommandType::CMD_RESET_USER_DATA.into();
+        let res = engine.on_reset_user_data(req)?;
+        assert_eq!(res.user_data_reset.frequencies_removed, 1);
+        assert_eq!(res.user_data_reset.recent_emojis_removed, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn it_lists_emojis_by_category() -> Result<()> {
+        let mut engine = get_engine().unwrap();
+        let mut req = Request::new();
+        req.type_ = CommandType::CMD_SELECT_EMOJI.into();
+        req.emoji = "🐒".into();
+        let res = engine.on_select_emoji(req)?;
+        assert_eq!(res.committed_text, "🐒");
+
+        let res = engine.on_list_emojis(Request::new())?;
+        let categories = &res.emoji_list.categories;
+        let recent = &categories[0];
+        assert_eq!(
+            recent.category.enum_value_or_default(),
+            Category::ECAT_RECENT
+        );
+        assert_eq!(recent.emojis[0].value, "🐒");
+        assert_eq!(recent.emojis[0].short_name, "monkey");
+        assert!(recent.emojis.len() > 1);
+
+        let smileys = &categories[1];
+        assert_eq!(
+            smileys.category.enum_value_or_default(),
+            Category::ECAT_SMILEYS
+        );
+        assert_eq!(smileys.emojis[0].value, "😀");
+        assert!(categories.iter().any(|c| {
+            c.category.enum_value_or_default()
+                == Category::ECAT_FULLWIDTH_PUNCTUATION
+        }));
+        Ok(())
+    }
+
+    #[test]
+    fn it_focuses_and_selects_candidates_by_id() -> Result<()> {
+        let mut engine = get_engine().unwrap();
+        engine.inner.conf.set_input_mode(InputMode::Classic);
+        let res = engine.on_send_key(mock_send_key_request('a'))?;
+        let second = res.candidate_list.candidates[1].value.clone();
+
+        let req = mock_candidate_request(CommandType::CMD_FOCUS_CANDIDATE, 1);
+        let res = engine.on_focus_candidate(req)?;
+        assert_eq!(res.candidate_list.focused, 1);
+        assert!(!res.committed);
+
+        let req = mock_candidate_request(CommandType::CMD_SELECT_CANDIDATE, 1);
+        let res = engine.on_select_candidate(req)?;
+        assert!(res.committed);
+        assert_eq!(res.committed_text, second);
+
+        let req = mock_candidate_request(CommandType::CMD_FOCUS_CANDIDATE, 99);
+        assert!(engine.on_focus_candidate(req).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn it_pairs_brackets_on_commit() -> Result<()> {
+        let mut engine = get_engine().unwrap();
+        engine.inner.conf.set_input_mode(InputMode::Continuous);
+        engine.inner.conf.set_output_mode(OutputMode::Hanji);
+        let res = engine.on_send_key(mock_send_key_request('('))?;
+        assert!(res.committed);
+        assert_eq!(res.committed_text, "（）");
+        assert_eq!(res.chars_after_caret, 1);
+
+        let req = mock_candidate_request(CommandType::CMD_REVERT, 0);
+        assert_eq!(engine.on_revert(req)?.reverted_text, "（");
+        engine.on_reset(Request::default())?;
+
+        engine.inner.conf.set_output_mode(OutputMode::Lomaji);
+        let res = engine.on_send_key(mock_send_key_request('.'))?;
+        assert_eq!(res.committed_text, ".");
+        assert_eq!(res.chars_after_caret, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn it_reverts_the_last_commit() -> Result<()> {
+        let mut engine = get_engine().unwrap();
+        engine.inner.conf.set_input_mode(InputMode::Continuous);
+        for ch in "lihopengan".chars() {
+            engine.on_send_key(mock_send_key_request(ch))?;
+        }
+        engine.buffer_mgr.focus_next_candidate(&engine.inner)?;
+
+        let req = mock_candidate_request(CommandType::CMD_REVERT, 0);
+        let res = engine.on_revert(req)?;
+        assert!(res.reverted_text.is_empty());
+        assert!(!engine.buffer_mgr.is_focused());
+
+        let committed = engine.on_commit(Request::default())?;
+        assert!(committed.committed);
+        let req = mock_candidate_request(CommandType::CMD_REVERT, 0);
+        let res = engine.on_revert(req)?;
+        let segments = &committed.preedit.segments;
+        let text: String = segments.iter().map(|s| s.value.as_str()).collect();
+        assert_eq!(res.reverted_text, text);
+        assert_eq!(engine.buffer_mgr.raw_text(), "lihopengan");
+        assert_eq!(
+            res.edit_state.enum_value_or_default(),
+            EditState::ES_COMPOSING
+        );
+
+        let req = mock_candidate_request(CommandType::CMD_REVERT, 0);
+        assert!(engine.on_revert(req)?.reverted_text.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn it_forgets_the_last_commit_after_switching_modes() -> Result<()> {
+        let mut engine = get_engine().unwrap();
+        engine.inner.conf.set_input_mode(InputMode::Continuous);
+        for ch in "lihopengan".chars() {
+            engine.on_send_key(mock_send_key_request(ch))?;
+        }
+        engine.buffer_mgr.focus_next_candidate(&engine.inner)?;
+        assert!(engine.on_commit(Request::default())?.committed);
+
+        let mut req =
+            mock_candidate_request(CommandType::CMD_SWITCH_INPUT_MODE, 0);
+        req.config.mut_or_insert_default().input_mode =
+            AppInputMode::CLASSIC.into();
+        engine.on_switch_input_mode(req)?;
+
+        let req = mock_candidate_request(CommandType::CMD_REVERT, 0);
+        assert!(engine.on_revert(req)?.reverted_text.is_empty());
+        assert!(engine.buffer_mgr.raw_text().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn it_selects_segments_in_continuous_mode() -> Result<()> {
+        let mut engine = get_engine().unwrap();
+        engine.inner.conf.set_input_mode(InputMode::Continuous);
+        for ch in "lihopengan".chars() {
+            engine.on_send_key(mock_send_key_request(ch))?;
+        }
+
+        let req = mock_candidate_request(CommandType::CMD_SELECT_CANDIDATE, 0);
+        let res = engine.on_select_candidate(req)?;
+        assert!(!res.committed);
+
+        let req = mock_candidate_request(CommandType::CMD_SELECT_CANDIDATE, 0);
+        let res = engine.on_select_candidate(req)?;
+        assert!(res.committed);
+        Ok(())
+    }
+
+    #[test]
+    fn it_keeps_sessions_separate() -> Result<()> {
+        let mut engine = get_engine().unwrap();
+        engine.inner.conf.set_input_mode(InputMode::Continuous);
+        let req = mock_candidate_request(CommandType::CMD_CREATE_SESSION, 0);
+        let first = engine.handle_request(req)?.session_id;
+        let req = mock_candidate_request(CommandType::CMD_CREATE_SESSION, 0);
+        let second = engine.handle_request(req)?.session_id;
+        assert_ne!(first, second);
+
+        engine.handle_request(mock_session_key_request(first, 'z'))?;
+        engine.handle_request(mock_session_key_request(second, 'q'))?;
+        let res = engine.handle_request(mock_session_key_request(first, 'z'))?;
+        assert_eq!(res.preedit.segments[0].value, "zz");
+        assert_eq!(engine.sessions[&second].buffer_mgr.raw_text(), "q");
+
+        let mut req =
+            mock_candidate_request(CommandType::CMD_DESTROY_SESSION, 0);
+        req.session_id = second;
+        engine.handle_request(req)?;
+        let req = mock_session_key_request(second, 'q');
+        assert!(engine.handle_request(req).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn it_expires_idle_sessions() -> Result<()> {
+        let mut engine = get_engine().unwrap();
+        let req = mock_candidate_request(CommandType::CMD_CREATE_SESSION, 0);
+        let id = engine.handle_request(req)?.session_id;
+        engine.expire_sessions(Instant::now() + SESSION_TIMEOUT);
+        assert!(!engine.sessions.contains_key(&id));
+        assert!(engine.switch_session(DEFAULT_SESSION_ID).is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn it_opens_the_database_read_only() -> Result<()> {
+        let user_filename = std::env::temp_dir()
+            .join(format!("khiin_user_{}.db", std::process::id()));
+        let mut engine = Engine::with_open_mode(
+            debug_db_path(),
+            &user_filename,
+            OpenMode::ReadOnly,
+        )
+        .unwrap();
+        let res = engine.handle_request(mock_send_key_request('a'))?;
+        assert_eq!(res.preedit.segments.len(), 1);
+        assert!(user_filename.exists());
+
+        drop(engine);
+        std::fs::remove_file(&user_filename)?;
+        Ok(())
+    }
+
+    #[test]
+    fn it_shares_the_dictionary_between_threads() -> Result<()> {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<SharedDictionary>();
+
+        let path = debug_db_path();
+        let dict = SharedDictionary::new(&path)?;
+        let handles: Vec<_> = (0..2)
+            .map(|_| {
+                let dict = dict.clone();
+                let path = path.clone();
+                std::thread::spawn(move || {
+                    let mut engine =
+                        Engine::with_dictionary(path, &dict).unwrap();
+                    let res = engine.handle_request(mock_send_key_request('a'));
+                    let tone_mode = engine.inner.conf.tone_mode();
+                    let db = engine.inner.db.as_ref();
+                    let shared = dict.get(db, tone_mode).unwrap();
+                    assert!(Arc::ptr_eq(&engine.inner.dict, &shared));
+                    res.map(|res| res.preedit.segments.len())
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            assert_eq!(handle.join().unwrap()?, 1);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_tests_keys_without_side_effects() -> Result<()> {
+        let mut engine = get_engine().unwrap();
+        let test = |engine: &mut Engine, mut req: Request| {
+            req.type_ = CommandType::CMD_TEST_SEND_KEY.into();
+            engine.handle_request(req).map(|res| res.consumable)
+        };
+        let enter = || mock_special_key_request(SpecialKey::SK_ENTER, &[]);
+
+        assert!(test(&mut engine, mock_send_key_request('a'))?);
+        assert!(!test(&mut engine, mock_send_key_request('1'))?);
+        assert!(!test(&mut engine, enter())?);
+        assert_eq!(engine.buffer_mgr.edit_state(), EditState::ES_EMPTY);
+
+        let res = engine.on_send_key(mock_send_key_request('1'))?;
+        assert!(!res.consumable);
+        assert_eq!(engine.buffer_mgr.edit_state(), EditState::ES_EMPTY);
+
+        let res = engine.on_send_key(mock_send_key_request('a'))?;
+        assert!(res.consumable);
+        assert!(test(&mut engine, mock_send_key_request('1'))?);
+        assert!(test(&mut engine, enter())?);
+        let req = mock_special_key_request(SpecialKey::SK_PGDN, &[]);
+        assert!(!test(&mut engine, req)?);
+
+        let mut req = mock_send_key_request('c');
+        let key_event = req.key_event.mut_or_insert_default();
+        key_event.modifier_keys = vec![ModifierKey::MODK_CTRL.into()];
+        assert!(!test(&mut engine, req)?);
+        assert_eq!(engine.buffer_mgr.raw_text(), "a");
+        Ok(())
+    }
+
+    #[test]
+    fn it_applies_key_configurations() -> Result<()> {
+        let mut engine = get_engine().unwrap();
+        let mut req = mock_candidate_request(CommandType::CMD_SET_CONFIG, 0);
+        let config = req.config.mut_or_insert_default();
+        let keys = config.key_config.mut_or_insert_default();
+        keys.telex_t2 = "Q".into();
+        keys.nasal = "z".into();
+        engine.handle_request(req.clone())?;
+        assert_eq!(engine.inner.conf.t2(), 'q');
+        assert_eq!(engine.inner.conf.nasal(), 'z');
+
+        for t3 in ["q", "ff"] {
+            let mut req = req.clone();
+            let config = req.config.mut_or_insert_default();
+            let keys = config.key_config.mut_or_insert_default();
+            keys.telex_t3 = t3.into();
+            assert!(engine.handle_request(req).is_err());
+            assert_eq!(engine.inner.conf.t3(), 'f');
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn it_sets_and_clears_the_dots_below_key() -> Result<()> {
+        let mut engine = get_engine().unwrap();
+        let mut req = mock_candidate_request(CommandType::CMD_SET_CONFIG, 0);
+        let config = req.config.mut_or_insert_default();
+        let keys = config.key_config.mut_or_insert_default();
+        keys.dots_below = "q".into();
+        engine.handle_request(req.clone())?;
+        assert_eq!(engine.inner.conf.dots_below(), Some('q'));
+
+        let config = req.config.mut_or_insert_default();
+        config.key_config.mut_or_insert_default().dots_below.clear();
+        engine.handle_request(req)?;
+        assert_eq!(engine.inner.conf.dots_below(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn it_segments_with_the_active_tone_mode() -> Result<()> {
+        let mut engine = get_engine().unwrap();
+        let telex = engine.inner.dict.clone();
+        assert_eq!(telex.segment("lishos")?, vec!["lishos"]);
+
+        let mut req = mock_candidate_request(CommandType::CMD_SET_CONFIG, 0);
+        let config = req.config.mut_or_insert_default();
+        config.input_mode = AppInputMode::CONTINUOUS.into();
+        config.telex_enabled = Some(BoolValue::default()).into();
+        engine.handle_request(req.clone())?;
+        assert_eq!(engine.inner.dict.segment("li2ho2")?, vec!["li2ho2"]);
+
+        let mut res = Response::default();
+        for ch in "li2ho2".chars() {
+            res = engine.handle_request(mock_send_key_request(ch))?;
+        }
+        assert_eq!(res.preedit.segments.len(), 1);
+        assert_eq!(res.preedit.segments[0].value, "lí hó");
+
+        let config = req.config.mut_or_insert_default();
+        config.telex_enabled.mut_or_insert_default().value = true;
+        engine.handle_request(req)?;
+        assert!(Arc::ptr_eq(&engine.inner.dict, &telex));
+        Ok(())
+    }
+
+    fn send_command(engine: &mut Engine, bytes: &[u8]) -> Response {
+        let bytes = engine.send_command_bytes(bytes).unwrap();
+        let cmd = Command::parse_from_bytes(&bytes).unwrap();
+        cmd.response.unwrap()
+    }
+
+    fn send_request(engine: &mut Engine, req: Request) -> Response {
+        let cmd = engine.send_command(commands::command(req));
+        cmd.response.unwrap()
+    }
+
+    #[test]
+    fn it_reports_typed_errors() {
+        let mut engine = get_engine().unwrap();
+        let code = |res: Response| res.error.enum_value().unwrap();
+
+        let res = send_command(&mut engine, &[0xff, 0xff, 0xff]);
+        assert_eq!(code(res), ErrorCode::MALFORMED_REQUEST);
+        let res = send_command(&mut engine, &[]);
+        assert_eq!(code(res), ErrorCode::MALFORMED_REQUEST);
+
+        let req = mock_candidate_request(CommandType::CMD_DISABLE, 0);
+        let res = send_request(&mut engine, req);
+        assert_eq!(res.error_message, "CMD_DISABLE is not supported");
+        assert_eq!(code(res), ErrorCode::UNSUPPORTED_COMMAND);
+
+        let mut req = mock_send_key_request('a');
+        req.session_id = 42;
+        let res = send_request(&mut engine, req);
+        assert_eq!(code(res), ErrorCode::UNKNOWN_SESSION);
+
+        for id in [99, -1] {
+            let req =
+                mock_candidate_request(CommandType::CMD_FOCUS_CANDIDATE, id);
+            let res = send_request(&mut engine, req);
+            assert_eq!(res.error_message, format!("Unknown candidate {}", id));
+            assert_eq!(code(res), ErrorCode::INVALID_REQUEST);
+        }
+
+        let req = mock_candidate_request(CommandType::CMD_SELECT_EMOJI, 0);
+        let res = send_request(&mut engine, req);
+        assert_eq!(res.error_message, "No emoji selected");
+        assert_eq!(code(res), ErrorCode::INVALID_REQUEST);
+
+        let mut req = mock_candidate_request(CommandType::CMD_SET_CONFIG, 0);
+        let config = req.config.mut_or_insert_default();
+        config.key_config.mut_or_insert_default().telex_t2 = "a".into();
+        let res = send_request(&mut engine, req);
+        assert!(!res.error_message.is_empty());
+        assert_eq!(code(res), ErrorCode::INVALID_CONFIG);
+
+        let res = send_request(&mut engine, mock_send_key_request('a'));
+        assert_eq!(code(res), ErrorCode::OK);
+    }
+
+    #[test]
+    fn it_survives_arbitrary_bytes() {
+        let mut engine = get_engine().unwrap();
+        let valid = mock_send_key_request('a').write_to_bytes().unwrap();
+        let mut seed: u32 = 1;
+        for len in 0..200 {
+            let mut bytes: Vec<u8> = (0..len % 40)
+                .map(|_| {
+                    seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+                    (seed >> 16) as u8
+                })
+                .collect();
+            if len % 2 == 0 {
+                bytes.splice(0..0, [0x12, valid.len() as u8]);
+                bytes.splice(2..2, valid.iter().copied());
+            }
+            let res = send_command(&mut engine, &bytes);
+            assert!(res.error.enum_value().is_ok());
+        }
+    }
+
+    /// `config.proto` is built for the lite runtime, which has no message
+    /// descriptors, so the field names are read from the file itself
+    fn proto_fields(message: &str) -> Vec<String> {
+        let proto = include_str!("../../protos/src/config.proto");
+        let body = proto
+            .split(&format!("message {} {{", message))
+            .nth(1)
+            .unwrap();
+        body[..body.find('}').unwrap()]
+            .lines()
+            .map(|line| line.split("//").next().unwrap().trim())
+            .filter(|line| line.ends_with(';'))
+            .map(|line| line.split_whitespace().nth(1).unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn it_lists_every_applied_config_key() {
+        // Fields that CMD_SET_CONFIG does not apply
+        let ignored = [
+            "ime_enabled",
+            "key_config",
+            "dotted_khin",
+            "autokhin",
+            "easy_ch",
+        ];
+        let mut fields: Vec<String> = proto_fields("AppConfig")
+            .into_iter()
+            .filter(|field| !ignored.contains(&field.as_str()))
+            .chain(
+                proto_fields("KeyConfiguration")
+                    .into_iter()
+                    .map(|field| format!("key_config.{}", field)),
+            )
+            .collect();
+        let mut keys: Vec<String> =
+            CONFIG_KEYS.iter().map(|&key| key.into()).collect();
+        fields.sort();
+        keys.sort();
+        assert_eq!(keys, fields);
+    }
+
+    #[test]
+    fn it_describes_the_engine() {
+        let mut engine = get_engine().unwrap();
+        let req = mock_candidate_request(CommandType::CMD_GET_ENGINE_INFO, 0);
+        let info = send_request(&mut engine, req).engine_info.unwrap();
+        assert_eq!(info.protocol_version, PROTOCOL_VERSION);
+        assert!(!info.engine_version.is_empty());
+        assert!(info.dictionary_metadata["inputs"] > 0);
+        assert!(info.config_keys.iter().any(|k| k == "key_config.nasal"));
+
+        for &cmd in CommandType::VALUES {
+            let supported = info.supported_commands.contains(&cmd.into());
+            let req = mock_candidate_request(cmd, 0);
+            let res = send_request(&mut engine, req);
+            let unsupported = res.error.enum_value()
+                == Ok(ErrorCode::UNSUPPORTED_COMMAND);
+            assert_eq!(supported, !unsupported, "{:?}", cmd);
+        }
+    }
+
+    #[test]
+    fn it_reads_hanji_in_poj() -> Result<()> {
+        let mut engine = get_engine().unwrap();
+        let segments = engine.hanji_to_poj("台灣 好")?;
+        assert_eq!(segments.len(), 3);
+        assert_eq!(segments[0].readings, vec!["tâi-oân"]);
+        assert!(segments[1].readings.is_empty());
+        assert_eq!(segments[2].readings[0], "hó");
+
+        let res = engine
+            .send_command(commands::hanji_to_poj("好"))
+            .response
+            .unwrap();
+        assert_eq!(res.error.enum_value(), Ok(ErrorCode::OK));
+        let segments = &res.transliteration.segments;
+        assert_eq!(segments.len(), 1);
+        assert_eq!(segments[0].text, "好");
+        assert_eq!(segments[0].readings[0], "hó");
+        Ok(())
+    }
+
+    #[test]
+    fn it_sends_typed_commands() {
+        let mut engine = get_engine().unwrap();
+        let mode = AppInputMode::CONTINUOUS;
+        engine.send_command(commands::switch_input_mode(mode));
+        assert!(engine.inner.conf.input_mode() == InputMode::Continuous);
+
+        engine.send_command(commands::send_char('z'));
+        let cmd = engine.send_command(commands::send_char('z'));
+        assert_eq!(cmd.request.key_event.key_code, 'z' as i32);
+        assert_eq!(cmd.response.preedit.segments[0].value, "zz");
+
+        let cmd = commands::simple(CommandType::CMD_COMMIT);
+        let res = engine.send_command(cmd).response.unwrap();
+        assert_eq!(res.committed_text, "zz");
+
+        let config = AppConfig {
+            output_mode: AppOutputMode::HANJI.into(),
+            ..Default::default()
+        };
+        engine.send_command(commands::set_config(config));
+        assert!(engine.inner.conf.is_hanji_first());
+        assert!(engine.inner.conf.input_mode() == InputMode::Continuous);
+    }
 }
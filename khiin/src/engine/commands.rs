@@ -0,0 +1,79 @@
+//! Builders for the commands apps send most often, to pass to
+//! `Engine::send_command`.
+
+use khiin_protos::command::Command;
+use khiin_protos::command::CommandType;
+use khiin_protos::command::KeyEvent;
+use khiin_protos::command::Request;
+use khiin_protos::config::AppConfig;
+use khiin_protos::config::AppInputMode;
+use khiin_protos::config::AppOutputMode;
+
+/// Wraps `req` in a command, ready to be sent
+pub fn command(req: Request) -> Command {
+    Command {
+        request: Some(req).into(),
+        ..Default::default()
+    }
+}
+
+/// A command that needs no data besides its type, e.g. CMD_RESET
+pub fn simple(cmd_type: CommandType) -> Command {
+    command(Request {
+        type_: cmd_type.into(),
+        ..Default::default()
+    })
+}
+
+pub fn send_key(key_event: KeyEvent) -> Command {
+    command(Request {
+        type_: CommandType::CMD_SEND_KEY.into(),
+        key_event: Some(key_event).into(),
+        ..Default::default()
+    })
+}
+
+/// Sends a printable ASCII key without modifiers
+pub fn send_char(ch: char) -> Command {
+    send_key(KeyEvent {
+        key_code: ch as i32,
+        ..Default::default()
+    })
+}
+
+/// Reads Hanji or Hanlo `text` out in POJ, see `Engine::hanji_to_poj`
+pub fn hanji_to_poj(text: &str) -> Command {
+    command(Request {
+        type_: CommandType::CMD_HANJI_TO_POJ.into(),
+        text: text.into(),
+        ..Default::default()
+    })
+}
+
+pub fn set_config(config: AppConfig) -> Command {
+    with_config(CommandType::CMD_SET_CONFIG, config)
+}
+
+pub fn switch_input_mode(mode: AppInputMode) -> Command {
+    let config = AppConfig {
+        input_mode: mode.into(),
+        ..Default::default()
+    };
+    with_config(CommandType::CMD_SWITCH_INPUT_MODE, config)
+}
+
+pub fn switch_output_mode(mode: AppOutputMode) -> Command {
+    let config = AppConfig {
+        output_mode: mode.into(),
+        ..Default::default()
+    };
+    with_config(CommandType::CMD_SWITCH_OUTPUT_MODE, config)
+}
+
+fn with_config(cmd_type: CommandType, config: AppConfig) -> Command {
+    command(Request {
+        type_: cmd_type.into(),
+        config: Some(config).into(),
+        ..Default::default()
+    })
+}
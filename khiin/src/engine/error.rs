@@ -0,0 +1,122 @@
+use std::any::Any;
+use std::fmt;
+
+use anyhow::Error;
+
+use khiin_protos::command::CommandType;
+use khiin_protos::command::ErrorCode;
+use khiin_protos::command::Response;
+
+/// A failure with a specific `ErrorCode` to report to the app. Errors
+/// without one are reported as `DATABASE_ERROR` if they come from the
+/// database, otherwise as `FAIL`.
+#[derive(Debug)]
+pub(crate) struct CommandError {
+    code: ErrorCode,
+    message: String,
+}
+
+impl CommandError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    pub fn malformed(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::MALFORMED_REQUEST, message)
+    }
+
+    pub fn unsupported(cmd: CommandType) -> Self {
+        Self::new(
+            ErrorCode::UNSUPPORTED_COMMAND,
+            format!("{:?} is not supported", cmd),
+        )
+    }
+
+    pub fn invalid_config(err: Error) -> Self {
+        Self::new(ErrorCode::INVALID_CONFIG, format!("{:#}", err))
+    }
+
+    pub fn invalid_request(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::INVALID_REQUEST, message)
+    }
+
+    pub fn unknown_session(id: u64) -> Self {
+        Self::new(
+            ErrorCode::UNKNOWN_SESSION,
+            format!("Unknown session: {}", id),
+        )
+    }
+
+    /// From the payload of a caught panic
+    pub fn internal(panic: Box<dyn Any + Send>) -> Self {
+        let message = if let Some(msg) = panic.downcast_ref::<&str>() {
+            msg.to_string()
+        } else if let Some(msg) = panic.downcast_ref::<String>() {
+            msg.clone()
+        } else {
+            "Unknown panic".to_string()
+        };
+        Self::new(ErrorCode::INTERNAL_ERROR, message)
+    }
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+pub(crate) fn error_code(err: &Error) -> ErrorCode {
+    for cause in err.chain() {
+        if let Some(err) = cause.downcast_ref::<CommandError>() {
+            return err.code;
+        }
+
+        #[cfg(feature = "sqlite")]
+        if cause.is::<rusqlite::Error>()
+            || cause.is::<rusqlite_migration::Error>()
+        {
+            return ErrorCode::DATABASE_ERROR;
+        }
+    }
+    ErrorCode::FAIL
+}
+
+pub(crate) fn error_response(err: &Error) -> Response {
+    Response {
+        error: error_code(err).into(),
+        error_message: format!("{:#}", err),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_finds_error_codes() {
+        let err = Error::from(CommandError::malformed("no request"));
+        assert_eq!(error_code(&err), ErrorCode::MALFORMED_REQUEST);
+        let err = err.context("While handling a command");
+        assert_eq!(error_code(&err), ErrorCode::MALFORMED_REQUEST);
+        let err = Error::from(rusqlite::Error::InvalidQuery);
+        assert_eq!(error_code(&err), ErrorCode::DATABASE_ERROR);
+        assert_eq!(error_code(&anyhow::anyhow!("other")), ErrorCode::FAIL);
+    }
+
+    #[test]
+    fn it_describes_errors() {
+        let err = Error::from(CommandError::unsupported(
+            CommandType::CMD_DISABLE,
+        ));
+        let res = error_response(&err);
+        assert_eq!(res.error.enum_value(), Ok(ErrorCode::UNSUPPORTED_COMMAND));
+        assert_eq!(res.error_message, "CMD_DISABLE is not supported");
+    }
+}
@@ -1,5 +1,7 @@
 pub(crate) mod converter;
 pub(crate) mod parser;
+pub(crate) mod punctuation;
+pub(crate) mod reverse;
 pub(crate) mod syllable;
 
 pub(crate) use parser::parse_longest_from_start;
@@ -9,20 +9,26 @@ use crate::buffer::KhiinElem;
 use crate::buffer::StringElem;
 use crate::config::Config;
 use crate::config::OutputMode;
+use crate::config::KeyConfig;
 use crate::config::KhinMode;
 use crate::config::ToneMode;
+use crate::config::NUMERIC_TONES;
 use crate::data::Dictionary;
 use crate::db::models::CaseType;
 use crate::db::models::InputType;
-use crate::db::Database;
+use crate::db::DictionaryBackend;
 use crate::engine::EngInner;
 use crate::input::parser::SectionType;
+use crate::input::parser::EMOJI_TRIGGER;
+use crate::input::punctuation::punct_output;
+use crate::input::punctuation::PunctOutput;
 
 use super::parse_longest_from_start;
 use super::parse_whole_input;
 use super::Syllable;
 
 use khiin_ji::lomaji::has_tone_letter;
+use khiin_ji::lomaji::key_to_tone;
 use khiin_ji::lomaji::strip_khin;
 use khiin_ji::lomaji::strip_tone_diacritic;
 use khiin_ji::Tone;
@@ -36,11 +42,69 @@ pub(crate) fn get_candidates(
     match ty {
         SectionType::Plaintext => Ok(Vec::new()),
         SectionType::Hyphens => Ok(Vec::new()),
-        SectionType::Punct => Ok(Vec::new()),
+        SectionType::Punct => candidates_for_punct(engine, query),
         SectionType::Splittable => candidates_for_splittable(engine, query),
+        SectionType::Emoji => candidates_for_emoji(engine, query),
     }
 }
 
+fn candidates_for_punct(engine: &EngInner, query: &str) -> Result<Vec<Buffer>> {
+    let key = query.chars().next().unwrap_or_default();
+    let items = match punct_output(&engine.conf, key) {
+        Some(PunctOutput::Direct(text)) => vec![text],
+        Some(PunctOutput::Menu(items)) => items,
+        None => Vec::new(),
+    };
+
+    let result = items
+        .into_iter()
+        .map(|item| {
+            let elem = StringElem::from_raw_input(query.into(), item);
+            let mut buffer: Buffer = BufferElementEnum::from(elem).into();
+            buffer.set_converted(true);
+            buffer
+        })
+        .collect();
+
+    Ok(result)
+}
+
+/// Output of a punctuation section before any menu item is chosen
+fn convert_punct(engine: &EngInner, section: &str) -> StringElem {
+    let key = section.chars().next().unwrap_or_default();
+    match punct_output(&engine.conf, key) {
+        Some(output) => {
+            StringElem::from_raw_input(section.into(), output.first().into())
+        },
+        None => StringElem::from(section),
+    }
+}
+
+/// Maximum number of emojis to suggest for a keyword
+const MAX_EMOJI_CANDIDATES: usize = 45;
+
+fn candidates_for_emoji(engine: &EngInner, query: &str) -> Result<Vec<Buffer>> {
+    let EngInner { db, conf, .. } = &engine;
+    let keyword = query.trim_start_matches(EMOJI_TRIGGER);
+    let emojis = db.select_emojis_by_keyword(
+        conf.tone_mode().into(),
+        keyword,
+        MAX_EMOJI_CANDIDATES,
+    )?;
+
+    let result = emojis
+        .into_iter()
+        .map(|emoji| {
+            let elem = StringElem::from_raw_input(query.into(), emoji.emoji);
+            let mut buffer: Buffer = BufferElementEnum::from(elem).into();
+            buffer.set_converted(true);
+            buffer
+        })
+        .collect();
+
+    Ok(result)
+}
+
 fn candidates_for_splittable(
     engine: &EngInner,
     query: &str,
@@ -215,11 +279,13 @@ pub(crate) fn convert_guess(
 
     for (ty, section) in sections {
         match ty {
-            SectionType::Plaintext => {
+            SectionType::Plaintext | SectionType::Emoji => {
                 composition.push(StringElem::from(section).into());
             },
             SectionType::Hyphens => todo!(),
-            SectionType::Punct => todo!(),
+            SectionType::Punct => {
+                composition.push(convert_punct(engine, section).into());
+            },
             SectionType::Splittable => {
                 let elems = convert_section_by_hanlo(
                     engine,
@@ -250,11 +316,13 @@ pub(crate) fn convert_all(
 
     for (ty, section) in sections {
         match ty {
-            SectionType::Plaintext => {
+            SectionType::Plaintext | SectionType::Emoji => {
                 composition.push(StringElem::from(section).into());
             },
             SectionType::Hyphens => todo!(),
-            SectionType::Punct => todo!(),
+            SectionType::Punct => {
+                composition.push(convert_punct(engine, section).into());
+            },
             SectionType::Splittable => {
                 let elems = convert_section(engine, ty, section)?;
                 for elem in elems.into_iter() {
@@ -283,7 +351,7 @@ pub(crate) fn convert_to_telex(
         raw_input.push(key);
         composition.push(StringElem::from(raw_input).into());
         return (Ok(composition), false);
-    } else if (lower_key == 'n'
+    } else if (lower_key == engine.conf.nasal()
         && (raw_buffer.ends_with("ⁿ") || raw_buffer.ends_with("ᴺ")))
     {
         let mut composition = Buffer::new();
@@ -292,7 +360,8 @@ pub(crate) fn convert_to_telex(
         raw_input.push(key);
         composition.push(StringElem::from(raw_input).into());
         return (Ok(composition), false);
-    } else if ((lower_key == 'u' || lower_key == 'o')
+    } else if ((lower_key == engine.conf.dot_above_right()
+        || lower_key == 'o')
         && (raw_buffer.ends_with("o͘") || raw_buffer.ends_with("O͘")))
     {
         let mut composition = Buffer::new();
@@ -331,6 +400,15 @@ pub(crate) fn convert_to_telex(
         raw_input.push(key);
         composition.push(StringElem::from(raw_input).into());
         return (Ok(composition), false);
+    } else if (Some(lower_key) == engine.conf.dots_below()
+        && raw_buffer.ends_with('\u{0324}'))
+    {
+        let mut composition = Buffer::new();
+        let mut raw_input = stripped.to_string();
+        raw_input.pop();
+        raw_input.push(key);
+        composition.push(StringElem::from(raw_input).into());
+        return (Ok(composition), false);
     }
     let mut word: Syllable = Syllable::new();
     word.raw_body = stripped.to_string();
@@ -344,7 +422,7 @@ pub(crate) fn convert_to_telex(
             tone_char = tone_to_char(engine, &tone);
             word.tone = tone;
             if key != ' ' {
-                word.raw_body.push(key);
+                push_telex_key(engine, &mut word.raw_body, key);
             }
         }
 
@@ -363,10 +441,13 @@ pub(crate) fn convert_to_telex(
         }
     } else {
         if key != ' ' {
-            word.raw_body.push(key);
+            push_telex_key(engine, &mut word.raw_body, key);
         }
     }
-    let syllable = word.compose();
+    let mut syllable = word.compose();
+    if !engine.conf.uppercase_nasal() {
+        syllable = syllable.replace('ᴺ', "ⁿ");
+    }
     let (mut stripped, tone) = strip_tone_diacritic(&syllable);
     // _ = strip_khin(&mut stripped);
     stripped = stripped
@@ -385,6 +466,87 @@ pub(crate) fn convert_to_telex(
     (Ok(composition), ret)
 }
 
+/// Appends a typed key to a syllable body, spelling the configured nasal,
+/// dot above right and dots below keys the way `Syllable::compose` reads
+/// them: "nn", "ou" and a leading "e".
+fn push_telex_key(engine: &EngInner, body: &mut String, key: char) {
+    let lower_key = key.to_ascii_lowercase();
+    let upper = key.is_ascii_uppercase();
+    let last = body.chars().last().map(|c| c.to_ascii_lowercase());
+
+    if lower_key == engine.conf.nasal() && lower_key != 'n' {
+        body.push_str(if upper { "NN" } else { "nn" });
+    } else if lower_key == engine.conf.dot_above_right()
+        && lower_key != 'u'
+        && lower_key != 'o'
+        && last == Some('o')
+    {
+        body.push(if upper { 'U' } else { 'u' });
+    } else if Some(lower_key) == engine.conf.dots_below()
+        && (last == Some('o') || last == Some('u'))
+    {
+        let vowel = body.pop().unwrap();
+        body.push(if upper { 'E' } else { 'e' });
+        body.push(vowel);
+    } else {
+        body.push(key);
+    }
+}
+
+/// Spells a key typed in continuous mode with the default keys, which the
+/// key sequences of the dictionary are built from. Returns the number of
+/// chars before the key to replace, and the text to replace them and the
+/// key with. Keys only take another role after a letter, and the khin,
+/// hyphen and done keys are not used in continuous mode.
+pub(crate) fn to_dictionary_keys(
+    engine: &EngInner,
+    last: Option<char>,
+    key: char,
+) -> (usize, String) {
+    let conf = &engine.conf;
+    let lower_key = key.to_ascii_lowercase();
+    let upper = key.is_ascii_uppercase();
+    let (last, lower_last) = match last {
+        Some(c) if c.is_ascii_alphabetic() => (c, c.to_ascii_lowercase()),
+        _ => return (0, key.to_string()),
+    };
+
+    if conf.tone_mode() == ToneMode::Telex {
+        let tones = [
+            (conf.t2(), 's'),
+            (conf.t3(), 'f'),
+            (conf.t5(), 'l'),
+            (conf.t7(), 'j'),
+            (conf.t8(), 'j'),
+            (conf.t9(), 'w'),
+        ];
+        if let Some(&(_, letter)) = tones.iter().find(|t| t.0 == lower_key) {
+            let letter = if upper {
+                letter.to_ascii_uppercase()
+            } else {
+                letter
+            };
+            return (0, letter.to_string());
+        }
+    }
+
+    if lower_key == conf.nasal() && lower_key != 'n' {
+        (0, if upper { "NN" } else { "nn" }.to_string())
+    } else if lower_key == conf.dot_above_right()
+        && lower_key != 'u'
+        && lower_key != 'o'
+        && lower_last == 'o'
+    {
+        (0, if upper { "U" } else { "u" }.to_string())
+    } else if Some(lower_key) == conf.dots_below()
+        && (lower_last == 'o' || lower_last == 'u')
+    {
+        (1, format!("{}{}", if upper { 'E' } else { 'e' }, last))
+    } else {
+        (0, key.to_string())
+    }
+}
+
 fn convert_section(
     engine: &EngInner,
 
@@ -500,6 +662,8 @@ fn char_to_tone(engine: &EngInner, ch: char) -> Tone {
         Tone::T9
     } else if ch == engine.conf.t8() {
         Tone::T8
+    } else if engine.conf.digits_as_fallback() && NUMERIC_TONES.contains(ch) {
+        key_to_tone(ch)
     } else {
         Tone::None
     }
@@ -526,6 +690,7 @@ fn get_shared_t8_tone(engine: &EngInner) -> Tone {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::db::Database;
     use crate::tests::*;
 
     fn setup() -> (Database, Dictionary, Config) {
@@ -554,4 +719,50 @@ mod tests {
         assert!(result.iter().any(|c| c.display_text() == "掖"));
         Ok(())
     }
+
+    #[test]
+    fn it_gets_emoji_candidates() -> Result<()> {
+        let (engine, _) = test_harness();
+        let cands = get_candidates(&engine, ":chhio")?;
+        assert_eq!(cands[0].display_text(), "😀");
+        assert_eq!(cands[0].raw_text(), ":chhio");
+        let cands = get_candidates(&engine, ":smile")?;
+        assert!(cands.iter().any(|c| c.display_text() == "😼"));
+        Ok(())
+    }
+
+    #[test]
+    fn it_gets_punctuation_candidates() -> Result<()> {
+        let (mut engine, _) = test_harness();
+        engine.conf.set_output_mode(OutputMode::Hanji);
+        let cands = get_candidates(&engine, "<")?;
+        assert_eq!(cands.len(), 2);
+        assert_eq!(cands[0].display_text(), "〈");
+        assert_eq!(cands[0].raw_text(), "<");
+        let comp = convert_all(&engine, "ho2.")?;
+        assert!(comp.display_text().ends_with("。"));
+        assert!(comp.raw_text().ends_with("."));
+        Ok(())
+    }
+
+    #[test]
+    fn it_uses_configured_telex_keys() -> Result<()> {
+        let (mut engine, _) = test_harness();
+        engine.conf.set_key_config(KeyConfig {
+            nasal: 'q',
+            dots_below: Some('z'),
+            uppercase_nasal: false,
+            digits_as_fallback: true,
+            ..Default::default()
+        })?;
+        let telex = |raw: &str, key: char| {
+            convert_to_telex(&engine, raw, key).0.unwrap().display_text()
+        };
+        assert_eq!(telex("a", 'q'), "aⁿ");
+        assert_eq!(telex("A", 'Q'), "Aⁿ");
+        assert_eq!(telex("o", 'z'), "o̤");
+        assert_eq!(telex("ho", '2'), "hó");
+        assert_eq!(telex("ho", 's'), "hó");
+        Ok(())
+    }
 }
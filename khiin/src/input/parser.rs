@@ -1,3 +1,4 @@
+use khiin_ji::punctuation::is_punctuation_key;
 use khiin_ji::ToByteLen;
 
 use crate::data::dictionary::Dictionary;
@@ -8,17 +9,31 @@ pub enum SectionType {
     Hyphens,
     Punct,
     Splittable,
+    Emoji,
 }
 
+/// Typing this character followed by a keyword searches for emojis
+pub(crate) const EMOJI_TRIGGER: char = ':';
+
 pub(crate) fn parse_longest_from_start<'a>(
     dict: &Dictionary,
     raw_buffer: &'a str,
 ) -> (SectionType, &'a str) {
+    let len = emoji_query_len(raw_buffer);
+    if len > 0 {
+        return (SectionType::Emoji, &raw_buffer[..len]);
+    }
+
     let len = max_segmentable_len(dict, raw_buffer);
     if len > 0 {
         return (SectionType::Splittable, &raw_buffer[..len]);
     }
 
+    let len = punct_len(raw_buffer);
+    if len > 0 {
+        return (SectionType::Punct, &raw_buffer[..len]);
+    }
+
     (SectionType::Plaintext, raw_buffer)
 }
 
@@ -35,6 +50,32 @@ fn max_segmentable_len(dict: &Dictionary, raw_buffer: &str) -> usize {
     raw_buffer.to_byte_len(char_count)
 }
 
+/// Returns byte length of an emoji query, being the trigger character followed
+/// by at least one ASCII letter or digit
+fn emoji_query_len(raw_buffer: &str) -> usize {
+    match raw_buffer.strip_prefix(EMOJI_TRIGGER) {
+        Some(rest) => {
+            let len = rest
+                .find(|c: char| !c.is_ascii_alphanumeric())
+                .unwrap_or(rest.len());
+            if len > 0 {
+                len + EMOJI_TRIGGER.len_utf8()
+            } else {
+                0
+            }
+        },
+        None => 0,
+    }
+}
+
+/// Returns byte length of a single punctuation key at the start of the input
+fn punct_len(raw_buffer: &str) -> usize {
+    match raw_buffer.chars().next() {
+        Some(ch) if is_punctuation_key(ch) => ch.len_utf8(),
+        _ => 0,
+    }
+}
+
 /// Iterates over the input string, matching the string against the functions
 /// provided in the while loop. These functions should provide the length (in
 /// bytes) of the consumed text.
@@ -67,6 +108,24 @@ fn parse_input_to_sections<'a>(
             }
         }
 
+        if !done {
+            let bytes = emoji_query_len(remaining);
+            if bytes > 0 {
+                parsed_type = SectionType::Emoji;
+                parsed_len = bytes;
+                done = true;
+            }
+        }
+
+        if !done {
+            let bytes = punct_len(remaining);
+            if bytes > 0 {
+                parsed_type = SectionType::Punct;
+                parsed_len = bytes;
+                done = true;
+            }
+        }
+
         if !done {
             // TODO check for a different segment type
         }
@@ -145,4 +204,27 @@ mod tests {
         assert_eq!(result[1].0, SectionType::Plaintext);
         assert_eq!(result[1].1, "zzz");
     }
+
+    #[test]
+    fn it_finds_emoji_queries() {
+        let dict = get_dict();
+        let result = parse_whole_input(&dict, "ho2:smile");
+        assert_eq!(result.len(), 2);
+        assert_eq!(result[1].0, SectionType::Emoji);
+        assert_eq!(result[1].1, ":smile");
+
+        let result = parse_whole_input(&dict, ":");
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].0, SectionType::Punct);
+    }
+
+    #[test]
+    fn it_finds_punctuation() {
+        let dict = get_dict();
+        let result = parse_whole_input(&dict, "ho2.zzz");
+        assert_eq!(result.len(), 3);
+        assert_eq!(result[1].0, SectionType::Punct);
+        assert_eq!(result[1].1, ".");
+        assert_eq!(result[2].0, SectionType::Plaintext);
+    }
 }
@@ -0,0 +1,120 @@
+use khiin_ji::punctuation::hanji_punctuation;
+use khiin_ji::punctuation::lomaji_punctuation;
+use khiin_ji::punctuation::to_fullwidth;
+use khiin_ji::punctuation::Punctuation;
+
+use crate::config::Config;
+use crate::config::PunctWidth;
+
+/// Output of a punctuation key for the current output mode and
+/// punctuation width
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum PunctOutput {
+    Direct(String),
+    Menu(Vec<String>),
+}
+
+impl PunctOutput {
+    /// Text shown in the composition before a menu item is chosen
+    pub fn first(&self) -> &str {
+        match self {
+            PunctOutput::Direct(text) => text,
+            PunctOutput::Menu(items) => &items[0],
+        }
+    }
+}
+
+pub(crate) fn punct_output(conf: &Config, key: char) -> Option<PunctOutput> {
+    let spec = if conf.is_hanji_first() {
+        hanji_punctuation(key)?
+    } else {
+        lomaji_punctuation(key)?
+    };
+
+    let preferred = match conf.punct_width() {
+        PunctWidth::Auto => None,
+        PunctWidth::Half => Some(key.to_string()),
+        PunctWidth::Whole => to_fullwidth(key).map(String::from),
+    };
+
+    match spec {
+        Punctuation::Direct(text) => {
+            let text = match conf.punct_width() {
+                PunctWidth::Auto => text.to_string(),
+                // Ideographic punctuation (e.g. 。) is already full width
+                PunctWidth::Whole if !text.is_ascii() => text.to_string(),
+                _ => preferred.unwrap_or_else(|| text.to_string()),
+            };
+            Some(PunctOutput::Direct(text))
+        },
+        Punctuation::Menu(items) => {
+            let mut items: Vec<String> =
+                items.iter().map(|s| s.to_string()).collect();
+            if let Some(preferred) = preferred {
+                move_to_front(&mut items, |item| *item == preferred);
+            }
+            Some(PunctOutput::Menu(items))
+        },
+    }
+}
+
+/// Moves the first matching item to the front, keeping the rest in order
+pub(crate) fn move_to_front<T, F>(items: &mut Vec<T>, pred: F)
+where
+    F: Fn(&T) -> bool,
+{
+    if let Some(pos) = items.iter().position(pred) {
+        let item = items.remove(pos);
+        items.insert(0, item);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::OutputMode;
+    use crate::tests::*;
+
+    fn conf(output_mode: OutputMode, width: PunctWidth) -> Config {
+        let mut conf = get_conf();
+        conf.set_output_mode(output_mode);
+        conf.set_punct_width(width);
+        conf
+    }
+
+    #[test]
+    fn it_follows_the_output_mode() {
+        let c = conf(OutputMode::Hanji, PunctWidth::Auto);
+        assert_eq!(
+            punct_output(&c, '.'),
+            Some(PunctOutput::Direct("。".into()))
+        );
+        let c = conf(OutputMode::Lomaji, PunctWidth::Auto);
+        assert_eq!(
+            punct_output(&c, '.'),
+            Some(PunctOutput::Direct(".".into()))
+        );
+        assert_eq!(punct_output(&c, 'a'), None);
+    }
+
+    #[test]
+    fn it_honors_the_punctuation_width() {
+        let c = conf(OutputMode::Hanji, PunctWidth::Half);
+        assert_eq!(
+            punct_output(&c, '!'),
+            Some(PunctOutput::Direct("!".into()))
+        );
+        let c = conf(OutputMode::Lomaji, PunctWidth::Whole);
+        assert_eq!(
+            punct_output(&c, '!'),
+            Some(PunctOutput::Direct("！".into()))
+        );
+        let c = conf(OutputMode::Hanji, PunctWidth::Whole);
+        assert_eq!(
+            punct_output(&c, '.'),
+            Some(PunctOutput::Direct("。".into()))
+        );
+        let out = punct_output(&c, '=').unwrap();
+        assert_eq!(out.first(), "＝");
+    }
+}
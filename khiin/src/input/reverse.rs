@@ -0,0 +1,175 @@
+use anyhow::Result;
+use khiin_ji::IsHanji;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::db::models::Reading;
+use crate::db::DictionaryBackend;
+
+/// Longest dictionary output tried at each position, in chars
+const MAX_WORD_CHARS: usize = 12;
+
+/// A piece of the text read by `Engine::hanji_to_poj`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReadingSegment {
+    /// A word found in the dictionary, or text without a reading, such as
+    /// spaces and punctuation
+    pub text: String,
+
+    /// POJ readings of `text`, most likely first, with syllables joined by
+    /// hyphens. Empty if `text` is not in the dictionary.
+    pub readings: Vec<String>,
+}
+
+/// Splits Hanji or Hanlo `text` into the longest outputs found in the
+/// dictionary, from left to right, and reads each of them out in POJ. Text
+/// that does not start any known output is passed through: Lomaji words
+/// whole, anything else one char at a time, joined with the unmatched text
+/// next to it.
+pub(crate) fn hanji_to_poj(
+    db: &dyn DictionaryBackend,
+    text: &str,
+) -> Result<Vec<ReadingSegment>> {
+    let text: Vec<char> = text.nfc().collect();
+    let mut segments: Vec<ReadingSegment> = Vec::new();
+    let mut start = 0;
+
+    while start < text.len() {
+        if let Some((end, readings)) = longest_match(db, &text, start)? {
+            segments.push(ReadingSegment {
+                text: text[start..end].iter().collect(),
+                readings,
+            });
+            start = end;
+            continue;
+        }
+
+        let end = unmatched_end(&text, start);
+        let unmatched: String = text[start..end].iter().collect();
+        match segments.last_mut() {
+            Some(last) if last.readings.is_empty() => {
+                last.text.push_str(&unmatched)
+            },
+            _ => segments.push(ReadingSegment {
+                text: unmatched,
+                readings: Vec::new(),
+            }),
+        }
+        start = end;
+    }
+
+    Ok(segments)
+}
+
+/// The end of the longest output starting at `start`, with its readings.
+/// Outputs that would end inside a Lomaji word are not matched.
+fn longest_match(
+    db: &dyn DictionaryBackend,
+    text: &[char],
+    start: usize,
+) -> Result<Option<(usize, Vec<String>)>> {
+    let max_end = text.len().min(start + MAX_WORD_CHARS);
+    let words: Vec<String> = (start + 1..=max_end)
+        .filter(|&end| is_word_end(text, end))
+        .map(|end| text[start..end].iter().collect())
+        .collect();
+
+    if words.is_empty() {
+        return Ok(None);
+    }
+
+    let words: Vec<&str> = words.iter().map(String::as_str).collect();
+    let rows = db.select_readings(&words)?;
+
+    // Longest outputs come first
+    let output = match rows.first() {
+        Some(row) => row.output.clone(),
+        None => return Ok(None),
+    };
+
+    let end = start + output.chars().count();
+    Ok(Some((end, readings_of(&rows, &output))))
+}
+
+fn readings_of(rows: &[Reading], output: &str) -> Vec<String> {
+    let mut readings: Vec<String> = Vec::new();
+
+    for row in rows.iter().filter(|row| row.output == output) {
+        let reading = row.input.replace(' ', "-");
+        if !readings.contains(&reading) {
+            readings.push(reading);
+        }
+    }
+
+    readings
+}
+
+fn is_lomaji_char(c: char) -> bool {
+    !c.is_hanji()
+        && (c.is_alphanumeric()
+            || c == '-'
+            || ('\u{0300}'..='\u{036f}').contains(&c))
+}
+
+fn is_word_end(text: &[char], end: usize) -> bool {
+    end == text.len()
+        || !is_lomaji_char(text[end - 1])
+        || !is_lomaji_char(text[end])
+}
+
+fn unmatched_end(text: &[char], start: usize) -> usize {
+    if !is_lomaji_char(text[start]) {
+        return start + 1;
+    }
+
+    let len = text[start..]
+        .iter()
+        .take_while(|&&c| is_lomaji_char(c))
+        .count();
+    start + len
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::get_db;
+
+    use super::*;
+
+    fn texts(segments: &[ReadingSegment]) -> Vec<&str> {
+        segments.iter().map(|s| s.text.as_str()).collect()
+    }
+
+    #[test]
+    fn it_reads_hanji() {
+        let db = get_db();
+        let result = hanji_to_poj(&db, "好").unwrap();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].readings[0], "hó");
+        assert!(result[0].readings.len() > 1);
+    }
+
+    #[test]
+    fn it_segments_by_longest_match() {
+        let db = get_db();
+        let result = hanji_to_poj(&db, "台灣").unwrap();
+        assert_eq!(texts(&result), vec!["台灣"]);
+        assert!(result[0].readings.iter().all(|r| r.contains('-')));
+    }
+
+    #[test]
+    fn it_passes_through_unknown_text() {
+        let db = get_db();
+        let result = hanji_to_poj(&db, "好，xyzzy！").unwrap();
+        assert_eq!(texts(&result), vec!["好", "，xyzzy！"]);
+        assert!(result[1].readings.is_empty());
+        assert!(hanji_to_poj(&db, "").unwrap().is_empty());
+    }
+
+    #[test]
+    fn it_does_not_split_lomaji_words() {
+        let text: Vec<char> = "beh khì".chars().collect();
+        assert!(!is_word_end(&text, 2));
+        assert!(is_word_end(&text, 3));
+        assert_eq!(unmatched_end(&text, 0), 3);
+        assert_eq!(unmatched_end(&text, 3), 4);
+    }
+}
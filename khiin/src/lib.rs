@@ -4,7 +4,11 @@
 pub mod db;
 pub mod engine;
 
+pub use crate::data::compiled_index::index_path;
+pub use crate::data::CompiledIndex;
+pub use crate::data::SharedDictionary;
 pub use crate::engine::Engine;
+pub use crate::input::reverse::ReadingSegment;
 
 pub(crate) mod buffer;
 pub(crate) mod config;
@@ -11,6 +11,30 @@ pub fn mock_send_key_request(ch: char) -> Request {
     req
 }
 
+pub fn mock_special_key_request(
+    key: SpecialKey,
+    modifiers: &[ModifierKey],
+) -> Request {
+    let key_event = KeyEvent {
+        special_key: key.into(),
+        modifier_keys: modifiers.iter().map(|&m| m.into()).collect(),
+        ..Default::default()
+    };
+    Request {
+        type_: CommandType::CMD_SEND_KEY.into(),
+        key_event: Some(key_event).into(),
+        ..Default::default()
+    }
+}
+
+pub fn mock_candidate_request(cmd: CommandType, id: i32) -> Request {
+    Request {
+        type_: cmd.into(),
+        candidate_id: id,
+        ..Default::default()
+    }
+}
+
 fn mock_command(cmd: &mut Command) {
     let mut cand = Candidate::new();
     cand.id = 1;
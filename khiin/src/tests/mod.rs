@@ -3,6 +3,7 @@
 pub(crate) mod mock_protos;
 
 use std::path::PathBuf;
+use std::sync::Arc;
 
 // use khiin_db::models;
 // use khiin_db::models::KeyConversion;
@@ -17,6 +18,7 @@ use crate::data::Dictionary;
 use crate::db::models::InputType;
 use crate::db::models::KeyConversion;
 use crate::db::Database;
+use crate::db::OpenMode;
 use crate::engine::EngInner;
 use crate::Engine;
 
@@ -32,12 +34,11 @@ pub(crate) fn debug_db_path() -> PathBuf {
 
 pub(crate) fn get_db() -> Database {
     let db_path = debug_db_path();
-    Database::new(db_path.to_str().unwrap()).unwrap()
+    Database::new(db_path.to_str().unwrap(), OpenMode::Memory).unwrap()
 }
 
 pub(crate) fn get_engine() -> Option<Engine> {
-    let filename = debug_db_path();
-    Engine::new(filename)
+    Engine::with_backend(get_db())
 }
 
 pub(crate) fn get_dict() -> Dictionary {
@@ -45,8 +46,11 @@ pub(crate) fn get_dict() -> Dictionary {
     Dictionary::new(&db, ToneMode::Numeric).expect("Could not load dictionary")
 }
 
+/// Types numeric tones, to match `get_dict`
 pub fn get_conf() -> Config {
-    Config::new()
+    let mut conf = Config::new();
+    conf.set_tone_mode(ToneMode::Numeric);
+    conf
 }
 
 pub fn mock_conversion(input: &str, output: &str) -> KeyConversion {
@@ -63,12 +67,18 @@ pub fn mock_conversion(input: &str, output: &str) -> KeyConversion {
     }
 }
 
+/// An engine with the dictionary for the tone mode of its config, as the
+/// engine itself always loads
 pub(crate) fn test_harness() -> (EngInner, BufferMgr) {
+    let db = get_db();
+    let conf = get_conf();
+    let dict = Dictionary::new(&db, conf.tone_mode())
+        .expect("Could not load dictionary");
     (
         EngInner {
-            db: get_db(),
-            dict: get_dict(),
-            conf: get_conf(),
+            db: Box::new(db),
+            dict: Arc::new(dict),
+            conf,
         },
         BufferMgr::new(),
     )
@@ -0,0 +1,48 @@
+use std::path::PathBuf;
+use std::process::Command;
+
+use anyhow::anyhow;
+use anyhow::Result;
+use clap::Parser;
+
+#[derive(Parser, Debug)]
+pub struct Args {
+    /// IBus bus address, by default `$IBUS_ADDRESS` or from `ibus address`
+    #[arg(short, long)]
+    pub address: Option<String>,
+
+    /// Database file, by default `khiin.db` next to the executable
+    #[arg(short, long)]
+    pub db_file: Option<PathBuf>,
+}
+
+impl Args {
+    /// IBus runs its own bus, separate from the session bus
+    pub fn address(&self) -> Result<String> {
+        if let Some(address) = &self.address {
+            return Ok(address.clone());
+        }
+
+        if let Ok(address) = std::env::var("IBUS_ADDRESS") {
+            return Ok(address);
+        }
+
+        let output = Command::new("ibus").arg("address").output()?;
+        let address = String::from_utf8(output.stdout)?.trim().to_string();
+        if !output.status.success() || address.is_empty() {
+            return Err(anyhow!("Unable to find the IBus address"));
+        }
+
+        Ok(address)
+    }
+
+    pub fn db_path(&self) -> Result<PathBuf> {
+        if let Some(db_file) = &self.db_file {
+            return Ok(db_file.clone());
+        }
+
+        let mut db_path = std::env::current_exe()?;
+        db_path.set_file_name("khiin.db");
+        Ok(db_path)
+    }
+}
@@ -0,0 +1,416 @@
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::PoisonError;
+
+use khiin::engine::commands;
+use khiin::Engine;
+use khiin_protos::command::CandidateList;
+use khiin_protos::command::Command;
+use khiin_protos::command::CommandType;
+use khiin_protos::command::EditState;
+use khiin_protos::command::ErrorCode;
+use khiin_protos::command::KeyEvent;
+use khiin_protos::command::Preedit;
+use khiin_protos::command::Request;
+use khiin_protos::command::Response;
+use khiin_protos::command::SpecialKey;
+use zbus::dbus_interface;
+use zbus::fdo;
+use zbus::zvariant::Value;
+use zbus::MessageHeader;
+use zbus::ObjectServer;
+use zbus::SignalContext;
+
+use crate::keys::translate_key;
+use crate::keys::KEYCODE_LEFT;
+use crate::keys::KEY_LEFT;
+use crate::keys::RELEASE_MASK;
+use crate::text;
+
+// From ibustypes.h
+const PREEDIT_CLEAR: u32 = 0;
+
+pub type SharedEngine = Arc<Mutex<Engine>>;
+
+/// The session of an input context, which both of its D-Bus interfaces
+/// use, and which is replaced if the engine drops it
+pub type SessionId = Arc<AtomicU64>;
+
+/// Sends `cmd` to the engine as part of `session_id`, returning the
+/// response only if the engine handled it
+pub fn send_command(
+    engine: &SharedEngine,
+    session_id: u64,
+    cmd: Command,
+) -> Option<Response> {
+    checked(request(engine, session_id, cmd))
+}
+
+pub fn create_session(engine: &SharedEngine) -> Option<u64> {
+    let cmd = commands::simple(CommandType::CMD_CREATE_SESSION);
+    send_command(engine, 0, cmd).map(|res| res.session_id)
+}
+
+fn request(
+    engine: &SharedEngine,
+    session_id: u64,
+    mut cmd: Command,
+) -> Response {
+    cmd.request.mut_or_insert_default().session_id = session_id;
+    let cmd = engine
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .send_command(cmd);
+    cmd.response.into_option().unwrap_or_default()
+}
+
+fn checked(res: Response) -> Option<Response> {
+    if res.error.enum_value_or_default() != ErrorCode::OK {
+        log::error!("{:?}: {}", res.error, res.error_message);
+        return None;
+    }
+
+    Some(res)
+}
+
+/// One IBus input context, with its own session in the shared engine
+pub struct KhiinEngine {
+    engine: SharedEngine,
+    session_id: SessionId,
+    candidates: CandidateList,
+    content_type: (u32, u32),
+}
+
+impl KhiinEngine {
+    pub fn new(engine: SharedEngine, session_id: SessionId) -> Self {
+        Self {
+            engine,
+            session_id,
+            candidates: CandidateList::default(),
+            content_type: (0, 0),
+        }
+    }
+
+    /// Sends `cmd`, first starting a new session if the engine dropped
+    /// this one after it was left idle
+    fn send(&self, cmd: Command) -> Option<Response> {
+        let session_id = self.session_id.load(Ordering::SeqCst);
+        let res = request(&self.engine, session_id, cmd.clone());
+        if res.error.enum_value_or_default() != ErrorCode::UNKNOWN_SESSION {
+            return checked(res);
+        }
+
+        let new_id = create_session(&self.engine)?;
+        log::debug!("Session {} replaced by {}", session_id, new_id);
+        self.session_id.store(new_id, Ordering::SeqCst);
+        send_command(&self.engine, new_id, cmd)
+    }
+
+    /// Commits any finished text, then shows what is left of the
+    /// composition and its candidates. When the whole composition is
+    /// committed, the engine may leave `committed_text` empty and send the
+    /// text in the preedit instead, as the other apps expect.
+    async fn render(
+        &mut self,
+        ctxt: &SignalContext<'_>,
+        res: Response,
+    ) -> zbus::Result<()> {
+        let mut preedit = res.preedit.into_option().unwrap_or_default();
+        let mut candidates =
+            res.candidate_list.into_option().unwrap_or_default();
+
+        if res.committed {
+            let committed_text = if res.committed_text.is_empty() {
+                preedit.segments.iter().map(|s| s.value.as_str()).collect()
+            } else {
+                res.committed_text
+            };
+
+            if !committed_text.is_empty() {
+                let text = text::plain_text(&committed_text);
+                Self::commit_text(ctxt, text).await?;
+                Self::move_caret_back(ctxt, res.chars_after_caret).await?;
+            }
+
+            if res.edit_state.enum_value_or_default() == EditState::ES_EMPTY {
+                preedit = Preedit::default();
+                candidates = CandidateList::default();
+            }
+        }
+
+        let visible = !preedit.segments.is_empty();
+        let caret = preedit.caret.max(0) as u32;
+        let text = text::preedit_text(&preedit);
+        Self::update_preedit_text(ctxt, text, caret, visible, PREEDIT_CLEAR)
+            .await?;
+
+        self.candidates = candidates;
+        let visible = !self.candidates.candidates.is_empty();
+        let table = text::lookup_table(&self.candidates);
+        Self::update_lookup_table(ctxt, table, visible).await
+    }
+
+    /// Moves the caret of the app back over `chars` committed chars, e.g.
+    /// into a pair of brackets committed together
+    async fn move_caret_back(
+        ctxt: &SignalContext<'_>,
+        chars: u32,
+    ) -> zbus::Result<()> {
+        for _ in 0..chars {
+            for state in [0, RELEASE_MASK] {
+                Self::forward_key_event(ctxt, KEY_LEFT, KEYCODE_LEFT, state)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends `cmd` and shows the result. A key the engine did not act on
+    /// leaves the composition as it was, so nothing is shown for it.
+    async fn send_and_render(
+        &mut self,
+        ctxt: &SignalContext<'_>,
+        cmd: Command,
+    ) {
+        let is_key = cmd.request.type_.enum_value_or_default()
+            == CommandType::CMD_SEND_KEY;
+
+        if let Some(res) = self.send(cmd) {
+            if is_key && !res.consumable {
+                return;
+            }
+
+            if let Err(e) = self.render(ctxt, res).await {
+                log::error!("Unable to update the panel: {}", e);
+            }
+        }
+    }
+
+    async fn send_special_key(
+        &mut self,
+        ctxt: &SignalContext<'_>,
+        key: SpecialKey,
+    ) {
+        let key_event = KeyEvent {
+            special_key: key.into(),
+            ..Default::default()
+        };
+        self.send_and_render(ctxt, commands::send_key(key_event))
+            .await;
+    }
+
+    /// Focuses the first candidate `pages` pages away from the one shown,
+    /// if there is one
+    async fn turn_page(&mut self, ctxt: &SignalContext<'_>, pages: isize) {
+        let page_size = text::PAGE_SIZE as isize;
+        let start = text::page_start(&self.candidates) as isize;
+        let index = start + pages * page_size;
+        if index < 0 {
+            return;
+        }
+
+        let id = match self.candidates.candidates.get(index as usize) {
+            Some(candidate) => candidate.id,
+            None => return,
+        };
+
+        let cmd = commands::command(Request {
+            type_: CommandType::CMD_FOCUS_CANDIDATE.into(),
+            candidate_id: id,
+            ..Default::default()
+        });
+        self.send_and_render(ctxt, cmd).await;
+    }
+}
+
+#[dbus_interface(name = "org.freedesktop.IBus.Engine")]
+impl KhiinEngine {
+    /// Returns whether the key was used, otherwise it goes to the app
+    async fn process_key_event(
+        &mut self,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+        keyval: u32,
+        _keycode: u32,
+        state: u32,
+    ) -> bool {
+        let key_event = match translate_key(keyval, state) {
+            Some(key_event) => key_event,
+            None => return false,
+        };
+
+        match self.send(commands::send_key(key_event)) {
+            Some(res) if res.consumable => {
+                if let Err(e) = self.render(&ctxt, res).await {
+                    log::error!("Unable to update the panel: {}", e);
+                }
+                true
+            },
+            _ => false,
+        }
+    }
+
+    async fn candidate_clicked(
+        &mut self,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+        index: u32,
+        _button: u32,
+        _state: u32,
+    ) {
+        let index = text::page_start(&self.candidates) + index as usize;
+        let id = match self.candidates.candidates.get(index) {
+            Some(candidate) => candidate.id,
+            None => return,
+        };
+
+        let cmd = commands::command(Request {
+            type_: CommandType::CMD_SELECT_CANDIDATE.into(),
+            candidate_id: id,
+            ..Default::default()
+        });
+        self.send_and_render(&ctxt, cmd).await;
+    }
+
+    async fn page_up(
+        &mut self,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+    ) {
+        self.turn_page(&ctxt, -1).await;
+    }
+
+    async fn page_down(
+        &mut self,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+    ) {
+        self.turn_page(&ctxt, 1).await;
+    }
+
+    async fn cursor_up(
+        &mut self,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+    ) {
+        self.send_special_key(&ctxt, SpecialKey::SK_UP).await;
+    }
+
+    async fn cursor_down(
+        &mut self,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+    ) {
+        self.send_special_key(&ctxt, SpecialKey::SK_DOWN).await;
+    }
+
+    async fn reset(&mut self, #[zbus(signal_context)] ctxt: SignalContext<'_>) {
+        let cmd = commands::simple(CommandType::CMD_RESET);
+        self.send_and_render(&ctxt, cmd).await;
+    }
+
+    async fn focus_out(
+        &mut self,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+    ) {
+        self.reset(ctxt).await;
+    }
+
+    async fn disable(
+        &mut self,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+    ) {
+        self.reset(ctxt).await;
+    }
+
+    fn focus_in(&self) {}
+
+    fn enable(&self) {}
+
+    fn set_capabilities(&self, _caps: u32) {}
+
+    fn set_cursor_location(&self, _x: i32, _y: i32, _w: i32, _h: i32) {}
+
+    fn set_surrounding_text(
+        &self,
+        _text: Value<'_>,
+        _cursor_pos: u32,
+        _anchor_pos: u32,
+    ) {
+    }
+
+    fn property_activate(&self, _name: &str, _state: u32) {}
+
+    fn property_show(&self, _name: &str) {}
+
+    fn property_hide(&self, _name: &str) {}
+
+    #[dbus_interface(property)]
+    fn content_type(&self) -> (u32, u32) {
+        self.content_type
+    }
+
+    #[dbus_interface(property)]
+    fn set_content_type(&mut self, content_type: (u32, u32)) {
+        self.content_type = content_type;
+    }
+
+    #[dbus_interface(signal)]
+    async fn commit_text(
+        ctxt: &SignalContext<'_>,
+        text: Value<'_>,
+    ) -> zbus::Result<()>;
+
+    #[dbus_interface(signal)]
+    async fn update_preedit_text(
+        ctxt: &SignalContext<'_>,
+        text: Value<'_>,
+        cursor_pos: u32,
+        visible: bool,
+        mode: u32,
+    ) -> zbus::Result<()>;
+
+    #[dbus_interface(signal)]
+    async fn forward_key_event(
+        ctxt: &SignalContext<'_>,
+        keyval: u32,
+        keycode: u32,
+        state: u32,
+    ) -> zbus::Result<()>;
+
+    #[dbus_interface(signal)]
+    async fn update_lookup_table(
+        ctxt: &SignalContext<'_>,
+        table: Value<'_>,
+        visible: bool,
+    ) -> zbus::Result<()>;
+}
+
+/// Lets IBus remove an engine when its input context goes away
+pub struct EngineService {
+    engine: SharedEngine,
+    session_id: SessionId,
+}
+
+impl EngineService {
+    pub fn new(engine: SharedEngine, session_id: SessionId) -> Self {
+        Self { engine, session_id }
+    }
+}
+
+#[dbus_interface(name = "org.freedesktop.IBus.Service")]
+impl EngineService {
+    async fn destroy(
+        &self,
+        #[zbus(object_server)] server: &ObjectServer,
+        #[zbus(header)] header: MessageHeader<'_>,
+    ) -> fdo::Result<()> {
+        let path = header
+            .path()?
+            .ok_or(fdo::Error::Failed("Missing object path".into()))?
+            .to_owned();
+
+        let cmd = commands::simple(CommandType::CMD_DESTROY_SESSION);
+        let session_id = self.session_id.load(Ordering::SeqCst);
+        send_command(&self.engine, session_id, cmd);
+        server.remove::<KhiinEngine, _>(&path).await?;
+        server.remove::<EngineService, _>(&path).await?;
+        Ok(())
+    }
+}
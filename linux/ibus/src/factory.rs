@@ -0,0 +1,281 @@
+use std::sync::atomic::AtomicU64;
+use std::sync::Arc;
+
+use khiin::engine::commands;
+use khiin_protos::config::AppConfig;
+use khiin_protos::config::AppInputMode;
+use khiin_protos::config::AppKhinMode;
+use zbus::dbus_interface;
+use zbus::fdo;
+use zbus::zvariant::OwnedObjectPath;
+use zbus::ObjectServer;
+
+use crate::engine::create_session;
+use crate::engine::send_command;
+use crate::engine::EngineService;
+use crate::engine::KhiinEngine;
+use crate::engine::SharedEngine;
+
+/// Well-known name of the component, which must match `khiin.xml`
+pub const COMPONENT_NAME: &str = "org.freedesktop.IBus.Khiin";
+pub const ENGINE_NAME: &str = "khiin";
+pub const FACTORY_PATH: &str = "/org/freedesktop/IBus/Factory";
+const ENGINE_PATH: &str = "/org/freedesktop/IBus/Engine";
+
+/// Creates an engine for each input context IBus asks for. All of them
+/// share one `Engine`, with a session each.
+pub struct Factory {
+    engine: SharedEngine,
+    engine_count: u32,
+}
+
+impl Factory {
+    /// Puts `engine` in continuous mode, since it starts in manual mode,
+    /// which has no candidates to show
+    pub fn new(engine: SharedEngine) -> Self {
+        let config = AppConfig {
+            input_mode: AppInputMode::CONTINUOUS.into(),
+            khin_mode: AppKhinMode::HYPHEN.into(),
+            ..Default::default()
+        };
+        if send_command(&engine, 0, commands::set_config(config)).is_none() {
+            log::error!("Unable to configure the engine");
+        }
+
+        Self {
+            engine,
+            engine_count: 0,
+        }
+    }
+}
+
+#[dbus_interface(name = "org.freedesktop.IBus.Factory")]
+impl Factory {
+    async fn create_engine(
+        &mut self,
+        #[zbus(object_server)] server: &ObjectServer,
+        name: &str,
+    ) -> fdo::Result<OwnedObjectPath> {
+        if name != ENGINE_NAME {
+            let msg = format!("Unknown engine: {}", name);
+            return Err(fdo::Error::InvalidArgs(msg));
+        }
+
+        let session_id = create_session(&self.engine)
+            .ok_or(fdo::Error::Failed("Unable to create a session".into()))?;
+
+        self.engine_count += 1;
+        let path = format!("{}/{}", ENGINE_PATH, self.engine_count);
+        let path =
+            OwnedObjectPath::try_from(path).map_err(zbus::Error::from)?;
+
+        let shared_id = Arc::new(AtomicU64::new(session_id));
+        let engine = KhiinEngine::new(self.engine.clone(), shared_id.clone());
+        server.at(&path, engine).await?;
+        let service = EngineService::new(self.engine.clone(), shared_id);
+        server.at(&path, service).await?;
+
+        log::debug!("Created engine {} for session {}", path, session_id);
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+
+    use futures::StreamExt;
+    use khiin::Engine;
+    use khiin_protos::command::CommandType;
+    use tokio::net::UnixStream;
+    use tokio::time::timeout;
+    use tokio::time::Duration;
+    use zbus::zvariant::Value;
+    use zbus::Connection;
+    use zbus::ConnectionBuilder;
+    use zbus::Guid;
+    use zbus::Message;
+    use zbus::MessageStream;
+    use zbus::MessageType;
+
+    use super::*;
+    use crate::text::PAGE_SIZE;
+
+    const ENGINE_IFACE: &str = "org.freedesktop.IBus.Engine";
+    const KEY_RETURN: u32 = 0xff0d;
+    const RELEASE_MASK: u32 = 1 << 30;
+
+    fn shared_engine() -> SharedEngine {
+        let db_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("..")
+            .join("..")
+            .join("target")
+            .join("debug")
+            .join("khiin.db");
+        Arc::new(Mutex::new(Engine::new(db_path).unwrap()))
+    }
+
+    /// Serves the factory over a private peer-to-peer bus, and returns the
+    /// other end, which stands in for ibus-daemon
+    async fn connect(engine: SharedEngine) -> (Connection, Connection) {
+        let (ours, theirs) = UnixStream::pair().unwrap();
+        let guid = Guid::generate();
+        let server = ConnectionBuilder::unix_stream(ours)
+            .server(&guid)
+            .p2p()
+            .serve_at(FACTORY_PATH, Factory::new(engine))
+            .unwrap()
+            .build();
+        let client = ConnectionBuilder::unix_stream(theirs).p2p().build();
+        futures::try_join!(server, client).unwrap()
+    }
+
+    async fn create_engine(
+        ibus: &Connection,
+        name: &str,
+    ) -> zbus::Result<OwnedObjectPath> {
+        let factory = Some("org.freedesktop.IBus.Factory");
+        ibus.call_method(
+            None::<&str>,
+            FACTORY_PATH,
+            factory,
+            "CreateEngine",
+            &(name,),
+        )
+        .await?
+        .body()
+    }
+
+    async fn process_key(
+        ibus: &Connection,
+        path: &OwnedObjectPath,
+        keyval: u32,
+        state: u32,
+    ) -> bool {
+        let body = (keyval, 0u32, state);
+        ibus.call_method(
+            None::<&str>,
+            path,
+            Some(ENGINE_IFACE),
+            "ProcessKeyEvent",
+            &body,
+        )
+        .await
+        .unwrap()
+        .body()
+        .unwrap()
+    }
+
+    async fn next_signal(
+        messages: &mut MessageStream,
+        member: &str,
+    ) -> Arc<Message> {
+        let wait = async {
+            while let Some(msg) = messages.next().await {
+                let msg = msg.unwrap();
+                if msg.message_type() == MessageType::Signal
+                    && msg.member().is_some_and(|m| m == member)
+                {
+                    return msg;
+                }
+            }
+            panic!("Connection closed");
+        };
+        timeout(Duration::from_secs(5), wait).await.unwrap()
+    }
+
+    async fn call(ibus: &Connection, path: &OwnedObjectPath, method: &str) {
+        ibus.call_method(None::<&str>, path, Some(ENGINE_IFACE), method, &())
+            .await
+            .unwrap();
+    }
+
+    fn cursor_pos_of(value: &Value) -> u32 {
+        match value {
+            Value::Structure(s) => match &s.fields()[3] {
+                Value::U32(pos) => *pos,
+                v => panic!("Not a u32: {:?}", v),
+            },
+            v => panic!("Not an IBusLookupTable: {:?}", v),
+        }
+    }
+
+    fn text_of(value: &Value) -> String {
+        match value {
+            Value::Structure(s) => match &s.fields()[2] {
+                Value::Str(text) => text.to_string(),
+                v => panic!("Not a string: {:?}", v),
+            },
+            v => panic!("Not an IBusText: {:?}", v),
+        }
+    }
+
+    #[tokio::test]
+    async fn it_serves_engines_to_ibus() {
+        let (_conn, ibus) = connect(shared_engine()).await;
+        let mut messages = MessageStream::from(&ibus);
+
+        assert!(create_engine(&ibus, "pinyin").await.is_err());
+        let path = create_engine(&ibus, ENGINE_NAME).await.unwrap();
+
+        assert!(process_key(&ibus, &path, 'a' as u32, 0).await);
+        let msg = next_signal(&mut messages, "UpdatePreeditText").await;
+        let (text, caret, visible, _): (Value, u32, bool, u32) =
+            msg.body().unwrap();
+        let preedit = text_of(&text);
+        assert!(!preedit.is_empty());
+        assert_eq!(caret, preedit.chars().count() as u32);
+        assert!(visible);
+        next_signal(&mut messages, "UpdateLookupTable").await;
+
+        // There is no page before the first one, so the composition stays
+        // as it is, and the next update comes from the page after it
+        call(&ibus, &path, "PageUp").await;
+        call(&ibus, &path, "PageDown").await;
+        let msg = next_signal(&mut messages, "UpdatePreeditText").await;
+        let (text, _, visible, _): (Value, u32, bool, u32) =
+            msg.body().unwrap();
+        let preedit = text_of(&text);
+        assert!(visible);
+        let msg = next_signal(&mut messages, "UpdateLookupTable").await;
+        let (table, visible): (Value, bool) = msg.body().unwrap();
+        assert_eq!(cursor_pos_of(&table), PAGE_SIZE);
+        assert!(visible);
+
+        assert!(!process_key(&ibus, &path, 'a' as u32, RELEASE_MASK).await);
+
+        assert!(process_key(&ibus, &path, KEY_RETURN, 0).await);
+        let msg = next_signal(&mut messages, "CommitText").await;
+        let text: Value = msg.body().unwrap();
+        assert_eq!(text_of(&text), preedit);
+        let msg = next_signal(&mut messages, "UpdatePreeditText").await;
+        let (_, _, visible, _): (Value, u32, bool, u32) = msg.body().unwrap();
+        assert!(!visible);
+
+        assert!(!process_key(&ibus, &path, KEY_RETURN, 0).await);
+
+        let service = Some("org.freedesktop.IBus.Service");
+        ibus.call_method(None::<&str>, &path, service, "Destroy", &())
+            .await
+            .unwrap();
+        let res = ibus
+            .call_method(None::<&str>, &path, Some(ENGINE_IFACE), "Reset", &())
+            .await;
+        assert!(res.is_err());
+    }
+    #[tokio::test]
+    async fn it_replaces_dropped_sessions() {
+        let engine = shared_engine();
+        let (_conn, ibus) = connect(engine.clone()).await;
+        let path = create_engine(&ibus, ENGINE_NAME).await.unwrap();
+
+        // As the engine does once the session has been idle for too long
+        let cmd = commands::simple(CommandType::CMD_DESTROY_SESSION);
+        let first_session = 1;
+        send_command(&engine, first_session, cmd).unwrap();
+
+        assert!(process_key(&ibus, &path, 'a' as u32, 0).await);
+    }
+}
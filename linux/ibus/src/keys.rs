@@ -0,0 +1,139 @@
+use khiin_protos::command::KeyEvent;
+use khiin_protos::command::ModifierKey;
+use khiin_protos::command::SpecialKey;
+
+// Modifier masks from ibustypes.h
+const SHIFT_MASK: u32 = 1 << 0;
+const CONTROL_MASK: u32 = 1 << 2;
+const MOD1_MASK: u32 = 1 << 3;
+pub const RELEASE_MASK: u32 = 1 << 30;
+
+// Keysyms from ibuskeysyms.h
+const KEY_SPACE: u32 = 0x020;
+const KEY_TILDE: u32 = 0x07e;
+const KEY_BACKSPACE: u32 = 0xff08;
+const KEY_TAB: u32 = 0xff09;
+const KEY_RETURN: u32 = 0xff0d;
+const KEY_ESCAPE: u32 = 0xff1b;
+const KEY_HOME: u32 = 0xff50;
+pub const KEY_LEFT: u32 = 0xff51;
+const KEY_UP: u32 = 0xff52;
+const KEY_RIGHT: u32 = 0xff53;
+const KEY_DOWN: u32 = 0xff54;
+const KEY_PAGE_UP: u32 = 0xff55;
+const KEY_PAGE_DOWN: u32 = 0xff56;
+const KEY_END: u32 = 0xff57;
+const KEY_KP_ENTER: u32 = 0xff8d;
+const KEY_DELETE: u32 = 0xffff;
+
+// From input-event-codes.h, which IBus uses for keycodes
+pub const KEYCODE_LEFT: u32 = 105;
+
+fn special_key(keyval: u32) -> SpecialKey {
+    match keyval {
+        KEY_SPACE => SpecialKey::SK_SPACE,
+        KEY_RETURN | KEY_KP_ENTER => SpecialKey::SK_ENTER,
+        KEY_ESCAPE => SpecialKey::SK_ESC,
+        KEY_BACKSPACE => SpecialKey::SK_BACKSPACE,
+        KEY_TAB => SpecialKey::SK_TAB,
+        KEY_LEFT => SpecialKey::SK_LEFT,
+        KEY_UP => SpecialKey::SK_UP,
+        KEY_RIGHT => SpecialKey::SK_RIGHT,
+        KEY_DOWN => SpecialKey::SK_DOWN,
+        KEY_PAGE_UP => SpecialKey::SK_PGUP,
+        KEY_PAGE_DOWN => SpecialKey::SK_PGDN,
+        KEY_HOME => SpecialKey::SK_HOME,
+        KEY_END => SpecialKey::SK_END,
+        KEY_DELETE => SpecialKey::SK_DEL,
+        _ => SpecialKey::SK_NONE,
+    }
+}
+
+/// Translates a key press from IBus, or returns `None` for releases and
+/// keys the engine has no use for, such as modifiers on their own
+pub fn translate_key(keyval: u32, state: u32) -> Option<KeyEvent> {
+    if state & RELEASE_MASK != 0 {
+        return None;
+    }
+
+    let mut ret = KeyEvent::new();
+    ret.special_key = special_key(keyval).into();
+
+    if (KEY_SPACE..=KEY_TILDE).contains(&keyval) {
+        ret.key_code = keyval as i32;
+    } else if ret.special_key.enum_value_or_default() == SpecialKey::SK_NONE {
+        return None;
+    }
+
+    let modifiers = [
+        (CONTROL_MASK, ModifierKey::MODK_CTRL),
+        (MOD1_MASK, ModifierKey::MODK_ALT),
+        (SHIFT_MASK, ModifierKey::MODK_SHIFT),
+    ];
+
+    for (mask, modifier) in modifiers {
+        if state & mask != 0 {
+            ret.modifier_keys.push(modifier.into());
+        }
+    }
+
+    Some(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_translates_printable_keys() {
+        let key = translate_key('a' as u32, 0).unwrap();
+        assert_eq!(key.key_code, 'a' as i32);
+        assert_eq!(
+            key.special_key.enum_value_or_default(),
+            SpecialKey::SK_NONE
+        );
+
+        let key = translate_key('A' as u32, SHIFT_MASK).unwrap();
+        assert_eq!(key.key_code, 'A' as i32);
+        assert_eq!(
+            key.modifier_keys[0].enum_value(),
+            Ok(ModifierKey::MODK_SHIFT)
+        );
+
+        let key = translate_key(' ' as u32, 0).unwrap();
+        assert_eq!(key.key_code, ' ' as i32);
+        assert_eq!(
+            key.special_key.enum_value_or_default(),
+            SpecialKey::SK_SPACE
+        );
+    }
+
+    #[test]
+    fn it_translates_special_keys() {
+        let key = translate_key(KEY_KP_ENTER, 0).unwrap();
+        assert_eq!(key.key_code, 0);
+        assert_eq!(
+            key.special_key.enum_value_or_default(),
+            SpecialKey::SK_ENTER
+        );
+
+        let key = translate_key(KEY_LEFT, CONTROL_MASK).unwrap();
+        assert_eq!(
+            key.special_key.enum_value_or_default(),
+            SpecialKey::SK_LEFT
+        );
+        assert_eq!(
+            key.modifier_keys[0].enum_value(),
+            Ok(ModifierKey::MODK_CTRL)
+        );
+    }
+
+    #[test]
+    fn it_ignores_releases_and_other_keys() {
+        assert!(translate_key('a' as u32, RELEASE_MASK).is_none());
+        // Shift_L
+        assert!(translate_key(0xffe1, SHIFT_MASK).is_none());
+        // dead_acute
+        assert!(translate_key(0xfe51, 0).is_none());
+    }
+}
@@ -0,0 +1,52 @@
+mod args;
+mod engine;
+mod factory;
+mod keys;
+mod text;
+
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use anyhow::anyhow;
+use anyhow::Result;
+use clap::Parser;
+use futures::StreamExt;
+use khiin::Engine;
+use zbus::ConnectionBuilder;
+use zbus::MessageStream;
+
+use crate::args::Args;
+use crate::factory::Factory;
+use crate::factory::COMPONENT_NAME;
+use crate::factory::FACTORY_PATH;
+
+/// Started by ibus-daemon as described in `khiin.xml`, and runs until
+/// IBus closes the connection
+#[tokio::main]
+pub async fn main() -> Result<()> {
+    env_logger::init();
+    let args = Args::parse();
+
+    let db_path = args.db_path()?;
+    let engine = Engine::new(&db_path)
+        .ok_or(anyhow!("Unable to load the database at {:?}", db_path))?;
+    let engine = Arc::new(Mutex::new(engine));
+
+    let address = args.address()?;
+    let conn = ConnectionBuilder::address(address.as_str())?
+        .serve_at(FACTORY_PATH, Factory::new(engine))?
+        .name(COMPONENT_NAME)?
+        .build()
+        .await?;
+    log::info!("Connected to IBus at {}", address);
+
+    let mut messages = MessageStream::from(&conn);
+    tokio::select! {
+        _ = async { while messages.next().await.is_some() {} } => {
+            log::info!("IBus closed the connection.");
+        },
+        _ = tokio::signal::ctrl_c() => {},
+    }
+
+    Ok(())
+}
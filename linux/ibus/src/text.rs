@@ -0,0 +1,217 @@
+//! Serialized forms of the IBus objects sent to the panel. Each one is a
+//! structure starting with its type name and a dictionary of attachments.
+
+use std::collections::HashMap;
+
+use khiin_protos::command::CandidateList;
+use khiin_protos::command::Preedit;
+use khiin_protos::command::SegmentStatus;
+use zbus::zvariant::StructureBuilder;
+use zbus::zvariant::Value;
+
+pub const PAGE_SIZE: u32 = 9;
+
+// From ibusattribute.h
+const ATTR_TYPE_UNDERLINE: u32 = 1;
+const ATTR_UNDERLINE_SINGLE: u32 = 1;
+const ATTR_UNDERLINE_DOUBLE: u32 = 2;
+const ATTR_UNDERLINE_ERROR: u32 = 4;
+
+// From ibustypes.h
+const ORIENTATION_SYSTEM: i32 = 2;
+
+fn attachments() -> HashMap<String, Value<'static>> {
+    HashMap::new()
+}
+
+/// Underline for a span of `start..end` characters
+fn underline(style: u32, start: u32, end: u32) -> Value<'static> {
+    StructureBuilder::new()
+        .add_field("IBusAttribute")
+        .add_field(attachments())
+        .add_field(ATTR_TYPE_UNDERLINE)
+        .add_field(style)
+        .add_field(start)
+        .add_field(end)
+        .build()
+        .into()
+}
+
+fn ibus_text(text: &str, attributes: Vec<Value<'static>>) -> Value<'static> {
+    let attr_list: Value = StructureBuilder::new()
+        .add_field("IBusAttrList")
+        .add_field(attachments())
+        .add_field(attributes)
+        .build()
+        .into();
+
+    StructureBuilder::new()
+        .add_field("IBusText")
+        .add_field(attachments())
+        .add_field(text.to_string())
+        .append_field(Value::new(attr_list))
+        .build()
+        .into()
+}
+
+pub fn plain_text(text: &str) -> Value<'static> {
+    ibus_text(text, Vec::new())
+}
+
+/// IBus has no dotted or thick underlines, so composing text gets the
+/// squiggle and the focused segment a double underline
+fn underline_style(status: SegmentStatus) -> Option<u32> {
+    match status {
+        SegmentStatus::SS_UNMARKED => None,
+        SegmentStatus::SS_COMPOSING => Some(ATTR_UNDERLINE_ERROR),
+        SegmentStatus::SS_CONVERTED => Some(ATTR_UNDERLINE_SINGLE),
+        SegmentStatus::SS_FOCUSED => Some(ATTR_UNDERLINE_DOUBLE),
+    }
+}
+
+pub fn preedit_text(preedit: &Preedit) -> Value<'static> {
+    let mut text = String::new();
+    let mut attributes = Vec::new();
+    let mut start = 0;
+
+    for segment in preedit.segments.iter() {
+        text.push_str(&segment.value);
+        let end = start + segment.value.chars().count() as u32;
+        let status = segment.status.enum_value_or_default();
+
+        if let Some(style) = underline_style(status) {
+            attributes.push(underline(style, start, end));
+        }
+
+        start = end;
+    }
+
+    ibus_text(&text, attributes)
+}
+
+/// Position of the first candidate on the page IBus shows for `list`
+pub fn page_start(list: &CandidateList) -> usize {
+    let page_size = PAGE_SIZE as usize;
+    if list.focused >= 0 {
+        list.focused as usize / page_size * page_size
+    } else {
+        list.page.max(0) as usize * page_size
+    }
+}
+
+pub fn lookup_table(list: &CandidateList) -> Value<'static> {
+    let candidates: Vec<Value> = list
+        .candidates
+        .iter()
+        .map(|c| plain_text(&c.value))
+        .collect();
+    let labels: Vec<Value> = Vec::new();
+    let cursor_pos = list.focused.max(page_start(list) as i32) as u32;
+
+    StructureBuilder::new()
+        .add_field("IBusLookupTable")
+        .add_field(attachments())
+        .add_field(PAGE_SIZE)
+        .add_field(cursor_pos)
+        .add_field(list.focused >= 0)
+        .add_field(false)
+        .add_field(ORIENTATION_SYSTEM)
+        .add_field(candidates)
+        .add_field(labels)
+        .build()
+        .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use khiin_protos::command::preedit::Segment;
+    use khiin_protos::command::Candidate;
+    use zbus::zvariant::Structure;
+
+    use super::*;
+
+    fn fields<'a>(value: &'a Value) -> &'a [Value<'a>] {
+        match value {
+            Value::Structure(s) => s.fields(),
+            _ => panic!("Not a structure: {:?}", value),
+        }
+    }
+
+    fn segment(value: &str, status: SegmentStatus) -> Segment {
+        Segment {
+            value: value.into(),
+            status: status.into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn it_underlines_preedit_segments() {
+        let preedit = Preedit {
+            segments: vec![
+                segment("好", SegmentStatus::SS_FOCUSED),
+                segment("lâng", SegmentStatus::SS_COMPOSING),
+                segment(" ", SegmentStatus::SS_UNMARKED),
+            ],
+            ..Default::default()
+        };
+
+        let text = preedit_text(&preedit);
+        let text = fields(&text);
+        assert_eq!(text[0], Value::from("IBusText"));
+        assert_eq!(text[2], Value::from("好lâng "));
+
+        let attr_list = match &text[3] {
+            Value::Value(v) => fields(v),
+            v => panic!("Not a variant: {:?}", v),
+        };
+        let attrs = match &attr_list[2] {
+            Value::Array(a) => a.get().to_vec(),
+            v => panic!("Not an array: {:?}", v),
+        };
+        assert_eq!(attrs.len(), 2);
+
+        let expected = [
+            (ATTR_UNDERLINE_DOUBLE, 0u32, 1u32),
+            (ATTR_UNDERLINE_ERROR, 1, 5),
+        ];
+        for (attr, (style, start, end)) in attrs.iter().zip(expected) {
+            let attr = match attr {
+                Value::Value(v) => Structure::try_from(*v.clone()).unwrap(),
+                v => Structure::try_from(v.clone()).unwrap(),
+            };
+            let attr = attr.fields();
+            assert_eq!(attr[2], Value::from(ATTR_TYPE_UNDERLINE));
+            assert_eq!(attr[3], Value::from(style));
+            assert_eq!(attr[4], Value::from(start));
+            assert_eq!(attr[5], Value::from(end));
+        }
+    }
+
+    #[test]
+    fn it_builds_lookup_tables() {
+        let list = CandidateList {
+            candidates: (0..12)
+                .map(|i| Candidate {
+                    id: i,
+                    value: i.to_string(),
+                    ..Default::default()
+                })
+                .collect(),
+            focused: 10,
+            ..Default::default()
+        };
+        assert_eq!(page_start(&list), 9);
+
+        let table = lookup_table(&list);
+        let table = fields(&table);
+        assert_eq!(table[0], Value::from("IBusLookupTable"));
+        assert_eq!(table[2], Value::from(PAGE_SIZE));
+        assert_eq!(table[3], Value::from(10u32));
+        assert_eq!(table[4], Value::from(true));
+        match &table[7] {
+            Value::Array(a) => assert_eq!(a.len(), 12),
+            v => panic!("Not an array: {:?}", v),
+        }
+    }
+}
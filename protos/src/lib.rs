@@ -44,11 +44,12 @@ pub mod helpers {
         }
     }
 
-    pub async fn parse_u32_delimited_bytes_async<T, R>(
+    /// Reads one message written with `write_u32_delimited_bytes`, without
+    /// parsing it
+    pub async fn read_u32_delimited_bytes_async<R>(
         reader: &mut BufReader<R>,
-    ) -> Result<T>
+    ) -> Result<Vec<u8>>
     where
-        T: Message,
         R: AsyncReadExt + Unpin,
     {
         let mut size_buf = [0u8; 4];
@@ -56,6 +57,17 @@ pub mod helpers {
         let size = u32::from_le_bytes(size_buf) as usize;
         let mut buf = vec![0u8; size];
         reader.read_exact(&mut buf).await?;
+        Ok(buf)
+    }
+
+    pub async fn parse_u32_delimited_bytes_async<T, R>(
+        reader: &mut BufReader<R>,
+    ) -> Result<T>
+    where
+        T: Message,
+        R: AsyncReadExt + Unpin,
+    {
+        let buf = read_u32_delimited_bytes_async(reader).await?;
         if let Ok(message) = T::parse_from_bytes(&buf) {
             Ok(message)
         } else {
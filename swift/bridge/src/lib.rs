@@ -1,10 +1,9 @@
 use std::ffi::c_void;
 use std::path::PathBuf;
 
+use khiin::db::OpenMode;
+use khiin::engine::commands;
 use khiin::Engine;
-use khiin_protos::command::Command;
-use khiin_protos::command::CommandType;
-use khiin_protos::command::Request;
 use khiin_protos::config::AppConfig;
 use khiin_protos::config::AppInputMode;
 use khiin_protos::config::AppKhinMode;
@@ -22,6 +21,12 @@ mod ffi {
         #[swift_bridge(associated_to = EngineBridge)]
         fn new(db_filename: String) -> Option<EngineBridge>;
 
+        #[swift_bridge(associated_to = EngineBridge, swift_name = "newReadOnly")]
+        fn new_read_only(
+            db_filename: String,
+            user_db_filename: String,
+        ) -> Option<EngineBridge>;
+
         #[swift_bridge(swift_name = "sendCommand")]
         fn send_command(&self, cmd_input: &[u8]) -> Option<Vec<u8>>;
 
@@ -47,6 +52,23 @@ impl EngineBridge {
         None
     }
 
+    /// Reads the database in place, e.g. from the read-only app bundle, and
+    /// keeps user data in `user_db_filename`
+    fn new_read_only(
+        db_filename: String,
+        user_db_filename: String,
+    ) -> Option<Self> {
+        let engine = khiin::Engine::with_open_mode(
+            &db_filename,
+            &user_db_filename,
+            OpenMode::ReadOnly,
+        )?;
+        let ptr = Box::into_raw(Box::new(engine));
+        Some(EngineBridge {
+            engine_ptr: ptr as *mut c_void,
+        })
+    }
+
     fn send_command(&self, cmd_input: &[u8]) -> Option<Vec<u8>> {
         let engine: &mut Engine =
             unsafe { &mut *(self.engine_ptr as *mut Engine) };
@@ -99,15 +121,7 @@ impl EngineBridge {
         key_config.alt_hyphen = settings.input_settings.hyphon.to_string();
         config.key_config = Some(key_config).into();
 
-        let mut req = Request::new();
-        req.type_ = CommandType::CMD_SET_CONFIG.into();
-        req.config = Some(config.clone()).into();
-
-        let mut cmd = Command::new();
-        cmd.request = Some(req).into();
-        if let Ok(bytes) = cmd.write_to_bytes() {
-            let _ = engine.send_command_bytes(&bytes);
-        }
+        engine.send_command(commands::set_config(config.clone()));
         config.write_to_bytes().ok()
     }
 }
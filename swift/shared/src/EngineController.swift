@@ -24,6 +24,13 @@ public class EngineController {
         }
         log.debug("dbpath : \(dbpath)")
 
+        guard let userDbPath = getUserDatabaseFilePath() else {
+            self.config = nil
+            self.engine = nil
+            return
+        }
+        log.debug("userDbPath : \(userDbPath)")
+
         guard let settingsPath = getSettingFilePath() else {
             self.config = nil
             self.engine = nil
@@ -31,7 +38,7 @@ public class EngineController {
         }
         log.debug("settingsPath : \(settingsPath)")
 
-        guard let engine = EngineBridge.new(dbpath) else {
+        guard let engine = EngineBridge.newReadOnly(dbpath, userDbPath) else {
             log.debug("No engine")
             self.config = nil
             self.engine = nil
@@ -26,6 +26,15 @@ func getDatabaseFilePath() -> String? {
     return nil
 }
 
+func getUserDatabaseFilePath() -> String? {
+    if let dataDir = getAppDataDir() {
+        return dataDir.appendingPathComponent("khiin_user.db").absoluteURL
+            .path(percentEncoded: false)
+    }
+
+    return nil
+}
+
 func getSettingFilePath() -> String? {
     if let dataDir = getAppDataDir() {
         return dataDir.appendingPathComponent("settings.toml").absoluteURL
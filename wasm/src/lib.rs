@@ -0,0 +1,72 @@
+//! The engine for web pages, built for `wasm32-unknown-unknown`. It uses the
+//! in-memory dictionary built from the data bundled into the module, so it
+//! works offline, and takes the same serialized protobuf `Command`s as the
+//! other platforms.
+
+use anyhow::anyhow;
+use anyhow::Result;
+use khiin::db::MemoryDatabase;
+use khiin::Engine;
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+pub struct KhiinEngine {
+    engine: Engine,
+}
+
+fn create_engine() -> Result<Engine> {
+    let db = MemoryDatabase::new()?;
+    Engine::with_backend(db).ok_or(anyhow!("Unable to create the engine"))
+}
+
+fn js_error(err: anyhow::Error) -> JsError {
+    JsError::new(&format!("{:#}", err))
+}
+
+#[wasm_bindgen]
+impl KhiinEngine {
+    /// Builds the dictionary, which takes a moment, so pages should keep one
+    /// engine for all of their inputs
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Result<KhiinEngine, JsError> {
+        let engine = create_engine().map_err(js_error)?;
+        Ok(Self { engine })
+    }
+
+    /// Handles a serialized `Command` and returns it with its `Response`
+    #[wasm_bindgen(js_name = sendCommand)]
+    pub fn send_command(&mut self, bytes: &[u8]) -> Result<Vec<u8>, JsError> {
+        self.engine.send_command_bytes(bytes).map_err(js_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use khiin::engine::commands;
+    use khiin_protos::command::Command;
+    use khiin_protos::command::ErrorCode;
+    use khiin_protos::command::KeyEvent;
+    use protobuf::Message;
+
+    use super::*;
+
+    #[test]
+    fn it_sends_commands() {
+        let mut engine = KhiinEngine::new().unwrap();
+        let key_event = KeyEvent {
+            key_code: 'a' as i32,
+            ..Default::default()
+        };
+        let bytes = commands::send_key(key_event).write_to_bytes().unwrap();
+
+        let bytes = engine.send_command(&bytes).unwrap();
+        let res = Command::parse_from_bytes(&bytes).unwrap().response;
+        assert_eq!(res.error.enum_value(), Ok(ErrorCode::OK));
+        assert!(res.consumable);
+        assert_eq!(res.preedit.segments.len(), 1);
+
+        let bytes = engine.send_command(&[0xff, 0xff]).unwrap();
+        let res = Command::parse_from_bytes(&bytes).unwrap().response;
+        assert_eq!(res.error.enum_value(), Ok(ErrorCode::MALFORMED_REQUEST));
+    }
+}